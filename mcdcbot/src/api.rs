@@ -0,0 +1,401 @@
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use hyper::{
+    body::Bytes,
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use minecraft_manager::{
+    events::{MinecraftServerEventType, MinecraftServerWarning},
+    tasks::MinecraftServerTask,
+    threaded::MinecraftServerStopReason,
+};
+use poise::serenity_prelude::json::{json, prelude::from_slice, Value};
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::broadcast;
+
+use crate::{data::Data, find_server, start_server};
+
+/// how many events are kept for `/events` clients which can't keep up
+pub const EVENTS_CAPACITY: usize = 256;
+/// how often `/events` clients get a comment, so proxies don't close the connection
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// serves the json api on `api_addr`, if it is set
+pub async fn serve(data: Arc<Data>) {
+    let Some(addr) = data.settings.lock().await.api_addr else {
+        return;
+    };
+    let server = match Server::try_bind(&addr) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("[WARN/API] Couldn't listen on {addr}: {e}");
+            return;
+        }
+    };
+    eprintln!("Serving the api on http://{addr}/");
+    let make_service = make_service_fn(move |_| {
+        let data = Arc::clone(&data);
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(Arc::clone(&data), req))) }
+    });
+    if let Err(e) = server.serve(make_service).await {
+        eprintln!("[WARN/API] The api server stopped: {e}");
+    }
+}
+
+async fn handle(data: Arc<Data>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let token = data.settings.lock().await.api_token.clone();
+    let authorized = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .zip(token)
+        .is_some_and(|(given, token)| token_matches(given, &token));
+    if !authorized {
+        return Ok(error(
+            StatusCode::UNAUTHORIZED,
+            "missing or wrong `Authorization: Bearer <api_token>` header",
+        ));
+    }
+    Ok(match (req.method(), req.uri().path()) {
+        (&Method::GET, "/servers") => servers(&data).await,
+        (&Method::GET, "/status") => status(&data).await,
+        (&Method::POST, "/start") => match body::<StartRequest>(req).await {
+            Ok(StartRequest { server: srv }) => match find_server(&data, &srv).await {
+                Some(server) => match start_server(&data, server).await {
+                    Ok(()) => ok(json!({ "message": "Starting..." })),
+                    Err(e) => error(StatusCode::CONFLICT, &e),
+                },
+                None => error(
+                    StatusCode::NOT_FOUND,
+                    &format!("there is no server called `{srv}`"),
+                ),
+            },
+            Err(response) => response,
+        },
+        (&Method::POST, "/stop") => send_task(&data, MinecraftServerTask::Stop).await,
+        (&Method::POST, "/kill") => send_task(&data, MinecraftServerTask::Kill).await,
+        (&Method::POST, "/command") => match body::<CommandRequest>(req).await {
+            Ok(CommandRequest { command }) => {
+                send_task(&data, MinecraftServerTask::RunCommand(command)).await
+            }
+            Err(response) => response,
+        },
+        (&Method::GET, "/events") => events(&data),
+        _ => error(StatusCode::NOT_FOUND, "unknown endpoint"),
+    })
+}
+
+#[derive(Deserialize)]
+struct StartRequest {
+    /// the server's name or one of its aliases
+    server: String,
+}
+#[derive(Deserialize)]
+struct CommandRequest {
+    /// without `/`
+    command: String,
+}
+
+/// reads a json request body
+async fn body<T: DeserializeOwned>(req: Request<Body>) -> Result<T, Response<Body>> {
+    let bytes = hyper::body::to_bytes(req.into_body())
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    from_slice(&bytes).map_err(|e| error(StatusCode::BAD_REQUEST, &format!("invalid body: {e}")))
+}
+
+/// compares the whole token, so how long it takes doesn't tell how much of it was right
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn ok(value: Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(value.to_string()));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    let mut response = ok(json!({ "error": message }));
+    *response.status_mut() = status;
+    response
+}
+
+async fn servers(data: &Data) -> Response<Body> {
+    let current = data
+        .current
+        .lock()
+        .await
+        .as_ref()
+        .map(|(server, _)| Arc::clone(server));
+    let mut servers = vec![];
+    for server in data.servers.lock().await.iter() {
+        let running = current
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(current, server));
+        let server = server.lock().await;
+        servers.push(json!({
+            "name": server.name,
+            "aliases": server.aliases,
+            "description": server.description,
+            "type": server.settings.server_type.to_string(),
+            "running": running,
+        }));
+    }
+    ok(Value::Array(servers))
+}
+
+async fn status(data: &Data) -> Response<Body> {
+    let current = data
+        .current
+        .lock()
+        .await
+        .as_ref()
+        .map(|(server, thread)| (Arc::clone(server), Arc::clone(thread)));
+    let Some((server, thread)) = current else {
+        return ok(json!({ "running": false }));
+    };
+    let server = server.lock().await;
+    let thread_lock = thread.lock().await;
+    let Some(thread) = thread_lock.as_ref() else {
+        return ok(json!({ "running": false }));
+    };
+    let resources = thread.resources();
+    let tick = thread.performance().latest();
+    ok(json!({
+        "running": true,
+        "server": server.name,
+        "type": server.settings.server_type.to_string(),
+        "version": thread.version(),
+        "players": thread.online_players(),
+        "pid": thread.pid(),
+        "uptime_secs": thread.uptime().map(|uptime| uptime.as_secs()),
+        "cpu_percent": resources.and_then(|usage| usage.cpu_percent),
+        "memory_bytes": resources.map(|usage| usage.stats.rss),
+        "memory_max_bytes": server.settings.ram_max as u64 * 1024 * 1024,
+        "threads": resources.map(|usage| usage.stats.threads),
+        "tps": tick.map(|sample| sample.tps),
+        "mspt": tick.and_then(|sample| sample.mspt),
+    }))
+}
+
+/// sends a task to the running server
+async fn send_task(data: &Data, task: MinecraftServerTask) -> Response<Body> {
+    let current_lock = data.current.lock().await;
+    let Some((_, thread)) = current_lock.as_ref() else {
+        return error(StatusCode::CONFLICT, "no server is running");
+    };
+    let sent = thread
+        .lock()
+        .await
+        .as_ref()
+        .is_some_and(|thread| thread.clone_task_sender().send_task(task).is_ok());
+    if sent {
+        ok(json!({ "message": "Sent." }))
+    } else {
+        error(StatusCode::CONFLICT, "the server is stopping")
+    }
+}
+
+/// streams the running server's events as server-sent events, one json object per event
+fn events(data: &Data) -> Response<Body> {
+    let mut receiver = data.events.subscribe();
+    let (mut sender, body) = Body::channel();
+    tokio::task::spawn(async move {
+        loop {
+            let chunk = match tokio::time::timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+                Ok(Ok(event)) => format!(
+                    "event: {}\ndata: {event}\n\n",
+                    event["type"].as_str().unwrap_or("event")
+                ),
+                Ok(Err(broadcast::error::RecvError::Lagged(missed))) => {
+                    format!(": missed {missed} events\n\n")
+                }
+                Ok(Err(broadcast::error::RecvError::Closed)) => break,
+                Err(_) => ": keep-alive\n\n".to_owned(),
+            };
+            // the client disconnected
+            if sender.send_data(Bytes::from(chunk)).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut response = Response::new(body);
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    response
+}
+
+/// an event from the running server, as sent to `/events`
+pub fn event(server: &str, event: &MinecraftServerEventType) -> Value {
+    let mut value = match event {
+        MinecraftServerEventType::Warning(w) => json!({
+            "type": "warning",
+            "message": match w {
+                MinecraftServerWarning::CouldNotGetServerProcessStdio => {
+                    "couldn't get the server process's stdin/stdout".to_owned()
+                }
+                MinecraftServerWarning::CantWriteToStdin(e) => format!("can't write to stdin: {e}"),
+            },
+        }),
+        MinecraftServerEventType::Spawned { pid } => json!({ "type": "spawned", "pid": pid }),
+        MinecraftServerEventType::JoinLeave(e) => json!({
+            "type": if e.joined { "join" } else { "leave" },
+            "player": e.username,
+        }),
+        MinecraftServerEventType::ChatMessage(e) => json!({
+            "type": "chat",
+            "player": e.author,
+            "message": e.message,
+        }),
+        MinecraftServerEventType::Death(e) => json!({
+            "type": "death",
+            "player": e.username,
+            "message": e.message,
+        }),
+        MinecraftServerEventType::Saved => json!({ "type": "saved" }),
+        MinecraftServerEventType::Version(version) => {
+            json!({ "type": "version", "version": version })
+        }
+        MinecraftServerEventType::Tps(tps) => json!({ "type": "tps", "tps": tps }),
+        MinecraftServerEventType::Mspt(mspt) => json!({ "type": "mspt", "mspt": mspt }),
+        MinecraftServerEventType::CantKeepUp { ms_behind } => {
+            json!({ "type": "cant_keep_up", "ms_behind": ms_behind })
+        }
+        MinecraftServerEventType::Custom { kind, fields } => json!({
+            "type": "custom",
+            "kind": kind,
+            "fields": fields,
+        }),
+    };
+    value["server"] = json!(server);
+    value
+}
+
+/// the running server stopped, as sent to `/events`
+pub fn stopped(server: &str, reason: Option<&MinecraftServerStopReason>) -> Value {
+    json!({
+        "type": "stopped",
+        "server": server,
+        "reason": reason.map(|reason| reason.to_string()),
+        "crash": reason.is_some_and(|reason| reason.is_crash()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use minecraft_manager::{
+        chat::ChatMessage,
+        events::{DeathEvent, JoinLeaveEvent},
+    };
+
+    use super::*;
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(token_matches("s3cret-token", "s3cret-token"));
+        for given in [
+            "",
+            "s3cret",
+            "s3cret-token ",
+            "s3cret-tokeN",
+            "S3cret-token",
+        ] {
+            assert!(!token_matches(given, "s3cret-token"), "{given}");
+        }
+        // multi-byte characters are compared by byte
+        assert!(token_matches("tökén", "tökén"));
+        assert!(!token_matches("tökén", "tokenxx"));
+    }
+
+    #[test]
+    fn event_json() {
+        let fields = BTreeMap::from([("boss".to_owned(), "Wither".to_owned())]);
+        for (event, expected) in [
+            (
+                MinecraftServerEventType::ChatMessage(ChatMessage {
+                    author: "Steve".to_owned(),
+                    message: "hi \"there\"".to_owned(),
+                }),
+                json!({ "type": "chat", "server": "Survival", "player": "Steve", "message": "hi \"there\"" }),
+            ),
+            (
+                MinecraftServerEventType::JoinLeave(JoinLeaveEvent {
+                    username: "Steve".to_owned(),
+                    joined: true,
+                }),
+                json!({ "type": "join", "server": "Survival", "player": "Steve" }),
+            ),
+            (
+                MinecraftServerEventType::JoinLeave(JoinLeaveEvent {
+                    username: "Steve".to_owned(),
+                    joined: false,
+                }),
+                json!({ "type": "leave", "server": "Survival", "player": "Steve" }),
+            ),
+            (
+                MinecraftServerEventType::Death(DeathEvent {
+                    username: "Steve".to_owned(),
+                    message: "Steve was slain by Zombie".to_owned(),
+                }),
+                json!({ "type": "death", "server": "Survival", "player": "Steve", "message": "Steve was slain by Zombie" }),
+            ),
+            (
+                MinecraftServerEventType::Spawned { pid: 1234 },
+                json!({ "type": "spawned", "server": "Survival", "pid": 1234 }),
+            ),
+            (
+                MinecraftServerEventType::Saved,
+                json!({ "type": "saved", "server": "Survival" }),
+            ),
+            (
+                MinecraftServerEventType::Version("1.20.4".to_owned()),
+                json!({ "type": "version", "server": "Survival", "version": "1.20.4" }),
+            ),
+            (
+                MinecraftServerEventType::Tps(19.5),
+                json!({ "type": "tps", "server": "Survival", "tps": 19.5 }),
+            ),
+            (
+                MinecraftServerEventType::Mspt(12.25),
+                json!({ "type": "mspt", "server": "Survival", "mspt": 12.25 }),
+            ),
+            (
+                MinecraftServerEventType::CantKeepUp { ms_behind: 2043 },
+                json!({ "type": "cant_keep_up", "server": "Survival", "ms_behind": 2043 }),
+            ),
+            (
+                MinecraftServerEventType::Custom {
+                    kind: "boss_kill".to_owned(),
+                    fields,
+                },
+                json!({ "type": "custom", "server": "Survival", "kind": "boss_kill", "fields": { "boss": "Wither" } }),
+            ),
+            (
+                MinecraftServerEventType::Warning(
+                    MinecraftServerWarning::CouldNotGetServerProcessStdio,
+                ),
+                json!({ "type": "warning", "server": "Survival", "message": "couldn't get the server process's stdin/stdout" }),
+            ),
+        ] {
+            assert_eq!(super::event("Survival", &event), expected, "{event:?}");
+        }
+        assert_eq!(
+            stopped("Survival", None),
+            json!({ "type": "stopped", "server": "Survival", "reason": null, "crash": false })
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use std::{
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use minecraft_manager::{
+    backup,
+    tasks::{MinecraftServerTask, MinecraftServerTaskCallback},
+};
+use poise::futures_util::lock::Mutex;
+
+use crate::{
+    autocomplete_server, confirm,
+    data::{Data, MinecraftServer},
+    embed, find_server, in_info_channel,
+    queue::QueuedMessage,
+    server_not_found, start_server, Context, Error,
+};
+
+/// how long to wait for the server to save its worlds before giving up
+const SAVE_TIMEOUT: Duration = Duration::from_secs(60);
+/// how often the scheduler checks if a backup is due
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+/// how long the user has to confirm stopping the server for a restore
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+/// how long to wait for the server to stop before a restore
+const STOP_TIMEOUT: Duration = Duration::from_secs(120);
+/// how often the restore's progress is updated in discord
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+/// how many backups `/backup list` shows
+const LIST_LIMIT: usize = 25;
+
+/// Manage world backups
+#[poise::command(slash_command, subcommands("now", "list", "restore"))]
+pub async fn backup(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Back up a server's worlds now
+#[poise::command(slash_command)]
+async fn now(
+    ctx: Context<'_>,
+    #[description = "Server's name (default: the running server)"]
+    #[autocomplete = "autocomplete_server"]
+    srv: Option<String>,
+) -> Result<(), Error> {
+    if !in_info_channel(ctx).await? {
+        return Ok(());
+    }
+    let Some(server) = server_or_current(ctx, srv).await? else {
+        return Ok(());
+    };
+    // archiving can take longer than discord waits for a reply
+    ctx.defer().await?;
+    let name = server.lock().await.name.clone();
+    ctx.say(match backup_server(ctx.data(), &server).await {
+        Ok(report) => format!("Backed up '{name}': {report}"),
+        Err(e) => format!("Couldn't back up '{name}': {e}"),
+    })
+    .await?;
+    Ok(())
+}
+
+/// List a server's backups
+#[poise::command(slash_command)]
+async fn list(
+    ctx: Context<'_>,
+    #[description = "Server's name (default: the running server)"]
+    #[autocomplete = "autocomplete_server"]
+    srv: Option<String>,
+) -> Result<(), Error> {
+    let Some(server) = server_or_current(ctx, srv).await? else {
+        return Ok(());
+    };
+    let Some(dir) = backup_dir(ctx.data(), &server).await else {
+        ctx.say("Set `backup_dir` in the bot's settings to make backups.")
+            .await?;
+        return Ok(());
+    };
+    let name = server.lock().await.name.clone();
+    let backups = backup::list(&dir)?;
+    if backups.is_empty() {
+        ctx.say(format!("There are no backups of '{name}'."))
+            .await?;
+        return Ok(());
+    }
+    let mut msg = format!("Backups of '{name}' ({}):", backups.len());
+    for backup in backups.iter().take(LIST_LIMIT) {
+        msg.push_str(&format!(
+            "\n- `{}` ({})",
+            backup.id,
+            format_size(backup.size)
+        ));
+    }
+    if backups.len() > LIST_LIMIT {
+        msg.push_str(&format!("\n... and {} older", backups.len() - LIST_LIMIT));
+    }
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Restore a server's worlds from a backup
+#[poise::command(slash_command)]
+async fn restore(
+    ctx: Context<'_>,
+    #[description = "The backup's id (see /backup list)"] id: String,
+    #[description = "Server's name (default: the running server)"]
+    #[autocomplete = "autocomplete_server"]
+    srv: Option<String>,
+    #[description = "Start the server after restoring (default: false)"] start: Option<bool>,
+) -> Result<(), Error> {
+    if !in_info_channel(ctx).await? {
+        return Ok(());
+    }
+    let Some(server) = server_or_current(ctx, srv).await? else {
+        return Ok(());
+    };
+    let Some(dir) = backup_dir(ctx.data(), &server).await else {
+        ctx.say("Set `backup_dir` in the bot's settings to make backups.")
+            .await?;
+        return Ok(());
+    };
+    let name = server.lock().await.name.clone();
+    let id = id.trim();
+    let Some(backup) = backup::list(&dir)?.into_iter().find(|b| b.id == id) else {
+        ctx.say(format!(
+            "There is no backup `{id}` of '{name}' (see /backup list)."
+        ))
+        .await?;
+        return Ok(());
+    };
+    let reply = if is_running(ctx.data(), &server).await {
+        let (reply, accepted) = confirm(
+            ctx,
+            format!("'{name}' is running. Stop it and restore `{id}`?"),
+            "Stop and restore",
+            CONFIRM_TIMEOUT,
+        )
+        .await?;
+        let cancelled = match accepted {
+            Some(true) => None,
+            Some(false) => Some("Cancelled."),
+            None => Some("Cancelled (timed out)."),
+        };
+        if let Some(cancelled) = cancelled {
+            reply
+                .edit(ctx, |m| m.content(cancelled).components(|c| c))
+                .await?;
+            return Ok(());
+        }
+        reply
+            .edit(ctx, |m| m.content("Stopping...").components(|c| c))
+            .await?;
+        if let Some((_, thread)) = ctx.data().current.lock().await.as_ref() {
+            if let Some(thread) = thread.lock().await.as_ref() {
+                _ = thread
+                    .clone_task_sender()
+                    .send_task(MinecraftServerTask::Stop);
+            }
+        }
+        let stopping = Instant::now();
+        while is_running(ctx.data(), &server).await {
+            if stopping.elapsed() > STOP_TIMEOUT {
+                reply
+                    .edit(ctx, |m| {
+                        m.content("The server didn't stop in time, nothing was restored.")
+                    })
+                    .await?;
+                return Ok(());
+            }
+            tokio::time::sleep(PROGRESS_INTERVAL).await;
+        }
+        reply
+    } else {
+        ctx.say(format!("Restoring `{id}`...")).await?
+    };
+
+    let backup_lock = ctx.data().backup_lock.try_lock();
+    // the server could have been started again while we waited
+    if backup_lock.is_none() || is_running(ctx.data(), &server).await {
+        reply
+            .edit(ctx, |m| {
+                m.content(
+                    "The server is running or another backup is being made, nothing was restored.",
+                )
+            })
+            .await?;
+        return Ok(());
+    }
+    let server_dir = PathBuf::from(&server.lock().await.settings.directory);
+    let (sender, receiver) = mpsc::channel();
+    let callback = MinecraftServerTaskCallback::new(receiver);
+    let restoring =
+        tokio::task::spawn_blocking(move || backup::restore(&server_dir, &backup, Some(&sender)));
+    let mut percent = 0;
+    while !restoring.is_finished() {
+        tokio::time::sleep(PROGRESS_INTERVAL).await;
+        let previous = percent;
+        // failures are reported once the restore finished
+        if let Some(p) = callback.recv.try_iter().filter_map(Result::ok).last() {
+            percent = p;
+        }
+        if percent != previous {
+            reply
+                .edit(ctx, |m| {
+                    m.content(format!("Restoring `{id}`... {percent}%"))
+                })
+                .await?;
+        }
+    }
+    let result = restoring.await;
+    drop(backup_lock);
+    let mut msg = match &result {
+        Ok(Ok(snapshot)) => format!(
+            "Restored `{id}`. The previous worlds were moved to `{}`.",
+            snapshot.display()
+        ),
+        Ok(Err(e)) => format!("Couldn't restore `{id}`, kept the current worlds: {e}"),
+        Err(e) => format!("Couldn't restore `{id}`: {e}"),
+    };
+    if matches!(result, Ok(Ok(_))) && start == Some(true) {
+        match start_server(ctx.data(), server).await {
+            Ok(()) => msg.push_str(" Starting..."),
+            Err(e) => msg.push_str(&format!(" {e}")),
+        }
+    }
+    reply.edit(ctx, |m| m.content(msg)).await?;
+    Ok(())
+}
+
+/// if the server is the one which is currently running
+pub async fn is_running(data: &Data, server: &Arc<Mutex<MinecraftServer>>) -> bool {
+    data.current
+        .lock()
+        .await
+        .as_ref()
+        .is_some_and(|(current, _)| Arc::ptr_eq(current, server))
+}
+
+/// the server called `srv` or, if `srv` isn't set, the running server.
+/// Replies and returns `None` if there is no such server.
+pub async fn server_or_current(
+    ctx: Context<'_>,
+    srv: Option<String>,
+) -> Result<Option<Arc<Mutex<MinecraftServer>>>, Error> {
+    if let Some(srv) = srv {
+        let server = find_server(ctx.data(), &srv).await;
+        if server.is_none() {
+            server_not_found(ctx, &srv).await?;
+        }
+        return Ok(server);
+    }
+    let current = ctx
+        .data()
+        .current
+        .lock()
+        .await
+        .as_ref()
+        .map(|(server, _)| Arc::clone(server));
+    if current.is_none() {
+        ctx.say("No server is running, please specify one.").await?;
+    }
+    Ok(current)
+}
+
+/// where the server's backups are saved, if `backup_dir` is set
+pub async fn backup_dir(data: &Data, server: &Arc<Mutex<MinecraftServer>>) -> Option<PathBuf> {
+    let backup_dir = data.settings.lock().await.backup_dir.clone()?;
+    Some(PathBuf::from(backup_dir).join(&server.lock().await.file))
+}
+
+/// backs up the server's worlds and removes old backups.
+/// If the server is running, it doesn't save while its worlds are archived.
+/// Returns the backup's size and how long it took.
+pub async fn backup_server(
+    data: &Data,
+    server: &Arc<Mutex<MinecraftServer>>,
+) -> Result<String, String> {
+    let start = Instant::now();
+    let dir = backup_dir(data, server)
+        .await
+        .ok_or("Set `backup_dir` in the bot's settings to make backups.")?;
+    let Some(_backup_lock) = data.backup_lock.try_lock() else {
+        return Err("Another backup is already running.".to_owned());
+    };
+    let server_dir = PathBuf::from(&server.lock().await.settings.directory);
+    let task_sender = match data.current.lock().await.as_ref() {
+        Some((current, thread)) if Arc::ptr_eq(current, server) => thread
+            .lock()
+            .await
+            .as_ref()
+            .map(|thread| thread.clone_task_sender()),
+        _ => None,
+    };
+    let run = |cmd: &str| {
+        if let Some(task_sender) = &task_sender {
+            _ = task_sender.send_task(MinecraftServerTask::RunCommand(cmd.to_owned()));
+        }
+    };
+    if task_sender.is_some() {
+        run("save-off");
+        // created before `save-all` so it can't miss the notification
+        let saved = data.world_saved.notified();
+        run("save-all flush");
+        if tokio::time::timeout(SAVE_TIMEOUT, saved).await.is_err() {
+            run("save-on");
+            return Err("The server didn't save its worlds in time.".to_owned());
+        }
+    }
+    let created = {
+        let dir = dir.clone();
+        tokio::task::spawn_blocking(move || backup::create(&server_dir, &dir, None)).await
+    };
+    run("save-on");
+    let created = match created {
+        Ok(Ok(created)) => created,
+        Ok(Err(e)) => return Err(e.to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let retention = data.settings.lock().await.backup_retention;
+    let mut report = format!(
+        "`{}`, {} in {:.1}s",
+        created.id,
+        format_size(created.size),
+        start.elapsed().as_secs_f32()
+    );
+    match backup::prune(&dir, &retention) {
+        Ok(removed) if removed.is_empty() => {}
+        Ok(removed) => report.push_str(&format!(", removed {} old backup(s)", removed.len())),
+        Err(e) => report.push_str(&format!(", couldn't remove old backups: {e}")),
+    }
+    Ok(report)
+}
+
+/// backs up the running server every `backup_interval_mins` minutes
+/// and sends the result to the info channel
+pub async fn schedule(data: Arc<Data>) {
+    let mut last = Instant::now();
+    loop {
+        tokio::time::sleep(SCHEDULE_INTERVAL).await;
+        let interval = data.settings.lock().await.backup_interval_mins;
+        if interval == 0 || last.elapsed() < Duration::from_secs(interval * 60) {
+            continue;
+        }
+        let current = data
+            .current
+            .lock()
+            .await
+            .as_ref()
+            .map(|(server, _)| Arc::clone(server));
+        // servers which aren't running don't change
+        let Some(server) = current else {
+            continue;
+        };
+        last = Instant::now();
+        let name = server.lock().await.name.clone();
+        let msg = match backup_server(&data, &server).await {
+            Ok(report) => embed::backup_created(&name, &report),
+            Err(e) => embed::backup_failed(&name, &e),
+        };
+        let channel_id = data.settings.lock().await.channel_id_info;
+        data.queue.push(channel_id, QueuedMessage::Message(msg));
+    }
+}
+
+/// i.e. `12.3 MB`
+pub fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KB", "MB", "GB"] {
+        if size < 1000.0 {
+            return format!("{size:.1} {unit}");
+        }
+        size /= 1000.0;
+    }
+    format!("{size:.1} TB")
+}
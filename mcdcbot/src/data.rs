@@ -1,25 +1,90 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::Instant,
+};
 
-use minecraft_manager::{thread::MinecraftServerThread, MinecraftServerSettings};
-use poise::futures_util::lock::Mutex;
+use minecraft_manager::{
+    config::LoadedServer, thread::MinecraftServerThread, MinecraftServerSettings,
+};
+use poise::{
+    futures_util::lock::Mutex,
+    serenity_prelude::{json::Value, MessageId},
+};
+use tokio::sync::{broadcast, Notify};
 
-use crate::settings::Settings;
+use crate::{mentions::Mentions, metrics::ServerCounters, queue::DiscordQueue, settings::Settings};
 
 pub struct Data {
     pub settings: Mutex<Settings>,
+    /// the file the settings were loaded from
+    pub settings_file: PathBuf,
     pub servers: Mutex<Vec<Arc<Mutex<MinecraftServer>>>>,
-    pub current: Arc<
-        Mutex<
-            Option<(
-                Arc<Mutex<MinecraftServer>>,
-                Arc<Mutex<Option<MinecraftServerThread>>>,
-            )>,
-        >,
-    >,
+    /// the directory containing the servers' config files
+    pub servers_dir: PathBuf,
+    pub current: Mutex<CurrentServer>,
+    pub mentions: Mutex<Mentions>,
+    /// all messages to discord (except command replies) are sent through this queue
+    pub queue: DiscordQueue,
+    /// the most recent discord messages which were sent to minecraft, and their author's name
+    pub relayed_messages: Mutex<VecDeque<(MessageId, String)>>,
+    /// locked while a backup is being made, so only one runs at a time
+    pub backup_lock: Mutex<()>,
+    /// notified when the running server has saved its worlds
+    pub world_saved: Notify,
+    /// when the last lag alert was sent (see `lag_alert_cooldown_mins`)
+    pub last_lag_alert: Mutex<Option<Instant>>,
+    /// chat messages, joins, starts and crashes since the bot was started, by the server's config file
+    pub counters: Mutex<BTreeMap<String, ServerCounters>>,
+    /// the running server's events, for the api's `/events` (see [`crate::api::event`])
+    pub events: broadcast::Sender<Value>,
 }
 
+/// the server which is currently running, if any
+pub type CurrentServer = Option<(
+    Arc<Mutex<MinecraftServer>>,
+    Arc<Mutex<Option<MinecraftServerThread>>>,
+)>;
+
 pub struct MinecraftServer {
+    /// the config file's name. Identifies the server when reloading.
+    pub file: String,
+    /// `name=`, or the file name
     pub name: String,
-    pub short: Option<String>,
+    /// other names for the server which can be used in commands (`aliases=`, comma separated)
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
     pub settings: MinecraftServerSettings,
+    /// settings which were changed (by a reload) while the server was running.
+    /// They are applied the next time the server is started.
+    pub pending_settings: Option<MinecraftServerSettings>,
+    /// the minecraft version the server logged the last time it was started
+    pub version: Option<String>,
+}
+
+impl From<LoadedServer> for MinecraftServer {
+    fn from(server: LoadedServer) -> Self {
+        Self {
+            file: server.file,
+            name: server.name,
+            aliases: server.aliases,
+            description: server.description,
+            settings: server.settings,
+            pending_settings: None,
+            version: None,
+        }
+    }
+}
+
+impl MinecraftServer {
+    /// the server's name and aliases
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+    /// if `srv` is the server's name or one of its aliases (ignoring case)
+    pub fn is_called(&self, srv: &str) -> bool {
+        self.names()
+            .any(|name| name.eq_ignore_ascii_case(srv.trim()))
+    }
 }
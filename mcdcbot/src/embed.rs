@@ -1,28 +1,139 @@
+use std::collections::BTreeMap;
+
 use minecraft_manager::{
-    chat::ChatMessage, events::JoinLeaveEvent, threaded::MinecraftServerStopReason,
+    chat::ChatMessage, events::DeathEvent, threaded::MinecraftServerStopReason,
 };
 use poise::serenity_prelude::{json::json, json::Value};
 
-pub fn chat_message(e: &ChatMessage) -> Value {
-    json!({
+use crate::{markdown, mentions, settings::CustomEventTemplate};
+
+pub fn chat_message(e: &ChatMessage, mentions: &[(String, u64)]) -> Value {
+    let mut msg = json!({
         "embeds": [{
             "title": e.author,
-            "description": e.message
-        }]
+            "description": with_mentions(&e.message, mentions)
+        }],
+        "allowed_mentions": {
+            "parse": [],
+            "users": mentions.iter().map(|(_, id)| id.to_string()).collect::<Vec<_>>()
+        }
+    });
+    // mentions in embeds don't ping anyone
+    if !mentions.is_empty() {
+        msg["content"] = Value::String(
+            mentions
+                .iter()
+                .map(|(_, id)| format!("<@{id}>"))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    }
+    msg
+}
+/// a chat message sent through the chat webhook, so it shows the player's name and head
+pub fn chat_message_webhook(
+    e: &ChatMessage,
+    skin_render_url: &str,
+    mentions: &[(String, u64)],
+) -> Value {
+    json!({
+        "username": e.author,
+        "avatar_url": skin_render_url.replace("{name}", &e.author),
+        "content": with_mentions(&e.message, mentions),
+        "allowed_mentions": {
+            "parse": [],
+            "users": mentions.iter().map(|(_, id)| id.to_string()).collect::<Vec<_>>()
+        }
     })
 }
-pub fn join_leave(e: &JoinLeaveEvent) -> Value {
+/// converts the message to markdown and replaces `@name`s with discord mentions.
+/// Only whole names are replaced, so `@Al` isn't found in `@Alice`.
+fn with_mentions(msg: &str, mentions: &[(String, u64)]) -> String {
+    // markdown escaping would break `<@id>`, so they are added afterwards
+    let placeholder = |i: usize| format!("\u{E000}{i}\u{E001}");
+    let msg = mentions::replace_mentions(msg, |name| {
+        mentions
+            .iter()
+            .position(|(mentioned, _)| mentioned.eq_ignore_ascii_case(name))
+            .map(placeholder)
+    });
+    let mut msg = markdown::from_minecraft(&msg);
+    for (i, (_, id)) in mentions.iter().enumerate() {
+        msg = msg.replace(&placeholder(i), &format!("<@{id}>"));
+    }
+    msg
+}
+/// one or more players joining or leaving
+pub fn join_leave(usernames: &[String], joined: bool) -> Value {
+    let action = if joined { "joined" } else { "left" };
     json!({
         "embeds": [{
-            "description": if e.joined {
-                format!("{} joined", e.username)
-            } else {
-                format!("{} left", e.username)
+            "description": match usernames {
+                [username] => format!("{username} {action}"),
+                usernames => format!(
+                    "{} players {action}: {}",
+                    usernames.len(),
+                    usernames.join(", ")
+                ),
             },
         }]
     })
 }
 
+pub fn death(e: &DeathEvent) -> Value {
+    json!({
+        "embeds": [{
+            "description": markdown::from_minecraft(&e.message),
+        }]
+    })
+}
+
+/// a custom event from a line parser or rule (see [`CustomEventTemplate`])
+pub fn custom_event(
+    template: &CustomEventTemplate,
+    kind: &str,
+    fields: &BTreeMap<String, String>,
+) -> Value {
+    // markdown isn't shown in titles, so it is only escaped in the description
+    let fill = |text: &str, escape: bool| {
+        let mut text = text.replace("{kind}", kind);
+        for (key, value) in fields {
+            let value = if escape {
+                markdown::from_minecraft(value)
+            } else {
+                value.clone()
+            };
+            text = text.replace(&format!("{{{key}}}"), &value);
+        }
+        text
+    };
+    let mut embed = json!({});
+    match (&template.title, &template.description) {
+        (None, None) => {
+            embed["title"] = Value::String(kind.to_owned());
+            embed["description"] = Value::String(
+                fields
+                    .iter()
+                    .map(|(key, value)| format!("{key}: {}", markdown::from_minecraft(value)))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+        }
+        (title, description) => {
+            if let Some(title) = title {
+                embed["title"] = Value::String(fill(title, false));
+            }
+            if let Some(description) = description {
+                embed["description"] = Value::String(fill(description, true));
+            }
+        }
+    }
+    if let Some(color) = template.color {
+        embed["color"] = Value::from(color);
+    }
+    json!({ "embeds": [embed] })
+}
+
 pub fn server_started(name: &str, ip: Option<String>) -> Value {
     json!({
         "embeds": [{
@@ -31,7 +142,7 @@ pub fn server_started(name: &str, ip: Option<String>) -> Value {
             "description": if let Some(ip) = ip {
                 format!("Server was started, IP: {ip}")
             } else {
-                format!("Server was started")
+                "Server was started".to_owned()
             },
         }]
     })
@@ -53,3 +164,68 @@ pub fn server_stopped(reason: Option<MinecraftServerStopReason>) -> Value {
         })
     }
 }
+
+pub fn config_reloaded(report: &[String]) -> Value {
+    json!({
+        "embeds": [{
+            "title": "Reloaded config files",
+            "description": report.join("\n"),
+        }]
+    })
+}
+
+pub fn lag_alert(name: &str, reasons: &[String]) -> Value {
+    json!({
+        "embeds": [{
+            "color": 16753920,
+            "title": name,
+            "description": format!("The server is lagging:\n- {}", reasons.join("\n- ")),
+        }]
+    })
+}
+
+pub fn backup_created(name: &str, report: &str) -> Value {
+    json!({
+        "embeds": [{
+            "title": name,
+            "description": format!("Backup created: {report}"),
+        }]
+    })
+}
+pub fn backup_failed(name: &str, error: &str) -> Value {
+    json!({
+        "embeds": [{
+            "color": 6881280,
+            "title": name,
+            "description": format!("Backup failed: {error}"),
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_only_replace_whole_names() {
+        let mentions = [("Al".to_owned(), 1), ("alice".to_owned(), 2)];
+        assert_eq!(
+            with_mentions("@Al and @Alice, @Albert.", &mentions),
+            "<@1> and <@2>, @Albert."
+        );
+        assert_eq!(
+            with_mentions("@Alice_2 @al.", &mentions[..1]),
+            "@Alice\\_2 <@1>."
+        );
+    }
+
+    #[test]
+    fn mentions_survive_markdown_escaping() {
+        let mentions = [("snake_case".to_owned(), 3)];
+        assert_eq!(
+            with_mentions("**hi** @snake_case > you", &mentions),
+            "\\*\\*hi\\*\\* <@3> \\> you"
+        );
+        assert_eq!(with_mentions("§l@snake_case", &mentions), "**<@3>**");
+    }
+}
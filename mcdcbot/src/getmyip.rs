@@ -10,7 +10,7 @@ pub async fn get_my_ip(url1: &str, url2: &str) -> String {
             }
         }
         (Some(ip), None) | (None, Some(ip)) => ip.to_string(),
-        (None, None) => format!("unknown"),
+        (None, None) => "unknown".to_owned(),
     }
 }
 
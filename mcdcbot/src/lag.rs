@@ -0,0 +1,104 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use minecraft_manager::{performance::Performance, tasks::MinecraftServerTask};
+
+use crate::{data::Data, embed, queue::QueuedMessage, settings::Settings};
+
+/// how far back `Can't keep up!` warnings are counted (for `lag_alert_cant_keep_up` and `/status`)
+pub const CANT_KEEP_UP_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// how often the scheduler checks if it's disabled
+const DISABLED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// asks the running server for its tick rate every `tps_interval_secs`
+pub async fn schedule(data: Arc<Data>) {
+    loop {
+        let interval = data.settings.lock().await.tps_interval_secs;
+        if interval == 0 {
+            tokio::time::sleep(DISABLED_INTERVAL).await;
+            continue;
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        let current = data
+            .current
+            .lock()
+            .await
+            .as_ref()
+            .map(|(server, thread)| (Arc::clone(server), Arc::clone(thread)));
+        let Some((server, thread)) = current else {
+            continue;
+        };
+        let commands = {
+            let server = server.lock().await;
+            let version = server
+                .version
+                .as_deref()
+                .or(server.settings.minecraft_version.as_deref());
+            server.settings.server_type.tick_query_commands(version)
+        };
+        let task_sender = thread
+            .lock()
+            .await
+            .as_ref()
+            .map(|thread| thread.clone_task_sender());
+        if let Some(task_sender) = task_sender {
+            for command in commands {
+                _ = task_sender.send_task(MinecraftServerTask::RunCommand(command.to_string()));
+            }
+        }
+    }
+}
+
+/// why the server is lagging according to the `lag_alert_*` settings, empty if it isn't
+pub fn lag_reasons(performance: &Performance, settings: &Settings) -> Vec<String> {
+    let mut reasons = vec![];
+    if let Some(latest) = performance.latest() {
+        if latest.tps < settings.lag_alert_tps {
+            reasons.push(format!(
+                "TPS is {:.1} (alert below {})",
+                latest.tps, settings.lag_alert_tps
+            ));
+        }
+        if let Some(mspt) = latest.mspt {
+            if settings.lag_alert_mspt > 0.0 && mspt > settings.lag_alert_mspt {
+                reasons.push(format!(
+                    "MSPT is {mspt:.1} (alert above {})",
+                    settings.lag_alert_mspt
+                ));
+            }
+        }
+    }
+    let cant_keep_up = performance.cant_keep_up_count(CANT_KEEP_UP_WINDOW);
+    if settings.lag_alert_cant_keep_up > 0 && cant_keep_up >= settings.lag_alert_cant_keep_up {
+        reasons.push(format!(
+            "\"Can't keep up!\" {cant_keep_up} times in the last {} minutes",
+            CANT_KEEP_UP_WINDOW.as_secs() / 60
+        ));
+    }
+    reasons
+}
+
+/// sends a lag alert to the info channel if the server is lagging and the last alert was long enough ago
+pub async fn alert_if_lagging(
+    data: &Data,
+    name: &str,
+    performance: &Performance,
+    settings: &Settings,
+) {
+    let reasons = lag_reasons(performance, settings);
+    if reasons.is_empty() {
+        return;
+    }
+    let mut last_alert = data.last_lag_alert.lock().await;
+    let cooldown = Duration::from_secs(settings.lag_alert_cooldown_mins * 60);
+    if last_alert.is_some_and(|time| time.elapsed() < cooldown) {
+        return;
+    }
+    *last_alert = Some(Instant::now());
+    data.queue.push(
+        settings.channel_id_info,
+        QueuedMessage::Message(embed::lag_alert(name, &reasons)),
+    );
+}
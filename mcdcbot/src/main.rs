@@ -1,34 +1,218 @@
+mod api;
+mod backup;
 mod data;
 mod embed;
 mod getmyip;
+mod lag;
+mod markdown;
+mod mentions;
+mod metrics;
+mod mirror;
+mod properties;
+mod queue;
+mod reload;
+mod server;
 mod settings;
+mod status;
+mod tellraw;
+mod webhook;
 
-use std::{collections::HashSet, env, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use crate::{data::Data, settings::Settings};
+use crate::{
+    data::{Data, MinecraftServer},
+    mentions::Mentions,
+    queue::{DiscordQueue, QueuedMessage},
+    settings::Settings,
+};
 use minecraft_manager::{
+    chat::ChatMessage,
+    config::{Loaded, LoadedServers},
     events::{MinecraftServerEventType, MinecraftServerWarning},
     tasks::MinecraftServerTask,
-    thread::MinecraftServerThread,
 };
 use poise::{futures_util::lock::Mutex, serenity_prelude as serenity};
+use tokio::sync::{broadcast, Notify};
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
-type Context<'a> = poise::Context<'a, Data, Error>;
+type Context<'a> = poise::Context<'a, Arc<Data>, Error>;
+
+/// how many relayed discord messages are remembered (for edits and deletions)
+const RELAYED_MESSAGES_CAPACITY: usize = 100;
+
+/// a message for discord from the running server's events
+enum Outgoing {
+    Queued(QueuedMessage),
+    /// mentions (`@name`) still have to be resolved
+    Chat(ChatMessage),
+}
+
+/// replies with an error and returns `false` if the command wasn't used in the info channel
+async fn in_info_channel(ctx: Context<'_>) -> Result<bool, Error> {
+    let cid = ctx.data().settings.lock().await.channel_id_info;
+    if ctx.channel_id() != cid {
+        ctx.say(format!("This command can only be used in <#{cid}>."))
+            .await?;
+        Ok(false)
+    } else {
+        Ok(true)
+    }
+}
+
+/// finds a server by its name or one of its aliases
+async fn find_server(data: &Data, srv: &str) -> Option<Arc<Mutex<MinecraftServer>>> {
+    for server in data.servers.lock().await.iter() {
+        if server.lock().await.is_called(srv) {
+            return Some(Arc::clone(server));
+        }
+    }
+    None
+}
+
+/// replies that there is no server called `srv`, suggesting the most similar name
+async fn server_not_found(ctx: Context<'_>, srv: &str) -> Result<(), Error> {
+    let srv = srv.trim().to_lowercase();
+    let mut best: Option<(usize, String)> = None;
+    for server in ctx.data().servers.lock().await.iter() {
+        let server = server.lock().await;
+        for name in server.names() {
+            let distance = edit_distance(&srv, &name.to_lowercase());
+            if best.as_ref().is_none_or(|(d, _)| distance < *d) {
+                best = Some((distance, server.name.clone()));
+            }
+        }
+    }
+    match best {
+        // only suggest names which are somewhat similar
+        Some((distance, name)) if distance <= (srv.chars().count() / 3).max(2) => {
+            ctx.say(format!(
+                "Can't find a server with that name! Did you mean '{name}'?"
+            ))
+            .await?;
+        }
+        _ => {
+            ctx.say("Can't find a server with that name! (see /list)")
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// the number of characters which have to be inserted, removed or replaced to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            row.push(
+                (prev[j] + usize::from(ca != *cb))
+                    .min(prev[j + 1] + 1)
+                    .min(row[j] + 1),
+            );
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+/// suggests servers whose name or aliases contain what the user has typed so far
+async fn autocomplete_server(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<poise::AutocompleteChoice<String>> {
+    let partial = partial.trim().to_lowercase();
+    let mut o = vec![];
+    for server in ctx.data().servers.lock().await.iter() {
+        let server = server.lock().await;
+        if server
+            .names()
+            .any(|name| name.to_lowercase().contains(&partial))
+        {
+            let mut label = server.name.clone();
+            if !server.aliases.is_empty() {
+                label.push_str(&format!(" ({})", server.aliases.join(", ")));
+            }
+            if let Some(description) = &server.description {
+                label.push_str(&format!(" - {description}"));
+            }
+            o.push(poise::AutocompleteChoice {
+                // discord's limit for choice names
+                name: label.chars().take(100).collect(),
+                value: server.name.clone(),
+            });
+        }
+    }
+    o
+}
+
+/// asks the user to confirm something with buttons. Returns the reply (so it can be edited)
+/// and `Some(true)` if they accepted, `Some(false)` if they cancelled, or `None` if they didn't answer in time.
+async fn confirm<'a>(
+    ctx: Context<'a>,
+    content: String,
+    accept_label: &str,
+    timeout: Duration,
+) -> Result<(poise::ReplyHandle<'a>, Option<bool>), Error> {
+    let accept_id = format!("{}accept", ctx.id());
+    let cancel_id = format!("{}cancel", ctx.id());
+    let reply = ctx
+        .send(|m| {
+            m.content(content).components(|c| {
+                c.create_action_row(|r| {
+                    r.create_button(|b| {
+                        b.custom_id(&accept_id)
+                            .label(accept_label)
+                            .style(serenity::ButtonStyle::Success)
+                    })
+                    .create_button(|b| {
+                        b.custom_id(&cancel_id)
+                            .label("Cancel")
+                            .style(serenity::ButtonStyle::Secondary)
+                    })
+                })
+            })
+        })
+        .await?;
+    let press = serenity::CollectComponentInteraction::new(ctx)
+        .message_id(reply.message().await?.id)
+        .author_id(ctx.author().id)
+        .timeout(timeout)
+        .await;
+    let Some(press) = press else {
+        return Ok((reply, None));
+    };
+    press
+        .create_interaction_response(ctx, |r| {
+            r.kind(serenity::InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+    Ok((reply, Some(press.data.custom_id == accept_id)))
+}
 
 #[poise::command(slash_command)]
 async fn list(ctx: Context<'_>) -> Result<(), Error> {
     ctx.say({
-        let mut acc = format!("Available servers:");
+        let mut acc = "Available servers:".to_owned();
         for server in ctx.data().servers.lock().await.iter() {
             let server = server.lock().await;
             acc.push_str("\n- ");
-            if let Some(short) = &server.short {
+            if !server.aliases.is_empty() {
                 acc.push('(');
-                acc.push_str(short);
+                acc.push_str(&server.aliases.join(", "));
                 acc.push_str(") ");
             }
             acc.push_str(server.name.as_str());
+            if let Some(description) = &server.description {
+                acc.push_str(": ");
+                acc.push_str(description);
+            }
         }
         acc
     })
@@ -39,93 +223,81 @@ async fn list(ctx: Context<'_>) -> Result<(), Error> {
 #[poise::command(slash_command)]
 async fn start(
     ctx: Context<'_>,
-    #[description = "Server's name (see /list)"] srv: String,
+    #[description = "Server's name (see /list)"]
+    #[autocomplete = "autocomplete_server"]
+    srv: String,
 ) -> Result<(), Error> {
-    {
-        let cid = ctx.data().settings.lock().await.channel_id_info;
-        if ctx.channel_id() != cid {
-            ctx.say(format!("This command can only be used in <#{cid}>.",))
-                .await?;
-            return Ok(());
-        }
+    if !in_info_channel(ctx).await? {
+        return Ok(());
     }
-    // find server by name
-    let servers_lock = ctx.data().servers.lock().await;
-    let mut matching_server = None;
-    for server in servers_lock.iter() {
-        let server_lock = server.lock().await;
-        if server_lock
-            .short
-            .as_ref()
-            .is_some_and(|short| short == &srv)
-        {
-            matching_server = Some(Arc::clone(server));
-            break;
-        }
+    if let Some(server) = find_server(ctx.data(), &srv).await {
+        match start_server(ctx.data(), server).await {
+            Ok(()) => ctx.say("Starting...").await?,
+            Err(e) => ctx.say(e).await?,
+        };
+    } else {
+        server_not_found(ctx, &srv).await?;
     }
-    if matching_server.is_none() {
-        for server in servers_lock.iter() {
-            let server_lock = server.lock().await;
-            if server_lock.name == srv {
-                matching_server = Some(Arc::clone(server));
-                break;
-            }
-        }
+    Ok(())
+}
+/// starts the server and announces it in discord.
+/// Returns why the server can't be started (i.e. because another one is running).
+async fn start_server(data: &Data, server: Arc<Mutex<MinecraftServer>>) -> Result<(), String> {
+    let mut current_lock = data.current.lock().await;
+    if let Some((current, _)) = current_lock.as_ref() {
+        let current = current.lock().await;
+        return Err(format!(
+            "Already running '{}'! (stop the server before starting it)",
+            current.name,
+        ));
     }
-    if let Some(server) = matching_server {
-        let mut current_lock = ctx.data().current.lock().await;
-        if let Some((current, _)) = current_lock.as_ref() {
-            let current = current.lock().await;
-            ctx.say(format!(
-                "Already running '{}'! (stop the server before starting it)",
-                current.name,
-            ))
-            .await?;
-        } else {
-            let server_lock = server.lock().await;
-            let settings = ctx.data().settings.lock().await;
-            _ = ctx
-                .http()
-                .send_message(
-                    settings.channel_id_info,
-                    &embed::server_started(
-                        &server_lock.name,
-                        Some(
-                            getmyip::get_my_ip(&settings.get_my_ip_url1, &settings.get_my_ip_url2)
-                                .await,
-                        ),
-                    ),
-                )
-                .await;
-            if settings.send_start_stop_messages_in_chat {
-                _ = ctx
-                    .http()
-                    .send_message(
-                        settings.channel_id_chat,
-                        &embed::server_started(&server_lock.name, None),
-                    )
-                    .await;
-            }
-            let thread = server_lock.settings.clone().spawn();
-            drop(server_lock);
-            *current_lock = Some((server, Arc::new(Mutex::new(Some(thread)))));
-            ctx.say(format!("Starting...")).await?;
-        }
-    } else {
-        ctx.say(format!("Can't find a server with that name!"))
-            .await?;
+    // a restore would replace the worlds while the server is using them
+    if data.backup_lock.try_lock().is_none() {
+        return Err("A backup is being made or restored, try again in a moment.".to_owned());
+    }
+    let mut server_lock = server.lock().await;
+    // changes from reloads while the server was running
+    if let Some(pending) = server_lock.pending_settings.take() {
+        server_lock.settings = pending;
+    }
+    let settings = data.settings.lock().await;
+    let queue = &data.queue;
+    queue.push(
+        settings.channel_id_info,
+        QueuedMessage::Message(embed::server_started(
+            &server_lock.name,
+            Some(getmyip::get_my_ip(&settings.get_my_ip_url1, &settings.get_my_ip_url2).await),
+        )),
+    );
+    if settings.send_start_stop_messages_in_chat {
+        queue.push(
+            settings.channel_id_chat,
+            QueuedMessage::Message(embed::server_started(&server_lock.name, None)),
+        );
     }
+    let mut server_settings = server_lock
+        .settings
+        .clone()
+        .with_java_search_paths(settings.java_paths.clone());
+    // the version the server logged (or the jar it was switched to) is more up to date than `version=`
+    if let Some(version) = &server_lock.version {
+        server_settings.minecraft_version = Some(version.clone());
+    }
+    let thread = server_settings.spawn();
+    data.counters
+        .lock()
+        .await
+        .entry(server_lock.file.clone())
+        .or_default()
+        .starts += 1;
+    drop(server_lock);
+    *current_lock = Some((server, Arc::new(Mutex::new(Some(thread)))));
     Ok(())
 }
 #[poise::command(slash_command)]
 async fn stop(ctx: Context<'_>) -> Result<(), Error> {
-    {
-        let cid = ctx.data().settings.lock().await.channel_id_info;
-        if ctx.channel_id() != cid {
-            ctx.say(format!("This command can only be used in <#{cid}>.",))
-                .await?;
-            return Ok(());
-        }
+    if !in_info_channel(ctx).await? {
+        return Ok(());
     }
     let current_lock = ctx.data().current.lock().await;
     if let Some((_, thread)) = current_lock.as_ref() {
@@ -136,10 +308,9 @@ async fn stop(ctx: Context<'_>) -> Result<(), Error> {
             .unwrap()
             .clone_task_sender()
             .send_task(MinecraftServerTask::Stop);
-        ctx.say(format!("Stopping...")).await?;
+        ctx.say("Stopping...").await?;
     } else {
-        ctx.say(format!("Use /start to start a server first"))
-            .await?;
+        ctx.say("Use /start to start a server first").await?;
     }
     Ok(())
 }
@@ -148,13 +319,8 @@ async fn run_command(
     ctx: Context<'_>,
     #[description = "command (without '/')"] cmd: String,
 ) -> Result<(), Error> {
-    {
-        let cid = ctx.data().settings.lock().await.channel_id_info;
-        if ctx.channel_id() != cid {
-            ctx.say(format!("This command can only be used in <#{cid}>.",))
-                .await?;
-            return Ok(());
-        }
+    if !in_info_channel(ctx).await? {
+        return Ok(());
     }
     let current_lock = ctx.data().current.lock().await;
     if let Some((_, thread)) = current_lock.as_ref() {
@@ -167,44 +333,163 @@ async fn run_command(
             .clone_task_sender()
             .send_task(MinecraftServerTask::RunCommand(cmd));
     } else {
-        ctx.say(format!("Use /start to start a server first"))
-            .await?;
+        ctx.say("Use /start to start a server first").await?;
     }
     Ok(())
 }
 
+/// Link your discord account to your minecraft account
+#[poise::command(slash_command)]
+async fn link(
+    ctx: Context<'_>,
+    #[description = "Your minecraft name"] minecraft_name: String,
+) -> Result<(), Error> {
+    let code = ctx
+        .data()
+        .mentions
+        .lock()
+        .await
+        .start_link(minecraft_name.trim(), ctx.author().id.0);
+    ctx.send(|m| {
+        m.content(format!(
+            "To link your account to '{}', join the server as '{0}' and type `{code}` in the chat within {} minutes.",
+            minecraft_name.trim(),
+            mentions::LINK_CODE_TIMEOUT.as_secs() / 60
+        ))
+        .ephemeral(true)
+    })
+    .await?;
+    Ok(())
+}
+/// Allow or disallow players to ping you from minecraft
+#[poise::command(slash_command)]
+async fn mentions(
+    ctx: Context<'_>,
+    #[description = "Whether players can ping you from minecraft"] enabled: bool,
+) -> Result<(), Error> {
+    ctx.data()
+        .mentions
+        .lock()
+        .await
+        .set_opted_out(ctx.author().id.0, !enabled)?;
+    ctx.say(if enabled {
+        "Players can now ping you from minecraft."
+    } else {
+        "Players can no longer ping you from minecraft."
+    })
+    .await?;
+    Ok(())
+}
+
 async fn event_handler(
     ctx: &serenity::Context,
     event: &poise::Event<'_>,
-    _framework: poise::FrameworkContext<'_, Data, Error>,
+    _framework: poise::FrameworkContext<'_, Arc<Data>, Error>,
     data: &Data,
 ) -> Result<(), Error> {
     match event {
         poise::Event::Message { new_message } => {
-            if !new_message.author.bot
-                && new_message.channel_id.0 == data.settings.lock().await.channel_id_chat
+            if new_message.author.bot
+                || new_message.channel_id.0 != data.settings.lock().await.channel_id_chat
             {
+                return Ok(());
+            }
+            let current_lock = data.current.lock().await;
+            if let Some((_current, thread)) = current_lock.as_ref() {
+                let (task_sender, online_players) = {
+                    let thread_lock = thread.lock().await;
+                    let thread = thread_lock.as_ref().unwrap();
+                    (thread.clone_task_sender(), thread.online_players().to_vec())
+                };
+                let msg = tellraw::DiscordChatMessage::from_message(
+                    ctx,
+                    new_message,
+                    &*data.mentions.lock().await,
+                    &online_players,
+                )
+                .await;
+                {
+                    let mut relayed = data.relayed_messages.lock().await;
+                    if relayed.len() == RELAYED_MESSAGES_CAPACITY {
+                        relayed.pop_front();
+                    }
+                    relayed.push_back((new_message.id, msg.author.clone()));
+                }
+                _ = task_sender
+                    .send_task(MinecraftServerTask::RunCommand(tellraw::chat_message(&msg)));
+                for player in &msg.mentioned_players {
+                    _ = task_sender.send_task(MinecraftServerTask::RunCommand(
+                        tellraw::mention_sound(player),
+                    ));
+                }
+            }
+        }
+        poise::Event::MessageUpdate { new, event, .. } => {
+            // discord also sends updates when it adds link previews, which aren't edits
+            if event.edited_timestamp.is_none()
+                || !data.settings.lock().await.send_discord_edits_in_minecraft
+                || !data
+                    .relayed_messages
+                    .lock()
+                    .await
+                    .iter()
+                    .any(|(id, _)| *id == event.id)
+            {
+                return Ok(());
+            }
+            let current_lock = data.current.lock().await;
+            if let Some((_current, thread)) = current_lock.as_ref() {
+                let new_message = if let Some(new) = new {
+                    new.clone()
+                } else {
+                    event.channel_id.message(ctx, event.id).await?
+                };
+                let (task_sender, online_players) = {
+                    let thread_lock = thread.lock().await;
+                    let thread = thread_lock.as_ref().unwrap();
+                    (thread.clone_task_sender(), thread.online_players().to_vec())
+                };
+                let msg = tellraw::DiscordChatMessage::from_message(
+                    ctx,
+                    &new_message,
+                    &*data.mentions.lock().await,
+                    &online_players,
+                )
+                .await;
+                _ = task_sender
+                    .send_task(MinecraftServerTask::RunCommand(tellraw::chat_message(&msg)));
+            }
+        }
+        poise::Event::MessageDelete {
+            deleted_message_id, ..
+        } => {
+            if !data
+                .settings
+                .lock()
+                .await
+                .send_discord_deletions_in_minecraft
+            {
+                return Ok(());
+            }
+            let author = data
+                .relayed_messages
+                .lock()
+                .await
+                .iter()
+                .find(|(id, _)| id == deleted_message_id)
+                .map(|(_, author)| author.clone());
+            if let Some(author) = author {
                 let current_lock = data.current.lock().await;
                 if let Some((_current, thread)) = current_lock.as_ref() {
-                    let msg = new_message.content_safe(&ctx.cache);
-                    let msg = msg
-                        .replace("\\", "\\\\")
-                        .replace("\n", "\\n")
-                        .replace("\r", "\\r");
-                    let author = new_message
-                        .author_nick(&ctx)
-                        .await
-                        .unwrap_or_else(|| new_message.author.name.clone());
                     _ = thread
                         .lock()
                         .await
                         .as_ref()
                         .unwrap()
                         .clone_task_sender()
-                        .send_task(MinecraftServerTask::RunCommand(format!(
-                            "tellraw @a \"<{author}> {msg}\"",
+                        .send_task(MinecraftServerTask::RunCommand(tellraw::message_deleted(
+                            &author,
                         )));
-                } else {
                 }
             }
         }
@@ -213,56 +498,96 @@ async fn event_handler(
     Ok(())
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() {
-    // read settings file
-    let settings =
-        Settings::from_file(env::var("McDcBotSettingsFile").unwrap_or(format!("settings.txt")))
-            .unwrap();
-    // read mc servers
-    let mut servers = vec![];
-    for file in std::fs::read_dir(env::var("McDcBotServersDir").unwrap_or(format!("servers")))
-        .expect("Couldn't read servers dir, maybe specify the directory with the McDcBotServersDir env variable?") {
-        let file = file.unwrap();
-        let content = std::fs::read_to_string(file.path()).unwrap();
-        let mut lines = content.lines().filter(|line| !line.trim().is_empty());
-        let settings = minecraft_manager::MinecraftServerSettings::from_lines(&mut lines).unwrap();
-        servers.push(data::MinecraftServer {
-            name: file.file_name().to_string_lossy().into_owned(),
-            short: None,
-            settings
-        });
+/// prints all errors and warnings from loading the config files.
+/// Returns false if there were errors.
+fn print_config_issues(
+    settings: &Loaded<Settings>,
+    servers: &std::io::Result<LoadedServers>,
+    servers_dir: &Path,
+) -> bool {
+    let mut ok = true;
+    match settings {
+        Ok((_, warnings)) => {
+            for warning in warnings {
+                eprintln!("[WARN/CONFIG] {warning}");
+            }
+        }
+        Err(e) => {
+            eprintln!("[ERR/CONFIG] {e}");
+            ok = false;
+        }
     }
-    let mut shorts = HashSet::new();
-    for server in &mut servers {
-        if let Some(ch) = server.name.trim().chars().next() {
-            let ch = ch.to_lowercase();
-            if shorts.insert(format!("{ch}")) {
-                server.short = Some(format!("{ch}"));
-                continue;
+    match servers {
+        Ok(servers) => {
+            for warning in &servers.warnings {
+                eprintln!("[WARN/CONFIG] {warning}");
             }
+            for error in &servers.errors {
+                eprintln!("[ERR/CONFIG] {error}");
+                ok = false;
+            }
+        }
+        Err(e) => {
+            eprintln!("[ERR/CONFIG] Couldn't read the servers directory ({}): {e}. Maybe specify the directory with the McDcBotServersDir env variable?", servers_dir.display());
+            ok = false;
         }
-        let initials = server
-            .name
-            .trim()
-            .split_whitespace()
-            .filter_map(|v| v.chars().next().map(|c| c.to_uppercase()))
-            .flatten()
-            .collect::<String>();
-        if !initials.is_empty() {
-            if shorts.insert(initials.clone()) {
-                server.short = Some(initials);
-                continue;
+    }
+    ok
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let check_config = env::args().any(|arg| arg == "--check-config");
+    // read settings file
+    let settings_file = env::var("McDcBotSettingsFile")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            if Path::new("settings.toml").exists() {
+                PathBuf::from("settings.toml")
+            } else {
+                PathBuf::from("settings.txt")
             }
+        });
+    let settings = Settings::from_file(&settings_file);
+    // read mc servers
+    let servers_dir = PathBuf::from(env::var("McDcBotServersDir").unwrap_or("servers".to_owned()));
+    let servers = minecraft_manager::config::load_servers(&servers_dir);
+    let ok = print_config_issues(&settings, &servers, &servers_dir);
+    if check_config {
+        if ok {
+            eprintln!("Config is valid.");
         }
+        std::process::exit(if ok { 0 } else { 1 });
     }
-    // current server
-    let current = Arc::new(Mutex::new(None));
-    let current_thread = Arc::clone(&current);
+    let (Ok((settings, _)), Ok(LoadedServers { servers, .. })) = (settings, servers) else {
+        std::process::exit(1);
+    };
+    let servers = servers
+        .into_iter()
+        .map(|server| Arc::new(Mutex::new(MinecraftServer::from(server))))
+        .collect();
+    // discord <-> minecraft account links
+    let mention_links = Mentions::load(
+        &settings.mentions_file,
+        Duration::from_secs(settings.mention_cooldown_secs),
+    )
+    .expect("Couldn't read the mentions file");
     // start
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![list(), start(), stop(), run_command()],
+            commands: vec![
+                list(),
+                start(),
+                stop(),
+                run_command(),
+                link(),
+                mentions(),
+                properties::properties(),
+                server::server(),
+                reload::reload(),
+                backup::backup(),
+                status::status(),
+            ],
             event_handler: |ctx, event, framework, data| {
                 Box::pin(event_handler(ctx, event, framework, data))
             },
@@ -272,98 +597,226 @@ async fn main() {
         .intents(
             serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT,
         )
-        .setup(|ctx, ready, framework: &poise::Framework<Data, Error>| {
-            Box::pin(async move {
-                ctx.idle().await;
-                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                eprintln!("Connected as '{}'.", ready.user.name);
-                {
-                    let ctx = ctx.clone();
-                    let settings = settings.clone();
-                    tokio::task::spawn(async move {
-                        let sleep_time = Duration::from_millis(100);
-                        let mut running = false;
-                        loop {
-                            tokio::time::sleep(sleep_time).await;
-                            let mut current_lock: poise::futures_util::lock::MutexGuard<
-                                '_,
-                                Option<(
-                                    Arc<Mutex<data::MinecraftServer>>,
-                                    Arc<Mutex<Option<MinecraftServerThread>>>,
-                                )>,
-                            > = current_thread.lock().await;
-                            if let Some((_current_server, current_thread_mutex)) =
-                                current_lock.as_ref()
-                            {
-                                let mut current_thread_opt = current_thread_mutex.lock().await;
-                                let current_thread = current_thread_opt.as_mut().unwrap();
-                                current_thread.update();
-                                for event in current_thread.handle_new_events() {
-                                    match &event.event {
-                                    MinecraftServerEventType::Warning(w) => match w {
-                                        MinecraftServerWarning::CouldNotGetServerProcessStdio
-                                        | MinecraftServerWarning::CantWriteToStdin(_) => {
-                                            ctx.dnd().await;
+        .setup(
+            |ctx, ready, framework: &poise::Framework<Arc<Data>, Error>| {
+                Box::pin(async move {
+                    ctx.idle().await;
+                    poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                    eprintln!("Connected as '{}'.", ready.user.name);
+                    let data = Arc::new(Data {
+                        settings: Mutex::new(settings),
+                        settings_file,
+                        servers: Mutex::new(servers),
+                        servers_dir,
+                        current: Mutex::new(None),
+                        mentions: Mutex::new(mention_links),
+                        queue: DiscordQueue::new(Arc::clone(&ctx.http)),
+                        relayed_messages: Mutex::new(VecDeque::with_capacity(
+                            RELAYED_MESSAGES_CAPACITY,
+                        )),
+                        backup_lock: Mutex::new(()),
+                        world_saved: Notify::new(),
+                        last_lag_alert: Mutex::new(None),
+                        counters: Mutex::new(BTreeMap::new()),
+                        events: broadcast::channel(api::EVENTS_CAPACITY).0,
+                    });
+                    tokio::task::spawn(reload::watch(Arc::clone(&data)));
+                    tokio::task::spawn(backup::schedule(Arc::clone(&data)));
+                    tokio::task::spawn(lag::schedule(Arc::clone(&data)));
+                    tokio::task::spawn(metrics::serve(Arc::clone(&data)));
+                    tokio::task::spawn(api::serve(Arc::clone(&data)));
+                    {
+                        let ctx = ctx.clone();
+                        let data = Arc::clone(&data);
+                        tokio::task::spawn(async move {
+                            let sleep_time = Duration::from_millis(100);
+                            let mut running = false;
+                            let queue = &data.queue;
+                            loop {
+                                tokio::time::sleep(sleep_time).await;
+                                let (outgoing, settings) = {
+                                    let mut current_lock = data.current.lock().await;
+                                    let Some((current_server, current_thread_mutex)) =
+                                        current_lock.as_ref()
+                                    else {
+                                        continue;
+                                    };
+                                    let mut current_thread_opt = current_thread_mutex.lock().await;
+                                    let current_thread = current_thread_opt.as_mut().unwrap();
+                                    current_thread.update();
+                                    // cloned so that settings can be reloaded while we wait for discord
+                                    let settings = data.settings.lock().await.clone();
+                                    let mut new_tick_sample = false;
+                                    // sent after the locks are released, because resolving mentions waits for discord
+                                    let mut outgoing = vec![];
+                                    let task_sender = current_thread.clone_task_sender();
+                                    for event in current_thread.handle_new_events() {
+                                        if data.events.receiver_count() > 0 {
+                                            let name = current_server.lock().await.name.clone();
+                                            _ = data.events.send(api::event(&name, &event.event));
                                         }
-                                    },
-                                    MinecraftServerEventType::JoinLeave(e) => {
-                                        if settings.send_join_and_leave_messages {
-                                        _ = ctx
-                                            .http
-                                            .send_message(
-                                                settings.channel_id_chat,
-                                                &embed::join_leave(
-                                                    e
-                                                ),
-                                            )
+                                        match &event.event {
+                                        MinecraftServerEventType::Warning(w) => match w {
+                                            MinecraftServerWarning::CouldNotGetServerProcessStdio
+                                            | MinecraftServerWarning::CantWriteToStdin(_) => {
+                                                ctx.dnd().await;
+                                            }
+                                        },
+                                        MinecraftServerEventType::JoinLeave(e) => {
+                                            if e.joined {
+                                                metrics::count(&data, current_server, |c| c.joins += 1)
+                                                    .await;
+                                            }
+                                            if settings.send_join_and_leave_messages {
+                                                outgoing.push((
+                                                    settings.channel_id_chat,
+                                                    Outgoing::Queued(QueuedMessage::JoinLeave {
+                                                        username: e.username.clone(),
+                                                        joined: e.joined,
+                                                    }),
+                                                ));
+                                            }
+                                        }
+                                        MinecraftServerEventType::ChatMessage(e) => {
+                                            // a code from /link isn't relayed
+                                            let confirmed =
+                                                data.mentions.lock().await.confirm_link(&e.author, &e.message);
+                                            if let Some(result) = confirmed {
+                                                if let Err(err) = &result {
+                                                    eprintln!("[WARN/MENTIONS] Couldn't save the mentions file: {err}");
+                                                }
+                                                _ = task_sender.send_task(
+                                                    MinecraftServerTask::RunCommand(tellraw::link_confirmed(
+                                                        &e.author,
+                                                        result.is_ok(),
+                                                    )),
+                                                );
+                                                continue;
+                                            }
+                                            metrics::count(&data, current_server, |c| {
+                                                c.chat_messages += 1
+                                            })
                                             .await;
+                                            outgoing.push((
+                                                settings.channel_id_chat,
+                                                Outgoing::Chat(e.clone()),
+                                            ));
+                                        }
+                                        MinecraftServerEventType::Death(e) => {
+                                            if settings.send_death_messages {
+                                                outgoing.push((
+                                                    settings.channel_id_chat,
+                                                    Outgoing::Queued(QueuedMessage::Message(
+                                                        embed::death(e),
+                                                    )),
+                                                ));
+                                            }
+                                        }
+                                        MinecraftServerEventType::Custom { kind, fields } => {
+                                            if let Some(template) = settings.custom_events.get(kind) {
+                                                outgoing.push((
+                                                    template.channel.unwrap_or(settings.channel_id_chat),
+                                                    Outgoing::Queued(QueuedMessage::Message(
+                                                        embed::custom_event(template, kind, fields),
+                                                    )),
+                                                ));
+                                            }
+                                        }
+                                        // the thread keeps track of the process itself
+                                        MinecraftServerEventType::Spawned { .. } => {}
+                                        MinecraftServerEventType::Saved => {
+                                            data.world_saved.notify_waiters();
+                                        }
+                                        MinecraftServerEventType::Version(version) => {
+                                            current_server.lock().await.version =
+                                                Some(version.clone());
                                         }
+                                        MinecraftServerEventType::Tps(_)
+                                        | MinecraftServerEventType::Mspt(_)
+                                        | MinecraftServerEventType::CantKeepUp { .. } => {
+                                            new_tick_sample = true;
+                                        }
+                                    }
+                                    }
+                                    if new_tick_sample {
+                                        let name = current_server.lock().await.name.clone();
+                                        lag::alert_if_lagging(
+                                            &data,
+                                            &name,
+                                            current_thread.performance(),
+                                            &settings,
+                                        )
+                                        .await;
                                     }
-                                    MinecraftServerEventType::ChatMessage(e) => {
-                                        _ = ctx
-                                            .http
-                                            .send_message(
+                                    if current_thread.is_finished() {
+                                        let cto = current_thread_opt.take().unwrap();
+                                        let reason = cto.get_stop_reason().ok();
+                                        if reason.as_ref().is_some_and(|r| r.is_crash()) {
+                                            metrics::count(&data, current_server, |c| c.crashes += 1)
+                                                .await;
+                                        }
+                                        if data.events.receiver_count() > 0 {
+                                            let name = current_server.lock().await.name.clone();
+                                            _ = data.events.send(api::stopped(&name, reason.as_ref()));
+                                        }
+                                        let msg = embed::server_stopped(reason);
+                                        if settings.send_start_stop_messages_in_chat {
+                                            outgoing.push((
                                                 settings.channel_id_chat,
-                                                &embed::chat_message(e),
-                                            )
-                                            .await;
+                                                Outgoing::Queued(QueuedMessage::Message(msg.clone())),
+                                            ));
+                                        }
+                                        outgoing.push((
+                                            settings.channel_id_info,
+                                            Outgoing::Queued(QueuedMessage::Message(msg)),
+                                        ));
+                                        running = false;
+                                        drop(current_thread_opt);
+                                        *current_lock = None;
+                                        ctx.idle().await;
+                                    } else if !running {
+                                        running = true;
+                                        ctx.online().await;
                                     }
-                                }
-                                }
-                                if current_thread.is_finished() {
-                                    let cto = current_thread_opt.take().unwrap();
-                                    let msg = embed::server_stopped(cto.get_stop_reason().ok());
-                                    _ = ctx.http.send_message(settings.channel_id_info, &msg).await;
-                                    if settings.send_start_stop_messages_in_chat {
-                                        _ = ctx
-                                            .http
-                                            .send_message(settings.channel_id_chat, &msg)
+                                    (outgoing, settings)
+                                };
+                                for (channel, message) in outgoing {
+                                    let message = match message {
+                                        Outgoing::Queued(message) => message,
+                                        Outgoing::Chat(e) => {
+                                            let mentions = mentions::resolve(
+                                                &ctx,
+                                                &data.mentions,
+                                                settings.channel_id_chat,
+                                                &e.author,
+                                                &e.message,
+                                            )
                                             .await;
-                                    }
-                                    running = false;
-                                    drop(current_thread_opt);
-                                    *current_lock = None;
-                                    ctx.idle().await;
-                                } else if !running {
-                                    running = true;
-                                    ctx.online().await;
+                                            if let Some(webhook) = &settings.chat_webhook {
+                                                QueuedMessage::Webhook(
+                                                    webhook.clone(),
+                                                    embed::chat_message_webhook(
+                                                        &e,
+                                                        &settings.skin_render_url,
+                                                        &mentions,
+                                                    ),
+                                                )
+                                            } else {
+                                                QueuedMessage::Message(embed::chat_message(
+                                                    &e, &mentions,
+                                                ))
+                                            }
+                                        }
+                                    };
+                                    queue.push(channel, message);
                                 }
                             }
-                        }
-                    });
-                }
-                Ok(Data {
-                    settings: Mutex::new(settings),
-                    current,
-                    servers: Mutex::new(
-                        servers
-                            .into_iter()
-                            .map(|s| Arc::new(Mutex::new(s)))
-                            .collect(),
-                    ),
+                        });
+                    }
+                    Ok(data)
                 })
-            })
-        });
+            },
+        );
 
     framework.run().await.unwrap();
 }
@@ -0,0 +1,326 @@
+use minecraft_manager::text::TextComponent;
+
+#[derive(Clone, Copy, Default)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+    strikethrough: bool,
+}
+
+/// discord markdown markers, longest first so that `**` isn't parsed as two `*`s
+/// (`***` is bold and italic). Code spans are handled separately because their content isn't formatted.
+const MARKERS: [&str; 7] = ["||", "***", "**", "__", "~~", "*", "_"];
+
+/// converts discord markdown (bold, italic, underline, strikethrough, spoilers, code)
+/// to a minecraft text component. Spoilers become obfuscated text which shows its content when hovered.
+pub fn to_minecraft(text: &str) -> TextComponent {
+    TextComponent::empty().append_all(parse(text, Style::default()))
+}
+
+fn parse(text: &str, style: Style) -> Vec<TextComponent> {
+    let mut o = vec![];
+    let mut plain = String::new();
+    let mut i = 0;
+    'chars: while let Some(ch) = text[i..].chars().next() {
+        let rest = &text[i..];
+        if ch == '\\' {
+            // escaped markdown character
+            if let Some(escaped) = rest[1..].chars().next() {
+                if escaped.is_ascii_punctuation() {
+                    plain.push(escaped);
+                    i += 1 + escaped.len_utf8();
+                    continue;
+                }
+            }
+        } else if ch == '`' {
+            let ticks = if rest.starts_with("```") { "```" } else { "`" };
+            if let Some(end) = rest[ticks.len()..].find(ticks) {
+                let code = &rest[ticks.len()..ticks.len() + end];
+                // drop the language of code blocks
+                let code = if ticks == "```" {
+                    match code.split_once('\n') {
+                        Some((lang, code)) if !lang.contains(' ') => code,
+                        _ => code,
+                    }
+                } else {
+                    code
+                };
+                flush(&mut o, &mut plain, style);
+                o.push(
+                    styled(TextComponent::text(code.trim_end_matches('\n')), style).color("gray"),
+                );
+                i += ticks.len() * 2 + end;
+                continue;
+            }
+        } else {
+            for marker in MARKERS {
+                if !rest.starts_with(marker) {
+                    continue;
+                }
+                // `_` only starts italic text at the start of a word (not in snake_case names)
+                if marker == "_"
+                    && text[..i]
+                        .chars()
+                        .last()
+                        .is_some_and(|c| c.is_alphanumeric())
+                {
+                    continue;
+                }
+                let inner_start = marker.len();
+                if let Some(end) = find_closing(&rest[inner_start..], marker) {
+                    let inner = &rest[inner_start..inner_start + end];
+                    if inner.trim().is_empty() {
+                        continue;
+                    }
+                    // `2 * 3 * 4` isn't italic
+                    if matches!(marker, "*" | "_")
+                        && (inner.starts_with(char::is_whitespace)
+                            || inner.ends_with(char::is_whitespace))
+                    {
+                        continue;
+                    }
+                    let mut inner_style = style;
+                    match marker {
+                        "||" => {}
+                        "***" => {
+                            inner_style.bold = true;
+                            inner_style.italic = true;
+                        }
+                        "**" => inner_style.bold = true,
+                        "__" => inner_style.underlined = true,
+                        "~~" => inner_style.strikethrough = true,
+                        _ => inner_style.italic = true,
+                    }
+                    flush(&mut o, &mut plain, style);
+                    let inner_components = parse(inner, inner_style);
+                    if marker == "||" {
+                        let hidden = TextComponent::empty().append_all(inner_components);
+                        let hover = hidden.plain_text();
+                        o.push(hidden.obfuscated().hover_text(TextComponent::text(hover)));
+                    } else {
+                        o.extend(inner_components);
+                    }
+                    i += inner_start * 2 + end;
+                    continue 'chars;
+                }
+            }
+        }
+        plain.push(ch);
+        i += ch.len_utf8();
+    }
+    flush(&mut o, &mut plain, style);
+    o
+}
+
+/// finds the next `marker` in `text` which isn't escaped with a backslash
+fn find_closing(text: &str, marker: &str) -> Option<usize> {
+    let i = text
+        .match_indices(marker)
+        .map(|(i, _)| i)
+        .find(|i| !text[..*i].ends_with('\\'))?;
+    // in `**bold *both***`, the italic text is closed first
+    if marker == "**" && text[i..].starts_with("***") && text[..i].matches('*').count() % 2 == 1 {
+        return Some(i + 1);
+    }
+    Some(i)
+}
+
+fn flush(o: &mut Vec<TextComponent>, plain: &mut String, style: Style) {
+    if !plain.is_empty() {
+        o.push(styled(TextComponent::with_links(plain), style));
+        plain.clear();
+    }
+}
+
+fn styled(mut component: TextComponent, style: Style) -> TextComponent {
+    if style.bold {
+        component = component.bold();
+    }
+    if style.italic {
+        component = component.italic();
+    }
+    if style.underlined {
+        component = component.underlined();
+    }
+    if style.strikethrough {
+        component = component.strikethrough();
+    }
+    component
+}
+
+/// converts minecraft formatting codes (`§l`, `§o`, ...) to discord markdown.
+/// colors are removed, obfuscated text becomes a spoiler,
+/// and characters which discord would interpret as markdown are escaped (except in links).
+pub fn from_minecraft(text: &str) -> String {
+    let mut o = String::new();
+    // markers which should apply to the next text
+    let mut wanted: Vec<&str> = vec![];
+    // markers which were written to `o` and not closed yet
+    let mut open: Vec<&str> = vec![];
+    let mut i = 0;
+    // if the last character of the text (not of a formatting code) is part of a word
+    let mut word = false;
+    while let Some(ch) = text[i..].chars().next() {
+        let start = i;
+        i += ch.len_utf8();
+        if ch == '§' {
+            let Some(code) = text[i..].chars().next() else {
+                break;
+            };
+            i += code.len_utf8();
+            let marker = match code.to_ascii_lowercase() {
+                'k' => "||",
+                'l' => "**",
+                'm' => "~~",
+                'n' => "__",
+                'o' => "*",
+                // colors and `§r` reset all formatting
+                _ => {
+                    wanted.clear();
+                    continue;
+                }
+            };
+            if !wanted.contains(&marker) {
+                wanted.push(marker);
+            }
+            continue;
+        }
+        if open != wanted {
+            // markers which are still wanted stay open, so nested formatting stays nested
+            let same = open
+                .iter()
+                .take_while(|marker| wanted.contains(marker))
+                .count();
+            let mut closing = open.split_off(same);
+            close(&mut o, &mut closing);
+            for marker in &wanted {
+                if !open.contains(marker) {
+                    o.push_str(marker);
+                    open.push(marker);
+                }
+            }
+            wanted.clone_from(&open);
+        }
+        // discord wouldn't recognize escaped links
+        if let Some(url) = link_at(&text[start..]).filter(|_| !word) {
+            o.push_str(url);
+            i = start + url.len();
+            continue;
+        }
+        word = ch.is_alphanumeric();
+        if matches!(
+            ch,
+            '\\' | '*' | '_' | '~' | '|' | '`' | '>' | '#' | '[' | ']'
+        ) {
+            o.push('\\');
+        }
+        o.push(ch);
+    }
+    close(&mut o, &mut open);
+    o
+}
+
+/// the `http://` or `https://` link at the start of `text`, up to the next whitespace or formatting code
+fn link_at(text: &str) -> Option<&str> {
+    if !(text.starts_with("https://") || text.starts_with("http://")) {
+        return None;
+    }
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == '§')
+        .unwrap_or(text.len());
+    Some(&text[..end])
+}
+
+fn close(o: &mut String, open: &mut Vec<&str>) {
+    while let Some(marker) = open.pop() {
+        o.push_str(marker);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `component` with minecraft formatting codes, like the server sends it
+    fn legacy(component: &TextComponent) -> String {
+        fn walk(component: &TextComponent, mut codes: String, o: &mut String) {
+            // links are underlined by `with_links`, which isn't part of the message
+            let link = component.click_event.is_some();
+            for (set, code) in [
+                (component.obfuscated, 'k'),
+                (component.bold, 'l'),
+                (component.strikethrough, 'm'),
+                (component.underlined, 'n'),
+                (component.italic, 'o'),
+            ] {
+                if set == Some(true) && !(link && code == 'n') {
+                    codes.push('§');
+                    codes.push(code);
+                }
+            }
+            if !component.text.is_empty() {
+                o.push_str(&format!("§r{codes}{}", component.text));
+            }
+            for child in &component.extra {
+                walk(child, codes.clone(), o);
+            }
+        }
+        let mut o = String::new();
+        walk(component, String::new(), &mut o);
+        o
+    }
+
+    fn round_trip(markdown: &str) -> String {
+        from_minecraft(&legacy(&to_minecraft(markdown)))
+    }
+
+    #[test]
+    fn nesting_round_trips() {
+        for markdown in [
+            "***x***",
+            "**bold *both***",
+            "~~gone~~ __under **both**__",
+            "||secret||",
+        ] {
+            assert_eq!(round_trip(markdown), markdown);
+        }
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        for markdown in [
+            r"\*not italic\*",
+            r"2 \* 3 \* 4",
+            r"snake\_case\_name",
+            r"\# not a heading",
+            r"\> not a quote",
+        ] {
+            assert_eq!(round_trip(markdown), markdown);
+        }
+        // markdown characters which aren't formatting are escaped on the way back
+        assert_eq!(round_trip("2 * 3 * 4"), r"2 \* 3 \* 4");
+        assert_eq!(round_trip("snake_case"), r"snake\_case");
+    }
+
+    #[test]
+    fn links_are_not_escaped() {
+        for markdown in [
+            "https://x.y/a_b",
+            "see https://x.y/a_b*c~d and http://x.y/_",
+            "**https://x.y/a_b**",
+        ] {
+            assert_eq!(round_trip(markdown), markdown);
+        }
+        assert_eq!(
+            from_minecraft("a_b https://x.y/a_b"),
+            r"a\_b https://x.y/a_b"
+        );
+        // not a link
+        assert_eq!(from_minecraft("xhttps://x.y/a_b"), r"xhttps://x.y/a\_b");
+        assert_eq!(
+            from_minecraft("§ohttps://x.y/a_b§r_"),
+            r"*https://x.y/a_b*\_"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    hash::{BuildHasher, Hasher},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use poise::serenity_prelude as serenity;
+
+/// how long a player has to type the code shown by `/link` in minecraft
+pub const LINK_CODE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Links between minecraft players and discord users, and users who don't want to be pinged from minecraft.
+/// Saved to a file, one entry per line: `link <minecraft name> <discord user id>` or `optout <discord user id>`.
+pub struct Mentions {
+    path: PathBuf,
+    /// lowercase minecraft name -> discord user id
+    links: HashMap<String, u64>,
+    opted_out: HashSet<u64>,
+    /// links which the player hasn't confirmed in minecraft yet: lowercase minecraft name -> discord user id, code, created
+    pending: HashMap<String, (u64, String, Instant)>,
+    /// when each minecraft player last pinged someone
+    last_ping: HashMap<String, Instant>,
+    cooldown: Duration,
+}
+
+impl Mentions {
+    /// loads the file at `path`, if it exists
+    pub fn load(path: impl Into<PathBuf>, cooldown: Duration) -> std::io::Result<Self> {
+        let path = path.into();
+        let mut links = HashMap::new();
+        let mut opted_out = HashSet::new();
+        match std::fs::read_to_string(&path) {
+            Ok(file) => {
+                for line in file.lines() {
+                    let mut words = line.split_whitespace();
+                    match (words.next(), words.next(), words.next()) {
+                        (Some("link"), Some(name), Some(id)) => {
+                            if let Ok(id) = id.parse() {
+                                links.insert(name.to_lowercase(), id);
+                            }
+                        }
+                        (Some("optout"), Some(id), None) => {
+                            if let Ok(id) = id.parse() {
+                                opted_out.insert(id);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(Self {
+            path,
+            links,
+            opted_out,
+            pending: HashMap::new(),
+            last_ping: HashMap::new(),
+            cooldown,
+        })
+    }
+    /// re-reads the links and opt-outs from the file at `path`. Unconfirmed `/link` codes and ping cooldowns are kept.
+    pub fn reload(&mut self, path: impl Into<PathBuf>, cooldown: Duration) -> std::io::Result<()> {
+        let loaded = Self::load(path, cooldown)?;
+        self.path = loaded.path;
+        self.links = loaded.links;
+        self.opted_out = loaded.opted_out;
+        self.cooldown = loaded.cooldown;
+        Ok(())
+    }
+    fn save(&self) -> std::io::Result<()> {
+        let mut file = String::new();
+        for (name, id) in &self.links {
+            file.push_str(&format!("link {name} {id}\n"));
+        }
+        for id in &self.opted_out {
+            file.push_str(&format!("optout {id}\n"));
+        }
+        std::fs::write(&self.path, file)
+    }
+
+    /// starts linking the minecraft player to the discord user, replacing any earlier unconfirmed link of that user.
+    /// Returns the code the player has to type in minecraft chat (see [`Mentions::confirm_link`]).
+    pub fn start_link(&mut self, minecraft_name: &str, user: u64) -> String {
+        self.pending
+            .retain(|_, (id, _, created)| *id != user && created.elapsed() < LINK_CODE_TIMEOUT);
+        // `RandomState`s are seeded randomly
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(user);
+        let code = format!("{:06}", hasher.finish() % 1_000_000);
+        self.pending.insert(
+            minecraft_name.to_lowercase(),
+            (user, code.clone(), Instant::now()),
+        );
+        code
+    }
+    /// if `msg` is the code from [`Mentions::start_link`] for `author`, links them and returns the discord user.
+    /// The player proved that they are `author`, so this replaces any previous link of that name and of the user.
+    pub fn confirm_link(&mut self, author: &str, msg: &str) -> Option<std::io::Result<u64>> {
+        let name = author.to_lowercase();
+        let (user, code, created) = self.pending.get(&name)?;
+        if msg.trim() != code || created.elapsed() >= LINK_CODE_TIMEOUT {
+            return None;
+        }
+        let user = *user;
+        self.pending.remove(&name);
+        self.links.retain(|_, id| *id != user);
+        self.links.insert(name, user);
+        Some(self.save().map(|()| user))
+    }
+    pub fn set_opted_out(&mut self, user: u64, opted_out: bool) -> std::io::Result<()> {
+        if opted_out {
+            self.opted_out.insert(user);
+        } else {
+            self.opted_out.remove(&user);
+        }
+        self.save()
+    }
+    pub fn linked_user(&self, minecraft_name: &str) -> Option<u64> {
+        self.links.get(&minecraft_name.to_lowercase()).copied()
+    }
+    /// the (lowercase) minecraft name linked to the discord user
+    pub fn linked_minecraft_name(&self, user: u64) -> Option<&str> {
+        self.links
+            .iter()
+            .find(|(_, id)| **id == user)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// the `@name`s in a minecraft chat message (without the `@`)
+pub fn find_mentions(msg: &str) -> Vec<&str> {
+    let mut o = vec![];
+    for (i, _) in msg.match_indices('@') {
+        let name = mention_at(&msg[i + 1..]);
+        if !name.is_empty() && !o.contains(&name) {
+            o.push(name);
+        }
+    }
+    o
+}
+
+/// replaces each `@name` (like [`find_mentions`] finds them) with `replace(name)`, unless that returns `None`
+pub fn replace_mentions(msg: &str, mut replace: impl FnMut(&str) -> Option<String>) -> String {
+    let mut o = String::with_capacity(msg.len());
+    let mut rest = msg;
+    while let Some(i) = rest.find('@') {
+        o.push_str(&rest[..i]);
+        let name = mention_at(&rest[i + 1..]);
+        match replace(name).filter(|_| !name.is_empty()) {
+            Some(replacement) => {
+                o.push_str(&replacement);
+                rest = &rest[i + 1 + name.len()..];
+            }
+            None => {
+                o.push('@');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    o.push_str(rest);
+    o
+}
+
+/// the whole name at the start of `text` (which follows an `@`). A trailing `.` ends the sentence.
+pub fn mention_at(text: &str) -> &str {
+    let end = text
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or(text.len());
+    text[..end].trim_end_matches('.')
+}
+
+/// finds the discord users mentioned (`@name`) in a minecraft chat message by `author`.
+/// Names are resolved to linked accounts first, then to members of the guild.
+/// Returns the mentioned names and the user ids, or nothing if `author` is on cooldown.
+pub async fn resolve(
+    ctx: &serenity::Context,
+    mentions: &poise::futures_util::lock::Mutex<Mentions>,
+    channel_id: u64,
+    author: &str,
+    msg: &str,
+) -> Vec<(String, u64)> {
+    let names = find_mentions(msg);
+    if names.is_empty() {
+        return vec![];
+    }
+    {
+        let mentions = mentions.lock().await;
+        if mentions
+            .last_ping
+            .get(author)
+            .is_some_and(|last| last.elapsed() < mentions.cooldown)
+        {
+            return vec![];
+        }
+    }
+    let guild_id = if let Some(channel) = ctx.cache.guild_channel(channel_id) {
+        channel.guild_id
+    } else if let Ok(serenity::Channel::Guild(channel)) =
+        serenity::ChannelId(channel_id).to_channel(ctx).await
+    {
+        channel.guild_id
+    } else {
+        return vec![];
+    };
+    let mut o = vec![];
+    for name in names {
+        let linked = mentions.lock().await.linked_user(name);
+        let user = if let Some(user) = linked {
+            Some(user)
+        } else if let Ok(members) = guild_id.search_members(ctx, name, Some(10)).await {
+            let lowercase = name.to_lowercase();
+            members
+                .iter()
+                .find(|member| {
+                    member.user.name.to_lowercase() == lowercase
+                        || member
+                            .nick
+                            .as_ref()
+                            .is_some_and(|nick| nick.to_lowercase() == lowercase)
+                })
+                .map(|member| member.user.id.0)
+        } else {
+            None
+        };
+        if let Some(user) = user {
+            if !mentions.lock().await.opted_out.contains(&user) {
+                o.push((name.to_owned(), user));
+            }
+        }
+    }
+    if !o.is_empty() {
+        mentions
+            .lock()
+            .await
+            .last_ping
+            .insert(author.to_owned(), Instant::now());
+    }
+    o
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_keeps_pending_links_and_cooldowns() {
+        let path = std::env::temp_dir().join(format!("mcdcbot-mentions-{}", std::process::id()));
+        std::fs::write(&path, "link steve 1\n").unwrap();
+        let mut mentions = Mentions::load(&path, Duration::from_secs(60)).unwrap();
+        let code = mentions.start_link("Alex", 2);
+        mentions
+            .last_ping
+            .insert("Steve".to_owned(), Instant::now());
+        std::fs::write(&path, "link steve 1\noptout 3\n").unwrap();
+        mentions.reload(&path, Duration::from_secs(60)).unwrap();
+        assert!(mentions.opted_out.contains(&3));
+        assert!(mentions.last_ping.contains_key("Steve"));
+        let confirmed = mentions.confirm_link("alex", &code).map(Result::unwrap);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(confirmed, Some(2));
+        assert_eq!(mentions.linked_user("ALEX"), Some(2));
+        assert_eq!(mentions.linked_user("steve"), Some(1));
+    }
+}
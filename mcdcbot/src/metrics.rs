@@ -0,0 +1,329 @@
+use std::{convert::Infallible, fmt::Write, sync::Arc};
+
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use poise::futures_util::lock::Mutex;
+
+use crate::data::{Data, MinecraftServer};
+
+/// things which happened to a server which its thread doesn't keep track of
+#[derive(Clone, Copy, Default)]
+pub struct ServerCounters {
+    pub chat_messages: u64,
+    pub joins: u64,
+    /// the bot doesn't restart servers by itself, so every start is counted
+    pub starts: u64,
+    /// stops where the server exited with an error
+    pub crashes: u64,
+}
+
+/// serves prometheus metrics at `http://<metrics_addr>/metrics`, if `metrics_addr` is set
+pub async fn serve(data: Arc<Data>) {
+    let Some(addr) = data.settings.lock().await.metrics_addr else {
+        return;
+    };
+    let server = match Server::try_bind(&addr) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("[WARN/METRICS] Couldn't listen on {addr}: {e}");
+            return;
+        }
+    };
+    eprintln!("Serving metrics on http://{addr}/metrics");
+    let make_service = make_service_fn(move |_| {
+        let data = Arc::clone(&data);
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(Arc::clone(&data), req))) }
+    });
+    if let Err(e) = server.serve(make_service).await {
+        eprintln!("[WARN/METRICS] The metrics server stopped: {e}");
+    }
+}
+
+async fn handle(data: Arc<Data>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::from("Not found, see /metrics\n"));
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+    let body = render(&samples(&data).await, data.queue.depth());
+    let mut response = Response::new(Body::from(body));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    Ok(response)
+}
+
+/// a server's metrics at the time of the scrape
+struct ServerSample {
+    name: String,
+    counters: ServerCounters,
+    /// `None` if the server isn't running
+    running: Option<RunningSample>,
+}
+struct RunningSample {
+    uptime: Option<f64>,
+    players: usize,
+    cpu_seconds: Option<f64>,
+    cpu_percent: Option<f32>,
+    memory: Option<u64>,
+    threads: Option<u32>,
+    tps: Option<f32>,
+    mspt: Option<f32>,
+}
+
+/// the metrics of all servers
+async fn samples(data: &Data) -> Vec<ServerSample> {
+    let current = data
+        .current
+        .lock()
+        .await
+        .as_ref()
+        .map(|(server, thread)| (Arc::clone(server), Arc::clone(thread)));
+    let servers = data.servers.lock().await.clone();
+    let mut samples = vec![];
+    for server in servers {
+        let running = match &current {
+            Some((current, thread)) if Arc::ptr_eq(current, &server) => {
+                thread.lock().await.as_ref().map(|thread| {
+                    let resources = thread.resources();
+                    let tick = thread.performance().latest();
+                    RunningSample {
+                        uptime: thread.uptime().map(|uptime| uptime.as_secs_f64()),
+                        players: thread.online_players().len(),
+                        cpu_seconds: resources.map(|usage| usage.stats.cpu_time.as_secs_f64()),
+                        cpu_percent: resources.and_then(|usage| usage.cpu_percent),
+                        memory: resources.map(|usage| usage.stats.rss),
+                        threads: resources.map(|usage| usage.stats.threads),
+                        tps: tick.map(|sample| sample.tps),
+                        mspt: tick.and_then(|sample| sample.mspt),
+                    }
+                })
+            }
+            _ => None,
+        };
+        let server = server.lock().await;
+        let counters = data
+            .counters
+            .lock()
+            .await
+            .get(&server.file)
+            .copied()
+            .unwrap_or_default();
+        samples.push(ServerSample {
+            name: server.name.clone(),
+            counters,
+            running,
+        });
+    }
+    samples
+}
+
+/// all metrics in prometheus' text format
+fn render(samples: &[ServerSample], queue_depth: usize) -> String {
+    let mut out = String::new();
+    let mut family =
+        |name: &str, kind: &str, help: &str, value: &dyn Fn(&ServerSample) -> Option<f64>| {
+            _ = writeln!(out, "# HELP {name} {help}");
+            _ = writeln!(out, "# TYPE {name} {kind}");
+            for sample in samples {
+                if let Some(value) = value(sample) {
+                    _ = writeln!(
+                        out,
+                        "{name}{{server=\"{}\"}} {value}",
+                        escape_label(&sample.name)
+                    );
+                }
+            }
+        };
+    family(
+        "mcdcbot_server_up",
+        "gauge",
+        "1 if the server is running",
+        &|s| Some(if s.running.is_some() { 1.0 } else { 0.0 }),
+    );
+    family(
+        "mcdcbot_server_uptime_seconds",
+        "gauge",
+        "how long the server's process has been running",
+        &|s| s.running.as_ref()?.uptime,
+    );
+    family(
+        "mcdcbot_server_players_online",
+        "gauge",
+        "players on the server",
+        &|s| Some(s.running.as_ref()?.players as f64),
+    );
+    family(
+        "mcdcbot_server_chat_messages_total",
+        "counter",
+        "chat messages sent in minecraft",
+        &|s| Some(s.counters.chat_messages as f64),
+    );
+    family(
+        "mcdcbot_server_joins_total",
+        "counter",
+        "players who joined the server",
+        &|s| Some(s.counters.joins as f64),
+    );
+    family(
+        "mcdcbot_server_starts_total",
+        "counter",
+        "times the server was started",
+        &|s| Some(s.counters.starts as f64),
+    );
+    family(
+        "mcdcbot_server_crashes_total",
+        "counter",
+        "times the server exited with an error",
+        &|s| Some(s.counters.crashes as f64),
+    );
+    family(
+        "mcdcbot_server_cpu_seconds_total",
+        "counter",
+        "cpu time used by the server's process",
+        &|s| s.running.as_ref()?.cpu_seconds,
+    );
+    family(
+        "mcdcbot_server_cpu_percent",
+        "gauge",
+        "the server's recent cpu usage, 100 is one core",
+        &|s| s.running.as_ref()?.cpu_percent.map(f64::from),
+    );
+    family(
+        "mcdcbot_server_memory_bytes",
+        "gauge",
+        "resident memory of the server's process",
+        &|s| s.running.as_ref()?.memory.map(|memory| memory as f64),
+    );
+    family(
+        "mcdcbot_server_threads",
+        "gauge",
+        "threads of the server's process",
+        &|s| s.running.as_ref()?.threads.map(f64::from),
+    );
+    family(
+        "mcdcbot_server_tps",
+        "gauge",
+        "the server's most recent ticks per second",
+        &|s| s.running.as_ref()?.tps.map(f64::from),
+    );
+    family(
+        "mcdcbot_server_mspt",
+        "gauge",
+        "the server's most recent milliseconds per tick",
+        &|s| s.running.as_ref()?.mspt.map(f64::from),
+    );
+    _ = writeln!(
+        out,
+        "# HELP mcdcbot_discord_queue_depth messages waiting to be sent to discord"
+    );
+    _ = writeln!(out, "# TYPE mcdcbot_discord_queue_depth gauge");
+    _ = writeln!(out, "mcdcbot_discord_queue_depth {queue_depth}");
+    out
+}
+
+/// escapes `\`, `"` and newlines in a label's value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// updates the counters of `server`
+pub async fn count(
+    data: &Data,
+    server: &Mutex<MinecraftServer>,
+    update: impl FnOnce(&mut ServerCounters),
+) {
+    let file = server.lock().await.file.clone();
+    update(data.counters.lock().await.entry(file).or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_samples() {
+        let samples = [
+            ServerSample {
+                name: "Survival".to_owned(),
+                counters: ServerCounters {
+                    chat_messages: 12,
+                    joins: 3,
+                    starts: 2,
+                    crashes: 1,
+                },
+                running: Some(RunningSample {
+                    uptime: Some(90.5),
+                    players: 2,
+                    cpu_seconds: Some(12.25),
+                    cpu_percent: Some(150.0),
+                    memory: Some(1073741824),
+                    threads: Some(64),
+                    tps: Some(19.5),
+                    mspt: None,
+                }),
+            },
+            ServerSample {
+                name: "The \"Best\" \\ Server\n".to_owned(),
+                counters: ServerCounters::default(),
+                running: None,
+            },
+        ];
+        let out = render(&samples, 3);
+        let lines: Vec<_> = out.lines().collect();
+        // every family has its HELP and TYPE lines, even without values
+        for (name, kind) in [
+            ("mcdcbot_server_up", "gauge"),
+            ("mcdcbot_server_uptime_seconds", "gauge"),
+            ("mcdcbot_server_players_online", "gauge"),
+            ("mcdcbot_server_chat_messages_total", "counter"),
+            ("mcdcbot_server_joins_total", "counter"),
+            ("mcdcbot_server_starts_total", "counter"),
+            ("mcdcbot_server_crashes_total", "counter"),
+            ("mcdcbot_server_cpu_seconds_total", "counter"),
+            ("mcdcbot_server_cpu_percent", "gauge"),
+            ("mcdcbot_server_memory_bytes", "gauge"),
+            ("mcdcbot_server_threads", "gauge"),
+            ("mcdcbot_server_tps", "gauge"),
+            ("mcdcbot_server_mspt", "gauge"),
+            ("mcdcbot_discord_queue_depth", "gauge"),
+        ] {
+            let help = lines
+                .iter()
+                .position(|line| line.starts_with(&format!("# HELP {name} ")))
+                .unwrap_or_else(|| panic!("no HELP for {name}"));
+            assert_eq!(lines[help + 1], format!("# TYPE {name} {kind}"));
+        }
+        let best = r#"server="The \"Best\" \\ Server\n""#;
+        for line in [
+            r#"mcdcbot_server_up{server="Survival"} 1"#.to_owned(),
+            format!("mcdcbot_server_up{{{best}}} 0"),
+            r#"mcdcbot_server_uptime_seconds{server="Survival"} 90.5"#.to_owned(),
+            r#"mcdcbot_server_players_online{server="Survival"} 2"#.to_owned(),
+            r#"mcdcbot_server_chat_messages_total{server="Survival"} 12"#.to_owned(),
+            format!("mcdcbot_server_chat_messages_total{{{best}}} 0"),
+            r#"mcdcbot_server_starts_total{server="Survival"} 2"#.to_owned(),
+            r#"mcdcbot_server_crashes_total{server="Survival"} 1"#.to_owned(),
+            r#"mcdcbot_server_cpu_seconds_total{server="Survival"} 12.25"#.to_owned(),
+            r#"mcdcbot_server_memory_bytes{server="Survival"} 1073741824"#.to_owned(),
+            r#"mcdcbot_server_tps{server="Survival"} 19.5"#.to_owned(),
+            "mcdcbot_discord_queue_depth 3".to_owned(),
+        ] {
+            assert!(lines.contains(&line.as_str()), "{line} in\n{out}");
+        }
+        // stopped servers and missing values have no samples
+        assert!(!out.contains(&format!("mcdcbot_server_uptime_seconds{{{best}}}")));
+        assert!(!out.contains("mcdcbot_server_mspt{"));
+        // the newline in the name is escaped, so every line is a comment or a sample
+        assert!(lines
+            .iter()
+            .all(|line| line.starts_with('#') || line.starts_with("mcdcbot_")));
+    }
+}
@@ -0,0 +1,29 @@
+use std::{fs::File, io, path::Path};
+
+use minecraft_manager::jars::{DirectoryMirror, JarMirror};
+
+/// a mirror which downloads jars from a url, `{name}` is replaced with the jar's name
+pub struct UrlMirror(pub String);
+
+impl JarMirror for UrlMirror {
+    fn fetch(&self, name: &str, to: &Path) -> io::Result<()> {
+        let url = self.0.replace("{name}", name);
+        let mut response = reqwest::blocking::get(&url)
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        response
+            .copy_to(&mut File::create(to)?)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// the mirror set with `jar_mirror=`: a url (containing `{name}`) or a directory
+pub fn from_setting(jar_mirror: Option<&str>) -> Option<Box<dyn JarMirror>> {
+    let jar_mirror = jar_mirror?;
+    if jar_mirror.starts_with("http://") || jar_mirror.starts_with("https://") {
+        Some(Box::new(UrlMirror(jar_mirror.to_owned())))
+    } else {
+        Some(Box::new(DirectoryMirror(jar_mirror.into())))
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use minecraft_manager::{
+    properties::{self, ServerProperties, SECRET_PROPERTIES},
+    tasks::MinecraftServerTask,
+};
+use poise::futures_util::lock::Mutex;
+
+use crate::{
+    autocomplete_server, data::MinecraftServer, find_server, in_info_channel, server_not_found,
+    Context, Error,
+};
+
+/// discord messages can only be 2000 characters long
+const MAX_LIST_LEN: usize = 1900;
+
+/// View or change a server's server.properties
+#[poise::command(slash_command, subcommands("get_property", "set_property"))]
+pub async fn properties(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show one or all properties of a server
+#[poise::command(slash_command, rename = "get")]
+async fn get_property(
+    ctx: Context<'_>,
+    #[description = "Property (i.e. difficulty), or nothing to show all"] key: Option<String>,
+    #[description = "Server's name (default: the running server)"]
+    #[autocomplete = "autocomplete_server"]
+    srv: Option<String>,
+) -> Result<(), Error> {
+    if !in_info_channel(ctx).await? {
+        return Ok(());
+    }
+    let Some((server, _)) = target_server(ctx, srv).await? else {
+        return Ok(());
+    };
+    let server_properties = ServerProperties::load(server.lock().await.settings.properties_path())?;
+    if let Some(key) = key {
+        if let Some(value) = server_properties.get(&key) {
+            ctx.say(format!("`{key}` = `{}`", shown_value(&key, value)))
+                .await?;
+        } else {
+            ctx.say(format!("`{key}` is not set.")).await?;
+        }
+    } else {
+        let mut list = String::new();
+        for (key, value) in server_properties.iter() {
+            let line = format!("{key}={}\n", shown_value(key, value));
+            if list.len() + line.len() > MAX_LIST_LEN {
+                list.push_str("...\n");
+                break;
+            }
+            list.push_str(&line);
+        }
+        ctx.say(format!("```properties\n{list}```")).await?;
+    }
+    Ok(())
+}
+
+/// Change a property of a server
+#[poise::command(slash_command, rename = "set")]
+async fn set_property(
+    ctx: Context<'_>,
+    #[description = "Property (i.e. difficulty)"] key: String,
+    #[description = "New value"] value: String,
+    #[description = "Server's name (default: the running server)"]
+    #[autocomplete = "autocomplete_server"]
+    srv: Option<String>,
+) -> Result<(), Error> {
+    if !in_info_channel(ctx).await? {
+        return Ok(());
+    }
+    let Some((server, running)) = target_server(ctx, srv).await? else {
+        return Ok(());
+    };
+    let path = server.lock().await.settings.properties_path();
+    let mut server_properties = ServerProperties::load(&path)?;
+    if let Err(e) = server_properties.set(&key, &value) {
+        ctx.say(e.to_string()).await?;
+        return Ok(());
+    }
+    server_properties.save(&path)?;
+    let shown = shown_value(&key, &value);
+    if !running {
+        ctx.say(format!("Set `{key}` to `{shown}`.")).await?;
+    } else if let Some(command) = properties::live_command(&key, &value) {
+        if let Some((_, thread)) = ctx.data().current.lock().await.as_ref() {
+            _ = thread
+                .lock()
+                .await
+                .as_ref()
+                .unwrap()
+                .clone_task_sender()
+                .send_task(MinecraftServerTask::RunCommand(command));
+        }
+        ctx.say(format!(
+            "Set `{key}` to `{shown}` and applied it to the running server."
+        ))
+        .await?;
+    } else {
+        ctx.say(format!(
+            "Set `{key}` to `{shown}`. Restart the server for this change to take effect."
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+/// the value as shown on discord, secrets are hidden
+fn shown_value<'a>(key: &str, value: &'a str) -> &'a str {
+    if SECRET_PROPERTIES.contains(&key) {
+        "(hidden)"
+    } else {
+        value
+    }
+}
+
+/// the server with the given name, or the running server.
+/// Replies with an error if there is no such server. The bool is true if the server is running.
+async fn target_server(
+    ctx: Context<'_>,
+    srv: Option<String>,
+) -> Result<Option<(Arc<Mutex<MinecraftServer>>, bool)>, Error> {
+    let current = ctx
+        .data()
+        .current
+        .lock()
+        .await
+        .as_ref()
+        .map(|(server, _)| Arc::clone(server));
+    if let Some(srv) = srv {
+        if let Some(server) = find_server(ctx.data(), &srv).await {
+            let running = current.is_some_and(|current| Arc::ptr_eq(&current, &server));
+            return Ok(Some((server, running)));
+        }
+        server_not_found(ctx, &srv).await?;
+    } else if let Some(current) = current {
+        return Ok(Some((current, true)));
+    } else {
+        ctx.say("No server is running, specify a server (see /list)")
+            .await?;
+    }
+    Ok(None)
+}
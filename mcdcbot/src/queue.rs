@@ -0,0 +1,331 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use poise::serenity_prelude::{self as serenity, json::Value, Http, HttpError};
+use tokio::sync::mpsc;
+
+use crate::{embed, webhook::ChatWebhook};
+
+/// how many messages can wait in a channel's queue before the oldest ones are dropped
+const MAX_QUEUED: usize = 500;
+/// how often sending a message is retried (on server or connection errors)
+const MAX_RETRIES: u32 = 5;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// discord allows up to 10 embeds per message
+const MAX_EMBEDS: usize = 10;
+/// discord allows up to 2000 characters per message
+const MAX_CONTENT_LEN: usize = 2000;
+
+pub enum QueuedMessage {
+    /// a normal bot message (`content`, `embeds`, `allowed_mentions`)
+    Message(Value),
+    /// a message sent through a webhook (see [`embed::chat_message_webhook`])
+    Webhook(ChatWebhook, Value),
+    /// players joining/leaving are combined into one message ("3 players joined: ...")
+    JoinLeave { username: String, joined: bool },
+}
+
+/// Sends messages to discord, one queue per channel.
+/// Messages which pile up (i.e. while rate limited) are combined into fewer messages,
+/// the order of messages in a channel is kept.
+#[derive(Clone)]
+pub struct DiscordQueue {
+    http: Arc<Http>,
+    channels: Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<QueuedMessage>>>>,
+    /// how many messages are waiting to be sent, in all channels
+    depth: Arc<AtomicUsize>,
+}
+
+impl DiscordQueue {
+    pub fn new(http: Arc<Http>) -> Self {
+        Self {
+            http,
+            channels: Arc::new(Mutex::new(HashMap::new())),
+            depth: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn push(&self, channel_id: u64, msg: QueuedMessage) {
+        let mut channels = self.channels.lock().unwrap();
+        let sender = channels.entry(channel_id).or_insert_with(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            tokio::task::spawn(run(
+                Arc::clone(&self.http),
+                channel_id,
+                receiver,
+                Arc::clone(&self.depth),
+            ));
+            sender
+        });
+        if sender.send(msg).is_ok() {
+            self.depth.fetch_add(1, Ordering::Relaxed);
+        } else {
+            eprintln!("[WARN/DISCORD-QUEUE] Queue for channel {channel_id} was closed, dropped a message.");
+        }
+    }
+
+    /// how many messages are waiting to be sent, in all channels
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+async fn run(
+    http: Arc<Http>,
+    channel_id: u64,
+    mut receiver: mpsc::UnboundedReceiver<QueuedMessage>,
+    depth: Arc<AtomicUsize>,
+) {
+    let mut pending = VecDeque::new();
+    while let Some(msg) = receiver.recv().await {
+        pending.push_back(msg);
+        // everything that was queued while we were sending the previous batch
+        while let Ok(msg) = receiver.try_recv() {
+            pending.push_back(msg);
+        }
+        if pending.len() > MAX_QUEUED {
+            let dropped = pending.len() - MAX_QUEUED;
+            pending.drain(..dropped);
+            depth.fetch_sub(dropped, Ordering::Relaxed);
+            eprintln!("[WARN/DISCORD-QUEUE] Too many queued messages for channel {channel_id}, dropped {dropped} messages.");
+        }
+        while let Some((batch, count)) = next_batch(&mut pending) {
+            if let Err(e) = send(&http, channel_id, &batch).await {
+                eprintln!(
+                    "[WARN/DISCORD-QUEUE] Dropped {count} message(s) for channel {channel_id}: {e}"
+                );
+            }
+            depth.fetch_sub(count, Ordering::Relaxed);
+        }
+    }
+}
+
+enum Batch {
+    Message(Value),
+    Webhook(ChatWebhook, Value),
+}
+
+/// combines as many messages from the start of `pending` as possible into one message.
+/// returns the message and how many queued messages it contains.
+fn next_batch(pending: &mut VecDeque<QueuedMessage>) -> Option<(Batch, usize)> {
+    let first = pending.pop_front()?;
+    let mut count = 1;
+    Some(match first {
+        QueuedMessage::JoinLeave { username, joined } => {
+            let mut usernames = vec![username];
+            while let Some(QueuedMessage::JoinLeave {
+                joined: next_joined,
+                ..
+            }) = pending.front()
+            {
+                if *next_joined != joined {
+                    break;
+                }
+                if let Some(QueuedMessage::JoinLeave { username, .. }) = pending.pop_front() {
+                    usernames.push(username);
+                    count += 1;
+                }
+            }
+            (Batch::Message(embed::join_leave(&usernames, joined)), count)
+        }
+        QueuedMessage::Message(mut msg) => {
+            while let Some(QueuedMessage::Message(next)) = pending.front() {
+                if !merge_messages(&mut msg, next) {
+                    break;
+                }
+                pending.pop_front();
+                count += 1;
+            }
+            (Batch::Message(msg), count)
+        }
+        QueuedMessage::Webhook(webhook, mut msg) => {
+            while let Some(QueuedMessage::Webhook(next_webhook, next)) = pending.front() {
+                if next_webhook.id != webhook.id
+                    || next["username"] != msg["username"]
+                    || !merge_messages(&mut msg, next)
+                {
+                    break;
+                }
+                pending.pop_front();
+                count += 1;
+            }
+            (Batch::Webhook(webhook, msg), count)
+        }
+    })
+}
+
+/// appends `next`'s content and embeds to `msg`, if the result isn't too large for one message
+fn merge_messages(msg: &mut Value, next: &Value) -> bool {
+    let get_content = |v: &Value| v["content"].as_str().unwrap_or_default().to_owned();
+    let get_embeds = |v: &Value| v["embeds"].as_array().cloned().unwrap_or_default();
+    let (content1, content2) = (get_content(msg), get_content(next));
+    let (mut embeds1, embeds2) = (get_embeds(msg), get_embeds(next));
+    // content is shown above embeds, so merging content after embeds would change the order
+    if !content2.is_empty() && !embeds1.is_empty() {
+        return false;
+    }
+    let content = match (content1.is_empty(), content2.is_empty()) {
+        (true, _) => content2,
+        (false, true) => content1,
+        (false, false) => format!("{content1}\n{content2}"),
+    };
+    if content.chars().count() > MAX_CONTENT_LEN || embeds1.len() + embeds2.len() > MAX_EMBEDS {
+        return false;
+    }
+    embeds1.extend(embeds2);
+    let mut users = msg["allowed_mentions"]["users"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    for user in next["allowed_mentions"]["users"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        if !users.contains(user) {
+            users.push(user.clone());
+        }
+    }
+    if !content.is_empty() {
+        msg["content"] = Value::String(content);
+    }
+    if !embeds1.is_empty() {
+        msg["embeds"] = Value::Array(embeds1);
+    }
+    if !users.is_empty() || msg.get("allowed_mentions").is_some() {
+        msg["allowed_mentions"] = serenity::json::json!({ "parse": [], "users": users });
+    }
+    true
+}
+
+async fn send(http: &Http, channel_id: u64, batch: &Batch) -> serenity::Result<()> {
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut retries = 0;
+    loop {
+        let result = match batch {
+            Batch::Message(msg) => http.send_message(channel_id, msg).await.map(|_| ()),
+            Batch::Webhook(webhook, msg) => webhook.execute(http, msg).await,
+        };
+        match result {
+            Err(serenity::Error::Http(e)) if retries < MAX_RETRIES && should_retry(&e) => {
+                eprintln!("[WARN/DISCORD-QUEUE] Sending to channel {channel_id} failed ({e}), retrying in {delay:?}.");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// discord's server errors (5xx) and failed connections are temporary.
+/// Rate limits (429) aren't retried here, serenity already waits until the rate limit is over.
+fn should_retry(e: &HttpError) -> bool {
+    match e {
+        HttpError::Request(_) => true,
+        e => e
+            .status_code()
+            .is_some_and(|status| status.is_server_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::json::json;
+
+    use super::*;
+
+    #[test]
+    fn contents_are_joined() {
+        let mut msg = json!({ "content": "hello" });
+        assert!(merge_messages(&mut msg, &json!({ "content": "world" })));
+        assert_eq!(msg, json!({ "content": "hello\nworld" }));
+        assert!(merge_messages(&mut msg, &json!({ "content": "" })));
+        assert_eq!(msg, json!({ "content": "hello\nworld" }));
+        let mut msg = json!({ "content": "" });
+        assert!(merge_messages(&mut msg, &json!({ "content": "first" })));
+        assert_eq!(msg, json!({ "content": "first" }));
+    }
+
+    #[test]
+    fn embeds_are_appended() {
+        let mut msg = json!({ "content": "started", "embeds": [{ "title": "1" }] });
+        assert!(merge_messages(
+            &mut msg,
+            &json!({ "embeds": [{ "title": "2" }, { "title": "3" }] })
+        ));
+        assert_eq!(
+            msg,
+            json!({ "content": "started", "embeds": [{ "title": "1" }, { "title": "2" }, { "title": "3" }] })
+        );
+    }
+
+    #[test]
+    fn content_after_embeds_is_not_merged() {
+        let mut msg = json!({ "embeds": [{ "title": "1" }] });
+        assert!(!merge_messages(&mut msg, &json!({ "content": "text" })));
+        assert_eq!(msg, json!({ "embeds": [{ "title": "1" }] }));
+        // embeds after content are fine, content is shown above them anyway
+        let mut msg = json!({ "content": "text" });
+        assert!(merge_messages(
+            &mut msg,
+            &json!({ "embeds": [{ "title": "1" }] })
+        ));
+        assert_eq!(
+            msg,
+            json!({ "content": "text", "embeds": [{ "title": "1" }] })
+        );
+    }
+
+    #[test]
+    fn too_large_messages_are_not_merged() {
+        let long = "é".repeat(MAX_CONTENT_LEN - 2);
+        let mut msg = json!({ "content": long });
+        // 2000 characters (not bytes) with the newline
+        assert!(merge_messages(&mut msg, &json!({ "content": "a" })));
+        assert!(!merge_messages(&mut msg, &json!({ "content": "b" })));
+        assert_eq!(
+            msg["content"].as_str().unwrap().chars().count(),
+            MAX_CONTENT_LEN
+        );
+
+        let embeds = |n: usize| json!({ "embeds": vec![json!({ "title": "x" }); n] });
+        let mut msg = embeds(4);
+        assert!(merge_messages(&mut msg, &embeds(6)));
+        assert!(!merge_messages(&mut msg, &embeds(1)));
+        assert_eq!(msg["embeds"].as_array().unwrap().len(), MAX_EMBEDS);
+    }
+
+    #[test]
+    fn allowed_mentions_are_combined() {
+        let mut msg =
+            json!({ "content": "<@1> hi", "allowed_mentions": { "parse": [], "users": ["1"] } });
+        assert!(merge_messages(
+            &mut msg,
+            &json!({ "content": "<@1> <@2> hey", "allowed_mentions": { "parse": [], "users": ["1", "2"] } })
+        ));
+        assert_eq!(
+            msg,
+            json!({ "content": "<@1> hi\n<@1> <@2> hey", "allowed_mentions": { "parse": [], "users": ["1", "2"] } })
+        );
+        // `allowed_mentions` is kept, so merged messages can't ping anyone they couldn't before
+        let mut msg = json!({ "content": "@everyone", "allowed_mentions": { "parse": [] } });
+        assert!(merge_messages(&mut msg, &json!({ "content": "text" })));
+        assert_eq!(msg["allowed_mentions"], json!({ "parse": [], "users": [] }));
+    }
+
+    #[test]
+    fn only_temporary_errors_are_retried() {
+        assert!(!should_retry(&HttpError::InvalidWebhook));
+        assert!(!should_retry(&HttpError::ApplicationIdMissing));
+        // nothing listens on port 1
+        let e = reqwest::blocking::get("http://127.0.0.1:1/").unwrap_err();
+        assert!(should_retry(&HttpError::Request(e)));
+    }
+}
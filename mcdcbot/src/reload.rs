@@ -0,0 +1,181 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use minecraft_manager::config::{load_servers, LoadedServers};
+use poise::futures_util::lock::Mutex;
+
+use crate::{
+    data::{Data, MinecraftServer},
+    embed, in_info_channel,
+    queue::QueuedMessage,
+    settings::Settings,
+    Context, Error,
+};
+
+/// how often the watcher checks if the config files changed
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reload settings.txt and the servers directory
+#[poise::command(slash_command)]
+pub async fn reload(ctx: Context<'_>) -> Result<(), Error> {
+    if !in_info_channel(ctx).await? {
+        return Ok(());
+    }
+    let report = reload_config(ctx.data()).await;
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title("Reloaded config files")
+                .description(report.join("\n"))
+        })
+    })
+    .await?;
+    Ok(())
+}
+
+/// re-reads the settings and the servers directory.
+/// Changes to idle servers are applied immediately, changes to the running server on its next start.
+/// Returns what changed (and what couldn't be loaded), one line each.
+pub async fn reload_config(data: &Data) -> Vec<String> {
+    let mut report = vec![];
+    match Settings::from_file(&data.settings_file) {
+        Ok((settings, warnings)) => {
+            report.extend(warnings.iter().map(|w| format!("Warning: {w}")));
+            if let Err(e) = data.mentions.lock().await.reload(
+                &settings.mentions_file,
+                Duration::from_secs(settings.mention_cooldown_secs),
+            ) {
+                report.push(format!("Couldn't load `{}`: {e}", settings.mentions_file));
+            }
+            *data.settings.lock().await = settings;
+            report.push("Reloaded the settings.".to_owned());
+        }
+        Err(e) => report.push(format!(
+            "Couldn't load the settings, keeping the old ones: {e}"
+        )),
+    }
+    let LoadedServers {
+        servers: loaded,
+        warnings,
+        errors,
+    } = match load_servers(&data.servers_dir) {
+        Ok(v) => v,
+        Err(e) => {
+            report.push(format!(
+                "Couldn't read the servers directory, keeping the old servers: {e}"
+            ));
+            return report;
+        }
+    };
+    let running = data
+        .current
+        .lock()
+        .await
+        .as_ref()
+        .map(|(server, _)| Arc::clone(server));
+    let is_running = |server: &Arc<Mutex<MinecraftServer>>| {
+        running
+            .as_ref()
+            .is_some_and(|running| Arc::ptr_eq(running, server))
+    };
+    let mut servers = data.servers.lock().await;
+    // removed servers (unless they are running)
+    let mut kept = vec![];
+    for server in servers.drain(..) {
+        let (file, name) = {
+            let server = server.lock().await;
+            (server.file.clone(), server.name.clone())
+        };
+        if loaded.iter().any(|loaded| loaded.file == file) {
+            kept.push(server);
+        } else if is_running(&server) {
+            report.push(format!(
+                "'{name}' was removed, but is still running. Reload again after stopping it."
+            ));
+            kept.push(server);
+        } else {
+            report.push(format!("Removed '{name}'."));
+        }
+    }
+    *servers = kept;
+    // changed and new servers
+    'loaded: for loaded in loaded {
+        for server in servers.iter() {
+            let mut server_lock = server.lock().await;
+            if server_lock.file != loaded.file {
+                continue;
+            }
+            let name = loaded.name.clone();
+            let renamed = server_lock.name != loaded.name
+                || server_lock.aliases != loaded.aliases
+                || server_lock.description != loaded.description;
+            server_lock.name = loaded.name;
+            server_lock.aliases = loaded.aliases;
+            server_lock.description = loaded.description;
+            let changed = server_lock
+                .pending_settings
+                .as_ref()
+                .unwrap_or(&server_lock.settings)
+                .to_lines()
+                != loaded.settings.to_lines();
+            if changed {
+                if is_running(server) {
+                    report.push(format!(
+                        "Updated '{name}', the changes will be applied when it is started again."
+                    ));
+                    server_lock.pending_settings = Some(loaded.settings);
+                } else {
+                    report.push(format!("Updated '{name}'."));
+                    server_lock.settings = loaded.settings;
+                }
+            } else if renamed {
+                report.push(format!("Updated '{name}'."));
+            }
+            continue 'loaded;
+        }
+        report.push(format!("Added '{}'.", loaded.name));
+        servers.push(Arc::new(Mutex::new(MinecraftServer::from(loaded))));
+    }
+    report.extend(warnings.iter().map(|w| format!("Warning: {w}")));
+    report.extend(errors.iter().map(|e| format!("Couldn't load server: {e}")));
+    report
+}
+
+/// reloads the config files whenever they change (if `watch_config_files` is enabled)
+/// and sends what changed to the info channel
+pub async fn watch(data: Arc<Data>) {
+    let mut last = fingerprint(&data.settings_file, &data.servers_dir);
+    loop {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+        let now = fingerprint(&data.settings_file, &data.servers_dir);
+        if now == last {
+            continue;
+        }
+        last = now;
+        if !data.settings.lock().await.watch_config_files {
+            continue;
+        }
+        let report = reload_config(&data).await;
+        let channel_id = data.settings.lock().await.channel_id_info;
+        data.queue.push(
+            channel_id,
+            QueuedMessage::Message(embed::config_reloaded(&report)),
+        );
+    }
+}
+
+/// the modification times of the settings file and every file in the servers directory
+fn fingerprint(settings_file: &Path, servers_dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+    let mut o = vec![(settings_file.to_owned(), modified(settings_file))];
+    if let Ok(files) = std::fs::read_dir(servers_dir) {
+        for file in files.flatten() {
+            let path = file.path();
+            o.push((path.clone(), modified(&path)));
+        }
+    }
+    o.sort();
+    o
+}
@@ -0,0 +1,366 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use minecraft_manager::{
+    backup, config,
+    create::{self, ServerSource},
+    jars::{self, JarCache},
+    MinecraftServerSettings, MinecraftServerType,
+};
+use poise::futures_util::lock::Mutex;
+
+use crate::{
+    autocomplete_server,
+    backup::{backup_server, is_running, server_or_current},
+    confirm,
+    data::MinecraftServer,
+    find_server, in_info_channel, mirror, server_not_found, Context, Error,
+};
+
+/// how long the user has to accept the EULA
+const EULA_TIMEOUT: Duration = Duration::from_secs(300);
+/// what the server jar is called in a new server's directory
+const EXECUTABLE: &str = "server.jar";
+
+#[derive(poise::ChoiceParameter)]
+pub enum ServerType {
+    #[name = "vanilla-mojang"]
+    VanillaMojang,
+    #[name = "vanilla-papermc"]
+    VanillaPaperMC,
+    #[name = "spigot"]
+    Spigot,
+    #[name = "purpur"]
+    Purpur,
+    #[name = "fabric"]
+    Fabric,
+    #[name = "forge"]
+    Forge,
+    #[name = "neoforge"]
+    NeoForge,
+    #[name = "velocity"]
+    Velocity,
+    #[name = "bungeecord"]
+    BungeeCord,
+}
+
+/// Manage servers
+#[poise::command(slash_command, subcommands("create", "version", "set_jar"))]
+pub async fn server(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Create a new server from a template or a cached jar
+#[poise::command(slash_command)]
+async fn create(
+    ctx: Context<'_>,
+    #[description = "The new server's name"] name: String,
+    #[description = "Server type"] server_type: ServerType,
+    #[description = "Minecraft version (i.e. 1.20.2)"] version: String,
+    #[description = "RAM in MB (default: 1024)"] ram: Option<u32>,
+) -> Result<(), Error> {
+    if !in_info_channel(ctx).await? {
+        return Ok(());
+    }
+    let name = name.trim().to_owned();
+    // the name is used for the config file and the server's directory
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        ctx.say("That name can't be used for a server.").await?;
+        return Ok(());
+    }
+    if !version
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        ctx.say("That's not a valid version.").await?;
+        return Ok(());
+    }
+    if find_server(ctx.data(), &name).await.is_some() {
+        ctx.say("A server with that name or alias already exists!")
+            .await?;
+        return Ok(());
+    }
+    if ctx.data().servers_dir.join(&name).exists() {
+        ctx.say("A config file with that name already exists in the servers directory!")
+            .await?;
+        return Ok(());
+    }
+    let (new_servers_dir, source) = {
+        let settings = ctx.data().settings.lock().await;
+        let Some(new_servers_dir) = settings.new_servers_dir.clone() else {
+            ctx.say("Set `new_servers_dir` in the bot's settings to create servers.")
+                .await?;
+            return Ok(());
+        };
+        let source = ServerSource::find(
+            &format!("{server_type}-{version}"),
+            settings.server_templates_dir.as_deref().map(Path::new),
+            settings.jar_cache_dir.as_deref().map(Path::new),
+        );
+        (new_servers_dir, source)
+    };
+    let Some(source) = source else {
+        ctx.say(format!(
+            "There is no template or cached jar for {server_type} {version}."
+        ))
+        .await?;
+        return Ok(());
+    };
+
+    // the EULA has to be accepted by someone, not by the bot
+    let (reply, accepted) = confirm(
+        ctx,
+        format!(
+            "To create '{name}', you have to accept the Minecraft EULA (https://aka.ms/MinecraftEULA)."
+        ),
+        "I accept the EULA",
+        EULA_TIMEOUT,
+    )
+    .await?;
+    let result = match accepted {
+        Some(true) => {
+            create_server(
+                ctx,
+                name,
+                server_type,
+                &version,
+                &new_servers_dir,
+                &source,
+                ram,
+            )
+            .await
+        }
+        Some(false) => "Cancelled.".to_owned(),
+        None => "Cancelled (timed out).".to_owned(),
+    };
+    reply
+        .edit(ctx, |m| m.content(result).components(|c| c))
+        .await?;
+    Ok(())
+}
+
+/// sets up the directory and config file and adds the server to the list.
+/// Returns the message for the user.
+async fn create_server(
+    ctx: Context<'_>,
+    name: String,
+    server_type: ServerType,
+    version: &str,
+    new_servers_dir: &str,
+    source: &ServerSource,
+    ram: Option<u32>,
+) -> String {
+    let directory = Path::new(new_servers_dir).join(&name);
+    let mut settings = MinecraftServerSettings::new(
+        match server_type {
+            ServerType::VanillaMojang => MinecraftServerType::VanillaMojang,
+            ServerType::VanillaPaperMC => MinecraftServerType::VanillaPaperMC,
+            ServerType::Spigot => MinecraftServerType::Spigot,
+            ServerType::Purpur => MinecraftServerType::Purpur,
+            ServerType::Fabric => MinecraftServerType::Fabric,
+            ServerType::Forge => MinecraftServerType::Forge,
+            ServerType::NeoForge => MinecraftServerType::NeoForge,
+            ServerType::Velocity => MinecraftServerType::Velocity,
+            ServerType::BungeeCord => MinecraftServerType::BungeeCord,
+        },
+        directory.to_string_lossy().into_owned(),
+        EXECUTABLE.to_owned(),
+    );
+    if let Some(ram) = ram {
+        settings = settings.with_ram(ram);
+    }
+    settings = settings.with_minecraft_version(Some(version.to_owned()));
+    if let Err(e) = create::create_server_dir(&directory, source, EXECUTABLE)
+        .and_then(|()| create::accept_eula(&directory))
+    {
+        return format!("Couldn't create the server's directory: {e}");
+    }
+    // locked before the config file exists, so a reload can't add the server a second time
+    let mut servers = ctx.data().servers.lock().await;
+    if let Err(e) = std::fs::write(ctx.data().servers_dir.join(&name), settings.to_lines()) {
+        return format!("Created the server's directory, but couldn't save its config: {e}");
+    }
+    let mut taken = HashSet::new();
+    for server in servers.iter() {
+        taken.extend(server.lock().await.names().map(str::to_lowercase));
+    }
+    let aliases = Vec::from_iter(config::default_alias(&name, &taken));
+    let msg = format!(
+        "Created '{name}'. Start it with `/start {}`.",
+        aliases.first().unwrap_or(&name)
+    );
+    servers.push(Arc::new(Mutex::new(MinecraftServer {
+        file: name.clone(),
+        name,
+        aliases,
+        description: None,
+        settings,
+        pending_settings: None,
+        version: None,
+    })));
+    msg
+}
+
+/// Show a server's jar and minecraft version
+#[poise::command(slash_command)]
+async fn version(
+    ctx: Context<'_>,
+    #[description = "Server's name (default: the running server)"]
+    #[autocomplete = "autocomplete_server"]
+    srv: Option<String>,
+) -> Result<(), Error> {
+    let Some(server) = server_or_current(ctx, srv).await? else {
+        return Ok(());
+    };
+    let running = is_running(ctx.data(), &server).await;
+    let (name, jar, version) = {
+        let server = server.lock().await;
+        (
+            server.name.clone(),
+            Path::new(&server.settings.directory).join(&server.settings.executable),
+            server.version.clone(),
+        )
+    };
+    let jar_cache_dir = ctx.data().settings.lock().await.jar_cache_dir.clone();
+    let mut msg = format!("'{name}' runs `{}`", jar.display());
+    if let Some(jar_cache_dir) = jar_cache_dir {
+        let identified = {
+            let jar = jar.clone();
+            tokio::task::spawn_blocking(move || JarCache::new(jar_cache_dir).identify(&jar)).await?
+        };
+        if let Ok(Some(cached)) = identified {
+            msg.push_str(&format!(" (`{cached}` from the jar cache)"));
+        }
+    }
+    msg.push_str(&match version {
+        Some(version) if running => format!("\nMinecraft version: {version}"),
+        Some(version) => format!("\nMinecraft version: {version} (when it was last started)"),
+        None => "\nMinecraft version: unknown (start the server to find out)".to_owned(),
+    });
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Switch a server to another jar from the jar cache (the worlds are backed up first)
+#[poise::command(slash_command, rename = "set-jar")]
+async fn set_jar(
+    ctx: Context<'_>,
+    #[description = "Server's name (see /list)"]
+    #[autocomplete = "autocomplete_server"]
+    srv: String,
+    #[description = "The jar, <type>-<version> (i.e. vanilla-papermc-1.20.4)"]
+    #[autocomplete = "autocomplete_jar"]
+    jar: String,
+) -> Result<(), Error> {
+    if !in_info_channel(ctx).await? {
+        return Ok(());
+    }
+    let Some(server) = find_server(ctx.data(), &srv).await else {
+        server_not_found(ctx, &srv).await?;
+        return Ok(());
+    };
+    let (name, server_dir, executable) = {
+        let server = server.lock().await;
+        (
+            server.name.clone(),
+            PathBuf::from(&server.settings.directory),
+            server.settings.executable.clone(),
+        )
+    };
+    if is_running(ctx.data(), &server).await {
+        ctx.say(format!("Stop '{name}' before changing its jar."))
+            .await?;
+        return Ok(());
+    }
+    let (jar_cache_dir, mirror) = {
+        let settings = ctx.data().settings.lock().await;
+        (
+            settings.jar_cache_dir.clone(),
+            mirror::from_setting(settings.jar_mirror.as_deref()),
+        )
+    };
+    let Some(jar_cache_dir) = jar_cache_dir else {
+        ctx.say("Set `jar_cache_dir` in the bot's settings to change jars.")
+            .await?;
+        return Ok(());
+    };
+    // downloading and backing up can take a while
+    ctx.defer().await?;
+    let jar = jar.trim().to_owned();
+    let cached = tokio::task::spawn_blocking({
+        let jar = jar.clone();
+        move || JarCache::new(jar_cache_dir).get(&jar, mirror.as_deref())
+    })
+    .await?;
+    let cached = match cached {
+        Ok(cached) => cached,
+        Err(e) => {
+            ctx.say(format!("Couldn't get the jar: {e}")).await?;
+            return Ok(());
+        }
+    };
+    // new servers don't have worlds yet
+    let backup = if backup::world_dirs(&server_dir).is_ok_and(|worlds| !worlds.is_empty()) {
+        match backup_server(ctx.data(), &server).await {
+            Ok(report) => format!(" Backup: {report}."),
+            Err(e) => {
+                ctx.say(format!(
+                    "Couldn't back up '{name}', the jar wasn't changed: {e}"
+                ))
+                .await?;
+                return Ok(());
+            }
+        }
+    } else {
+        String::new()
+    };
+    // the server could have been started during the backup
+    if is_running(ctx.data(), &server).await {
+        ctx.say(format!("'{name}' was started, the jar wasn't changed."))
+            .await?;
+        return Ok(());
+    }
+    match jars::install(&cached, &server_dir, &executable) {
+        Ok(()) => {
+            // what the server logged before is outdated, and the version is used to pick a java
+            server.lock().await.version = jar_version(&jar);
+            ctx.say(format!(
+                "'{name}' now uses `{}`, the previous jar was kept as `{executable}.previous`.{backup}",
+                cached.display()
+            ))
+            .await?
+        }
+        Err(e) => ctx.say(format!("Couldn't install the jar: {e}")).await?,
+    };
+    Ok(())
+}
+
+/// the minecraft version from a jar's name in the jar cache (`<type>-<version>`)
+fn jar_version(jar: &str) -> Option<String> {
+    MinecraftServerType::BUILT_IN
+        .iter()
+        .filter(|server_type| !server_type.is_proxy())
+        .find_map(|server_type| {
+            jar.strip_prefix(server_type.config_name())?
+                .strip_prefix('-')
+        })
+        .map(str::to_owned)
+}
+
+/// suggests the jars in the jar cache
+async fn autocomplete_jar(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(jar_cache_dir) = ctx.data().settings.lock().await.jar_cache_dir.clone() else {
+        return vec![];
+    };
+    JarCache::new(jar_cache_dir)
+        .list()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| name.contains(partial.trim()))
+        .take(25)
+        .collect()
+}
@@ -1,51 +1,416 @@
-use std::path::Path;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use minecraft_manager::{
+    backup::Retention,
+    config::{self, ConfigIssue, Loaded},
+};
+use serde::Deserialize;
+
+use crate::webhook::ChatWebhook;
 
 #[derive(Clone)]
 pub struct Settings {
     pub channel_id_info: u64,
     pub channel_id_chat: u64,
     pub send_join_and_leave_messages: bool,
+    /// relay death messages to the chat channel (default: `send_join_and_leave_messages`)
+    pub send_death_messages: bool,
     pub send_start_stop_messages_in_chat: bool,
+    /// show edited discord messages in minecraft again
+    pub send_discord_edits_in_minecraft: bool,
+    /// announce in minecraft when a discord message which was shown there is deleted
+    pub send_discord_deletions_in_minecraft: bool,
     pub get_my_ip_url1: String,
     pub get_my_ip_url2: String,
+    /// if set, minecraft chat messages are sent through this webhook
+    /// (with the player's name and head) instead of as embeds from the bot.
+    pub chat_webhook: Option<ChatWebhook>,
+    /// the avatar url used for chat messages sent through the webhook.
+    /// `{name}` is replaced with the player's name.
+    pub skin_render_url: String,
+    /// where links between minecraft and discord accounts (and mention opt-outs) are saved
+    pub mentions_file: String,
+    /// how long a player has to wait before they can ping someone on discord again
+    pub mention_cooldown_secs: u64,
+    /// `/server create` creates the directories for new servers in here
+    pub new_servers_dir: Option<String>,
+    /// directories named `<type>-<version>` which are copied when creating a new server
+    pub server_templates_dir: Option<String>,
+    /// server jars named `<type>-<version>.jar`, used if there is no template for a new server
+    pub jar_cache_dir: Option<String>,
+    /// where jars which aren't in the jar cache are downloaded from:
+    /// a url containing `{name}` (i.e. `vanilla-papermc-1.20.4`) or a directory with `<name>.jar` files
+    pub jar_mirror: Option<String>,
+    /// java homes or executables to choose from (in addition to `JAVA_HOME` and `/usr/lib/jvm/*`)
+    pub java_paths: Vec<PathBuf>,
+    /// reload the settings file and the servers directory when they change
+    pub watch_config_files: bool,
+    /// world backups are saved in `<backup_dir>/<server's config file>/`
+    pub backup_dir: Option<String>,
+    /// how often the running server is backed up, 0 to disable scheduled backups
+    pub backup_interval_mins: u64,
+    /// how many hourly, daily and weekly backups are kept
+    pub backup_retention: Retention,
+    /// how often the running server is asked for its tick rate (`tps`, `tick query`), 0 to disable
+    pub tps_interval_secs: u64,
+    /// a lag alert is sent to the info channel if the tps drops below this (0 to disable)
+    pub lag_alert_tps: f32,
+    /// ... or the milliseconds per tick go above this (0 to disable)
+    pub lag_alert_mspt: f32,
+    /// ... or the server logs `Can't keep up!` this often within 10 minutes (0 to disable)
+    pub lag_alert_cant_keep_up: usize,
+    /// the minimum time between two lag alerts
+    pub lag_alert_cooldown_mins: u64,
+    /// how custom events from line parsers and rules are sent to discord, by kind.
+    /// Kinds which aren't in here aren't sent.
+    pub custom_events: BTreeMap<String, CustomEventTemplate>,
+    /// where prometheus metrics are served (at `/metrics`), if set.
+    /// Only read when the bot starts.
+    pub metrics_addr: Option<SocketAddr>,
+    /// where the json api is served, if set. Only read when the bot starts.
+    pub api_addr: Option<SocketAddr>,
+    /// api requests need an `Authorization: Bearer <api_token>` header
+    pub api_token: Option<String>,
+}
+
+/// a custom event's discord message. `{field}` in the title and description is replaced with the event's fields
+/// and `{kind}` with its kind. Without a title or description, the kind and all fields are shown.
+#[derive(Clone, Default, Deserialize)]
+pub struct CustomEventTemplate {
+    /// the channel's id (default: the chat channel)
+    pub channel: Option<u64>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// the embed's color, i.e. `0xff0000` in toml (or `16711680`)
+    pub color: Option<u32>,
 }
 
 impl Settings {
-    pub fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
-        let file = std::fs::read_to_string(path)?;
-        let mut cii = None;
-        let mut cic = None;
-        let mut send_join_and_leave_messages = false;
-        let mut send_start_stop_messages_in_chat = false;
-        let mut get_my_ip_url1 = String::new();
-        let mut get_my_ip_url2 = String::new();
-        for (name, value) in file
-            .lines()
-            .map(|line| line.split_once("=").unwrap_or((line, "")))
+    /// loads `settings.toml`, or a file in the older `key=value` format (`settings.txt`)
+    pub fn from_file(path: impl AsRef<Path>) -> Loaded<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigIssue::new(path, None, format!("couldn't read the file: {e}")))?;
+        let (raw, warnings) = if config::is_toml(path) {
+            config::from_toml::<RawSettings>(path, &content)?
+        } else {
+            RawSettings::from_lines(path, &content)?
+        };
+        let issue = |key: &str, message: &str| {
+            ConfigIssue::new(path, config::find_key(&content, key), message)
+        };
+        let non_empty = |v: Option<String>| v.filter(|v| !v.trim().is_empty());
+        let chat_webhook = match non_empty(raw.chat_webhook_url) {
+            // don't show the webhook's token
+            Some(url) => Some(ChatWebhook::from_url(&url).ok_or_else(|| {
+                issue(
+                    "chat_webhook_url",
+                    "`chat_webhook_url` is not a webhook url",
+                )
+            })?),
+            None => None,
+        };
+        let metrics_addr = match non_empty(raw.metrics_addr) {
+            Some(addr) => Some(addr.trim().parse().map_err(|_| {
+                issue(
+                    "metrics_addr",
+                    "`metrics_addr` should be an address like `127.0.0.1:9100`",
+                )
+            })?),
+            None => None,
+        };
+        let api_addr = match non_empty(raw.api_addr) {
+            Some(addr) => Some(addr.trim().parse().map_err(|_| {
+                issue(
+                    "api_addr",
+                    "`api_addr` should be an address like `127.0.0.1:8080`",
+                )
+            })?),
+            None => None,
+        };
+        let api_token = non_empty(raw.api_token);
+        if api_addr.is_some() && api_token.is_none() {
+            return Err(issue("api_addr", "`api_addr` needs an `api_token`"));
+        }
+        // the api is plain http, so the token could be read by anyone on the network
+        if api_addr.is_some_and(|addr: SocketAddr| !addr.ip().is_loopback())
+            && !raw.api_allow_remote
         {
-            match name {
-                "channel_id_info" => {
-                    cii = value.trim().parse().ok();
+            return Err(issue(
+                "api_addr",
+                "`api_addr` isn't a loopback address like `127.0.0.1:8080`, set `api_allow_remote=true` to serve the api on the network anyway",
+            ));
+        }
+        let settings = Self {
+            channel_id_info: raw
+                .channel_id_info
+                .ok_or_else(|| issue("channel_id_info", "missing `channel_id_info`"))?,
+            channel_id_chat: raw
+                .channel_id_chat
+                .ok_or_else(|| issue("channel_id_chat", "missing `channel_id_chat`"))?,
+            send_join_and_leave_messages: raw.send_join_and_leave_messages,
+            send_death_messages: raw
+                .send_death_messages
+                .unwrap_or(raw.send_join_and_leave_messages),
+            send_start_stop_messages_in_chat: raw.send_start_stop_messages_in_chat,
+            send_discord_edits_in_minecraft: raw.send_discord_edits_in_minecraft,
+            send_discord_deletions_in_minecraft: raw.send_discord_deletions_in_minecraft,
+            get_my_ip_url1: raw.get_my_ip_url1,
+            get_my_ip_url2: raw.get_my_ip_url2,
+            chat_webhook,
+            skin_render_url: raw.skin_render_url,
+            mentions_file: raw.mentions_file,
+            mention_cooldown_secs: raw.mention_cooldown_secs,
+            new_servers_dir: non_empty(raw.new_servers_dir),
+            server_templates_dir: non_empty(raw.server_templates_dir),
+            jar_cache_dir: non_empty(raw.jar_cache_dir),
+            jar_mirror: non_empty(raw.jar_mirror),
+            java_paths: raw.java_paths.into_iter().map(PathBuf::from).collect(),
+            watch_config_files: raw.watch_config_files,
+            backup_dir: non_empty(raw.backup_dir),
+            backup_interval_mins: raw.backup_interval_mins,
+            backup_retention: Retention {
+                hourly: raw.backup_keep_hourly,
+                daily: raw.backup_keep_daily,
+                weekly: raw.backup_keep_weekly,
+            },
+            tps_interval_secs: raw.tps_interval_secs,
+            lag_alert_tps: raw.lag_alert_tps,
+            lag_alert_mspt: raw.lag_alert_mspt,
+            lag_alert_cant_keep_up: raw.lag_alert_cant_keep_up,
+            lag_alert_cooldown_mins: raw.lag_alert_cooldown_mins,
+            custom_events: raw.custom_events,
+            metrics_addr,
+            api_addr,
+            api_token,
+        };
+        Ok((settings, warnings))
+    }
+}
+
+/// the settings file's keys, before they are checked
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawSettings {
+    channel_id_info: Option<u64>,
+    channel_id_chat: Option<u64>,
+    send_join_and_leave_messages: bool,
+    send_death_messages: Option<bool>,
+    send_start_stop_messages_in_chat: bool,
+    send_discord_edits_in_minecraft: bool,
+    send_discord_deletions_in_minecraft: bool,
+    get_my_ip_url1: String,
+    get_my_ip_url2: String,
+    chat_webhook_url: Option<String>,
+    skin_render_url: String,
+    mentions_file: String,
+    mention_cooldown_secs: u64,
+    new_servers_dir: Option<String>,
+    server_templates_dir: Option<String>,
+    jar_cache_dir: Option<String>,
+    jar_mirror: Option<String>,
+    java_paths: Vec<String>,
+    watch_config_files: bool,
+    backup_dir: Option<String>,
+    backup_interval_mins: u64,
+    backup_keep_hourly: usize,
+    backup_keep_daily: usize,
+    backup_keep_weekly: usize,
+    tps_interval_secs: u64,
+    lag_alert_tps: f32,
+    lag_alert_mspt: f32,
+    lag_alert_cant_keep_up: usize,
+    lag_alert_cooldown_mins: u64,
+    custom_events: BTreeMap<String, CustomEventTemplate>,
+    metrics_addr: Option<String>,
+    api_addr: Option<String>,
+    api_token: Option<String>,
+    api_allow_remote: bool,
+}
+impl Default for RawSettings {
+    fn default() -> Self {
+        Self {
+            channel_id_info: None,
+            channel_id_chat: None,
+            send_join_and_leave_messages: false,
+            send_death_messages: None,
+            send_start_stop_messages_in_chat: false,
+            send_discord_edits_in_minecraft: false,
+            send_discord_deletions_in_minecraft: false,
+            get_my_ip_url1: String::new(),
+            get_my_ip_url2: String::new(),
+            chat_webhook_url: None,
+            skin_render_url: "https://mc-heads.net/avatar/{name}".to_owned(),
+            mentions_file: "mentions.txt".to_owned(),
+            mention_cooldown_secs: 30,
+            new_servers_dir: None,
+            server_templates_dir: None,
+            jar_cache_dir: None,
+            jar_mirror: None,
+            java_paths: vec![],
+            watch_config_files: false,
+            backup_dir: None,
+            backup_interval_mins: 0,
+            backup_keep_hourly: 24,
+            backup_keep_daily: 7,
+            backup_keep_weekly: 4,
+            tps_interval_secs: 60,
+            lag_alert_tps: 15.0,
+            lag_alert_mspt: 0.0,
+            lag_alert_cant_keep_up: 0,
+            lag_alert_cooldown_mins: 30,
+            custom_events: BTreeMap::new(),
+            metrics_addr: None,
+            api_addr: None,
+            api_token: None,
+            api_allow_remote: false,
+        }
+    }
+}
+impl RawSettings {
+    /// the older `key=value` format
+    fn from_lines(path: &Path, content: &str) -> Loaded<Self> {
+        let mut o = Self::default();
+        let mut warnings = vec![];
+        for (line, text) in config::lines(content) {
+            let Some((key, value)) = text.split_once('=') else {
+                warnings.push(ConfigIssue::new(path, Some(line), "expected `key=value`"));
+                continue;
+            };
+            let value = value.trim();
+            let invalid = |e: String| ConfigIssue::new(path, Some(line), format!("`{key}`: {e}"));
+            let number = |value: &str| {
+                value
+                    .parse()
+                    .map_err(|_| invalid(format!("expected a number, found `{value}`")))
+            };
+            let float = |value: &str| {
+                value
+                    .parse()
+                    .map_err(|_| invalid(format!("expected a number, found `{value}`")))
+            };
+            let bool = |value: &str| config::parse_bool(value).map_err(invalid);
+            match key.trim() {
+                "channel_id_info" => o.channel_id_info = Some(number(value)?),
+                "channel_id_chat" => o.channel_id_chat = Some(number(value)?),
+                "send_join_and_leave_messages" => o.send_join_and_leave_messages = bool(value)?,
+                "send_death_messages" => o.send_death_messages = Some(bool(value)?),
+                "send_start_stop_messages_in_chat" => {
+                    o.send_start_stop_messages_in_chat = bool(value)?
                 }
-                "channel_id_chat" => {
-                    cic = value.trim().parse().ok();
+                "send_discord_edits_in_minecraft" => {
+                    o.send_discord_edits_in_minecraft = bool(value)?
                 }
-                "get_my_ip_url1" => get_my_ip_url1 = value.trim().to_owned(),
-                "get_my_ip_url2" => get_my_ip_url2 = value.trim().to_owned(),
-                "send_join_and_leave_messages" => send_join_and_leave_messages = value != "false",
-                "send_start_stop_messages_in_chat" => {
-                    send_start_stop_messages_in_chat = value != "false"
+                "send_discord_deletions_in_minecraft" => {
+                    o.send_discord_deletions_in_minecraft = bool(value)?
+                }
+                "get_my_ip_url1" => o.get_my_ip_url1 = value.to_owned(),
+                "get_my_ip_url2" => o.get_my_ip_url2 = value.to_owned(),
+                "chat_webhook_url" => o.chat_webhook_url = Some(value.to_owned()),
+                "skin_render_url" => o.skin_render_url = value.to_owned(),
+                "mentions_file" => o.mentions_file = value.to_owned(),
+                "mention_cooldown_secs" => o.mention_cooldown_secs = number(value)?,
+                "new_servers_dir" => o.new_servers_dir = Some(value.to_owned()),
+                "server_templates_dir" => o.server_templates_dir = Some(value.to_owned()),
+                "jar_cache_dir" => o.jar_cache_dir = Some(value.to_owned()),
+                "jar_mirror" => o.jar_mirror = Some(value.to_owned()),
+                "java_paths" => {
+                    o.java_paths = value
+                        .split(',')
+                        .map(|path| path.trim().to_owned())
+                        .filter(|path| !path.is_empty())
+                        .collect()
                 }
-                _ => {}
+                "watch_config_files" => o.watch_config_files = bool(value)?,
+                "backup_dir" => o.backup_dir = Some(value.to_owned()),
+                "backup_interval_mins" => o.backup_interval_mins = number(value)?,
+                "backup_keep_hourly" => o.backup_keep_hourly = number(value)? as usize,
+                "backup_keep_daily" => o.backup_keep_daily = number(value)? as usize,
+                "backup_keep_weekly" => o.backup_keep_weekly = number(value)? as usize,
+                "tps_interval_secs" => o.tps_interval_secs = number(value)?,
+                "lag_alert_tps" => o.lag_alert_tps = float(value)?,
+                "lag_alert_mspt" => o.lag_alert_mspt = float(value)?,
+                "lag_alert_cant_keep_up" => o.lag_alert_cant_keep_up = number(value)? as usize,
+                "lag_alert_cooldown_mins" => o.lag_alert_cooldown_mins = number(value)?,
+                "metrics_addr" => o.metrics_addr = Some(value.to_owned()),
+                "api_addr" => o.api_addr = Some(value.to_owned()),
+                "api_token" => o.api_token = Some(value.to_owned()),
+                "api_allow_remote" => o.api_allow_remote = bool(value)?,
+                // `custom_event.<kind>.<channel|title|description|color>`
+                key if key.starts_with("custom_event.") => {
+                    let template = key["custom_event.".len()..]
+                        .rsplit_once('.')
+                        .filter(|(kind, _)| !kind.is_empty());
+                    let Some((kind, field)) = template else {
+                        warnings.push(ConfigIssue::new(
+                            path,
+                            Some(line),
+                            format!("`{key}` should be `custom_event.<kind>.<field>`"),
+                        ));
+                        continue;
+                    };
+                    let template = o.custom_events.entry(kind.to_owned()).or_default();
+                    match field {
+                        "channel" => template.channel = Some(number(value)?),
+                        "title" => template.title = Some(value.to_owned()),
+                        "description" => template.description = Some(value.to_owned()),
+                        "color" => template.color = Some(number(value)? as u32),
+                        field => warnings.push(ConfigIssue::new(
+                            path,
+                            Some(line),
+                            format!("unknown custom event key `{field}` (channel, title, description or color)"),
+                        )),
+                    }
+                }
+                key => warnings.push(ConfigIssue::new(
+                    path,
+                    Some(line),
+                    format!("unknown key `{key}`"),
+                )),
             }
         }
-        Ok(Self {
-            channel_id_info: cii.expect("[settings] Missing `channel_id_info`"),
-            channel_id_chat: cic.expect("[settings] Missing `channel_id_chat`"),
-            send_join_and_leave_messages,
-            send_start_stop_messages_in_chat,
-            get_my_ip_url1,
-            get_my_ip_url2,
-        })
+        Ok((o, warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// loads settings with the api on `addr`
+    fn load_api(addr: &str, allow_remote: bool) -> Result<Option<SocketAddr>, String> {
+        let path = std::env::temp_dir().join(format!(
+            "mcdcbot-settings-api-{}-{}.txt",
+            addr.replace([':', '[', ']'], "_"),
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            format!(
+                "channel_id_info=1\nchannel_id_chat=2\napi_addr={addr}\napi_token=t\napi_allow_remote={allow_remote}\n"
+            ),
+        )
+        .unwrap();
+        let result = Settings::from_file(&path);
+        _ = std::fs::remove_file(&path);
+        result
+            .map(|(settings, _)| settings.api_addr)
+            .map_err(|e| e.message)
+    }
+
+    #[test]
+    fn api_is_only_served_remotely_if_allowed() {
+        for addr in ["127.0.0.1:8080", "[::1]:8080"] {
+            assert_eq!(load_api(addr, false), Ok(Some(addr.parse().unwrap())));
+        }
+        for addr in ["0.0.0.0:8080", "192.168.1.2:8080", "[::]:8080"] {
+            let e = load_api(addr, false).unwrap_err();
+            assert!(e.contains("api_allow_remote=true"), "{e}");
+            assert_eq!(load_api(addr, true), Ok(Some(addr.parse().unwrap())));
+        }
     }
 }
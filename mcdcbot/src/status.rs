@@ -0,0 +1,83 @@
+use std::{sync::Arc, time::Duration};
+
+use minecraft_manager::resources;
+
+use crate::{backup::format_size, lag::CANT_KEEP_UP_WINDOW, Context, Error};
+
+/// Show the running server's players and resource usage
+#[poise::command(slash_command)]
+pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
+    let current = ctx
+        .data()
+        .current
+        .lock()
+        .await
+        .as_ref()
+        .map(|(server, thread)| (Arc::clone(server), Arc::clone(thread)));
+    let Some((server, thread)) = current else {
+        ctx.say("No server is running. Use /start to start one.")
+            .await?;
+        return Ok(());
+    };
+    let server = server.lock().await;
+    let thread_lock = thread.lock().await;
+    let Some(thread) = thread_lock.as_ref() else {
+        ctx.say("No server is running. Use /start to start one.")
+            .await?;
+        return Ok(());
+    };
+    let title = format!("{} ({})", server.name, server.settings.server_type);
+    let mut lines = vec![];
+    if let Some(version) = thread.version() {
+        lines.push(format!("Minecraft {version}"));
+    }
+    let players = thread.online_players();
+    lines.push(if players.is_empty() {
+        "Players: 0".to_owned()
+    } else {
+        format!("Players: {} ({})", players.len(), players.join(", "))
+    });
+    if let Some(uptime) = thread.uptime() {
+        lines.push(format!("Uptime: {}", resources::format_duration(uptime)));
+    }
+    let performance = thread.performance();
+    if let Some(latest) = performance.latest() {
+        let mut tps = format!("TPS: {:.1}", latest.tps);
+        if let Some(min) = performance.min_tps(Duration::from_secs(60 * 60)) {
+            if min < latest.tps {
+                tps.push_str(&format!(" (lowest in the last hour: {min:.1})"));
+            }
+        }
+        if let Some(mspt) = latest.mspt {
+            tps.push_str(&format!(", MSPT: {mspt:.1} ms"));
+        }
+        lines.push(tps);
+    }
+    let cant_keep_up = performance.cant_keep_up_count(CANT_KEEP_UP_WINDOW);
+    if cant_keep_up > 0 {
+        lines.push(format!(
+            "\"Can't keep up!\" {cant_keep_up} times in the last {} minutes",
+            CANT_KEEP_UP_WINDOW.as_secs() / 60
+        ));
+    }
+    match thread.resources() {
+        Some(usage) => {
+            if let Some(cpu) = usage.cpu_percent {
+                lines.push(format!("CPU: {cpu:.0}%"));
+            }
+            lines.push(format!(
+                "Memory: {} (-Xmx {})",
+                format_size(usage.stats.rss),
+                format_size(server.settings.ram_max as u64 * 1024 * 1024)
+            ));
+            lines.push(format!("Threads: {}", usage.stats.threads));
+        }
+        None => lines.push("Resource usage isn't available yet.".to_owned()),
+    }
+    // discord can be slow, the server's thread shouldn't wait for it
+    drop(thread_lock);
+    drop(server);
+    ctx.send(|m| m.embed(|e| e.title(title).description(lines.join("\n"))))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,321 @@
+use minecraft_manager::text::{tellraw, ClickEvent, TextComponent};
+use poise::serenity_prelude as serenity;
+
+use crate::{
+    markdown,
+    mentions::{self, Mentions},
+};
+
+/// how many characters of the parent message are shown for replies
+const REPLY_SNIPPET_LEN: usize = 40;
+
+/// a message from the discord chat channel, to be shown in minecraft
+pub struct DiscordChatMessage {
+    /// the name shown in minecraft (nickname or username)
+    pub author: String,
+    /// the discord tag/username, shown when hovering over the name
+    pub author_tag: String,
+    /// the color of the author's highest colored role
+    pub author_color: Option<u32>,
+    /// the message this is a reply to
+    pub reply_to: Option<DiscordReply>,
+    pub content: String,
+    pub attachments: Vec<DiscordAttachment>,
+    pub stickers: Vec<String>,
+    /// embeds which aren't just previews of links in `content`
+    pub embeds: Vec<DiscordEmbed>,
+    pub edited: bool,
+    /// online players who were mentioned (`@name` or a mention of their linked discord account)
+    pub mentioned_players: Vec<String>,
+}
+
+pub struct DiscordReply {
+    pub author: String,
+    pub content: String,
+}
+
+pub struct DiscordAttachment {
+    /// `image`, `video`, `audio` or `file`
+    pub kind: &'static str,
+    pub name: String,
+    pub url: String,
+}
+
+pub struct DiscordEmbed {
+    pub title: String,
+    pub url: Option<String>,
+}
+
+impl DiscordChatMessage {
+    /// `online_players` is used to show mentions of linked discord accounts as the player's name.
+    pub async fn from_message(
+        ctx: &serenity::Context,
+        msg: &serenity::Message,
+        mentions: &Mentions,
+        online_players: &[String],
+    ) -> Self {
+        let content = clean_content(ctx, msg, mentions, online_players).await;
+        let reply_to = if let Some(reply) = &msg.referenced_message {
+            Some(DiscordReply {
+                author: display_name(ctx, reply).await,
+                content: clean_content(ctx, reply, mentions, online_players).await,
+            })
+        } else {
+            None
+        };
+        let mentioned = mentions::find_mentions(&content);
+        let mentioned_players = online_players
+            .iter()
+            .filter(|player| {
+                mentioned
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(player))
+            })
+            .cloned()
+            .collect();
+        Self {
+            author: display_name(ctx, msg).await,
+            author_tag: discord_tag(&msg.author),
+            author_color: msg
+                .member(ctx)
+                .await
+                .ok()
+                .and_then(|member| member.colour(&ctx.cache))
+                .map(|color| color.0)
+                .filter(|color| *color != 0),
+            reply_to,
+            attachments: msg
+                .attachments
+                .iter()
+                .map(|attachment| DiscordAttachment {
+                    kind: match attachment
+                        .content_type
+                        .as_deref()
+                        .and_then(|t| t.split_once('/'))
+                    {
+                        Some(("image", _)) => "image",
+                        Some(("video", _)) => "video",
+                        Some(("audio", _)) => "audio",
+                        _ => "file",
+                    },
+                    name: attachment.filename.clone(),
+                    url: attachment.url.clone(),
+                })
+                .collect(),
+            stickers: msg
+                .sticker_items
+                .iter()
+                .map(|sticker| sticker.name.clone())
+                .collect(),
+            embeds: msg
+                .embeds
+                .iter()
+                .filter(|embed| {
+                    !embed
+                        .url
+                        .as_ref()
+                        .is_some_and(|url| content.contains(url.as_str()))
+                })
+                .filter_map(|embed| {
+                    Some(DiscordEmbed {
+                        title: embed
+                            .title
+                            .clone()
+                            .or_else(|| embed.author.as_ref().map(|a| a.name.clone()))?,
+                        url: embed.url.clone(),
+                    })
+                })
+                .collect(),
+            content,
+            edited: msg.edited_timestamp.is_some(),
+            mentioned_players,
+        }
+    }
+}
+
+pub fn chat_message(msg: &DiscordChatMessage) -> String {
+    let mut author = TextComponent::text(&msg.author)
+        .hover_text(TextComponent::text(format!("Discord: {}", msg.author_tag)));
+    if let Some(color) = msg.author_color {
+        author = author.color_rgb(color);
+    }
+    let mut text = TextComponent::empty();
+    if let Some(reply) = &msg.reply_to {
+        let reply_content = markdown::to_minecraft(&reply.content).plain_text();
+        let mut snippet = reply_content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some((i, _)) = snippet.char_indices().nth(REPLY_SNIPPET_LEN) {
+            snippet.truncate(i);
+            snippet.push_str("...");
+        }
+        text = text.append(
+            TextComponent::text(format!("(replying to {}: \"{snippet}\") ", reply.author))
+                .color("gray")
+                .italic()
+                .hover_text(TextComponent::text(reply_content)),
+        );
+    }
+    text = text
+        .append(TextComponent::text("<"))
+        .append(author)
+        .append(TextComponent::text("> "));
+    if msg.edited {
+        text = text.append(TextComponent::text("(edited) ").color("gray").italic());
+    }
+    text = text.append(highlight(
+        markdown::to_minecraft(&msg.content),
+        &msg.mentioned_players,
+    ));
+    for attachment in &msg.attachments {
+        text = text.append(TextComponent::text(" ")).append(
+            TextComponent::text(format!("[{}: {}]", attachment.kind, attachment.name))
+                .color("aqua")
+                .click(ClickEvent::OpenUrl(attachment.url.clone()))
+                .hover_text(TextComponent::text(format!("Open {}", attachment.name))),
+        );
+    }
+    for sticker in &msg.stickers {
+        text = text
+            .append(TextComponent::text(" "))
+            .append(TextComponent::text(format!("[sticker: {sticker}]")).color("light_purple"));
+    }
+    for embed in &msg.embeds {
+        let mut component = TextComponent::text(format!("[embed: {}]", embed.title)).color("aqua");
+        if let Some(url) = &embed.url {
+            component = component
+                .click(ClickEvent::OpenUrl(url.clone()))
+                .hover_text(TextComponent::text(url));
+        }
+        text = text.append(TextComponent::text(" ")).append(component);
+    }
+    tellraw("@a", &text)
+}
+
+/// plays a sound for a player who was mentioned on discord
+pub fn mention_sound(player: &str) -> String {
+    format!("execute as {player} at @s run playsound minecraft:block.note_block.pling player @s ~ ~ ~ 1 2")
+}
+
+/// makes all mentions (`@name`, ascii case-insensitive) of the `players` yellow and bold.
+/// Only whole names are highlighted, so `@Al` isn't found in `@Alice`.
+fn highlight(mut component: TextComponent, players: &[String]) -> TextComponent {
+    if players.is_empty() {
+        return component;
+    }
+    component.extra = component
+        .extra
+        .into_iter()
+        .map(|child| highlight(child, players))
+        .collect();
+    let mut parts = vec![];
+    let mut text = component.text.as_str();
+    while !text.is_empty() {
+        let next = text
+            .match_indices('@')
+            .map(|(i, _)| (i, mentions::mention_at(&text[i + 1..])))
+            .find(|(_, name)| {
+                players
+                    .iter()
+                    .any(|player| player.eq_ignore_ascii_case(name))
+            })
+            .map(|(i, name)| (i, 1 + name.len()));
+        if let Some((i, len)) = next {
+            if i > 0 {
+                parts.push(TextComponent::text(&text[..i]));
+            }
+            parts.push(
+                TextComponent::text(&text[i..i + len])
+                    .color("yellow")
+                    .bold(),
+            );
+            text = &text[i + len..];
+        } else {
+            parts.push(TextComponent::text(text));
+            break;
+        }
+    }
+    if parts.len() > 1 || parts.first().is_some_and(|part| part.bold.is_some()) {
+        let mut extra = parts;
+        extra.append(&mut component.extra);
+        component.text = String::new();
+        component.extra = extra;
+    }
+    component
+}
+
+/// the message's content with user mentions replaced by names (without discriminators)
+/// and role/channel/everyone mentions cleaned like [`serenity::Message::content_safe`] does.
+/// Users linked to an online player are shown as `@<player name>`.
+async fn clean_content(
+    ctx: &serenity::Context,
+    msg: &serenity::Message,
+    mentions: &Mentions,
+    online_players: &[String],
+) -> String {
+    let mut content = msg.content.clone();
+    for user in &msg.mentions {
+        let name = if let Some(player) =
+            mentions.linked_minecraft_name(user.id.0).and_then(|name| {
+                online_players
+                    .iter()
+                    .find(|player| player.to_lowercase() == name)
+            }) {
+            player.clone()
+        } else if let Some(guild_id) = msg.guild_id {
+            user.nick_in(ctx, guild_id)
+                .await
+                .unwrap_or_else(|| user.name.clone())
+        } else {
+            user.name.clone()
+        };
+        content = content
+            .replace(&format!("<@{}>", user.id.0), &format!("@{name}"))
+            .replace(&format!("<@!{}>", user.id.0), &format!("@{name}"));
+    }
+    serenity::content_safe(
+        &ctx.cache,
+        content,
+        &serenity::ContentSafeOptions::default(),
+        &msg.mentions,
+    )
+}
+
+/// the message shown in minecraft when a relayed discord message is deleted
+pub fn message_deleted(author: &str) -> String {
+    tellraw(
+        "@a",
+        &TextComponent::text(format!("{author} deleted a message"))
+            .color("gray")
+            .italic(),
+    )
+}
+
+/// tells a player who typed their code from `/link` whether their account was linked
+pub fn link_confirmed(player: &str, linked: bool) -> String {
+    tellraw(
+        player,
+        &if linked {
+            TextComponent::text("Linked your discord account.").color("green")
+        } else {
+            TextComponent::text("Couldn't link your discord account.").color("red")
+        },
+    )
+}
+
+/// the author's nickname, or their username if they don't have one
+pub async fn display_name(ctx: &serenity::Context, msg: &serenity::Message) -> String {
+    msg.author_nick(ctx)
+        .await
+        .unwrap_or_else(|| msg.author.name.clone())
+}
+
+/// `name#1234` for users with a discriminator, `@name` otherwise
+pub fn discord_tag(user: &serenity::User) -> String {
+    if user.discriminator == 0 {
+        format!("@{}", user.name)
+    } else {
+        user.tag()
+    }
+}
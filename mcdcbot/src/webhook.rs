@@ -0,0 +1,36 @@
+use poise::serenity_prelude::{self as serenity, json::Value, Http};
+
+/// A discord webhook, used to send minecraft chat messages with the player's name and avatar.
+#[derive(Clone)]
+pub struct ChatWebhook {
+    pub id: u64,
+    pub token: String,
+}
+
+impl ChatWebhook {
+    /// parses a webhook url (`https://discord.com/api/webhooks/<id>/<token>`)
+    pub fn from_url(url: &str) -> Option<Self> {
+        let (_, rest) = url.trim().split_once("/api/webhooks/")?;
+        let (id, token) = rest.split_once('/')?;
+        let token = token.trim_end_matches('/');
+        if token.is_empty() || token.contains('/') {
+            return None;
+        }
+        Some(Self {
+            id: id.parse().ok()?,
+            token: token.to_owned(),
+        })
+    }
+
+    pub async fn execute(&self, http: &Http, msg: &Value) -> serenity::Result<()> {
+        if let Some(map) = msg.as_object() {
+            http.execute_webhook(self.id, &self.token, false, map)
+                .await
+                .map(|_| ())
+        } else {
+            Err(serenity::Error::Other(
+                "webhook message is not a json object",
+            ))
+        }
+    }
+}
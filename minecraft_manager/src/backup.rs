@@ -0,0 +1,515 @@
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs::{self, File},
+    io,
+    path::{Component, Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// backups are saved as `<id>.tar.zst`
+pub const EXTENSION: &str = ".tar.zst";
+const ZSTD_LEVEL: i32 = 3;
+
+/// a world backup
+pub struct Backup {
+    /// when the backup was started (UTC), `YYYY-MM-DD_hh-mm-ss`,
+    /// followed by `_2`, `_3`, ... for more backups started in the same second
+    pub id: String,
+    pub path: PathBuf,
+    pub time: SystemTime,
+    /// the archive's size in bytes
+    pub size: u64,
+}
+
+/// how many backups to keep: the newest backup of each of the last `hourly` hours,
+/// `daily` days and `weekly` weeks (which have backups). The newest backup is always kept.
+#[derive(Clone, Copy)]
+pub struct Retention {
+    pub hourly: usize,
+    pub daily: usize,
+    pub weekly: usize,
+}
+
+/// the directories in the server's directory which contain a world (a `level.dat`)
+pub fn world_dirs(server_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut o = vec![];
+    for entry in fs::read_dir(server_dir)? {
+        let path = entry?.path();
+        if path.join("level.dat").is_file() {
+            o.push(path);
+        }
+    }
+    o.sort();
+    Ok(o)
+}
+
+/// archives the server's worlds to `<backup_dir>/<id>.tar.zst`.
+/// The server must not write to its worlds while this runs (`save-off`).
+/// If `progress` is set, it receives the percentage of worlds which were archived,
+/// like a [task callback](crate::tasks::MinecraftServerTaskCallback).
+pub fn create(
+    server_dir: &Path,
+    backup_dir: &Path,
+    progress: Option<&mpsc::Sender<Result<u8, String>>>,
+) -> io::Result<Backup> {
+    let worlds = world_dirs(server_dir)?;
+    if worlds.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no worlds in {}", server_dir.display()),
+        ));
+    }
+    fs::create_dir_all(backup_dir)?;
+    let time = SystemTime::now();
+    let base = format_time(time);
+    let mut id = base.clone();
+    // in case of two backups in the same second
+    let mut i = 1;
+    let (path, partial, file) = loop {
+        let path = backup_dir.join(format!("{id}{EXTENSION}"));
+        // only has the real name once it's complete
+        let partial = backup_dir.join(format!("{id}{EXTENSION}.partial"));
+        if !path.exists() {
+            match File::options().write(true).create_new(true).open(&partial) {
+                Ok(file) => break (path, partial, file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        }
+        i += 1;
+        id = format!("{base}_{i}");
+    };
+    let result = (|| {
+        let encoder = zstd::Encoder::new(file, ZSTD_LEVEL)?;
+        let mut archive = tar::Builder::new(encoder);
+        for (i, world) in worlds.iter().enumerate() {
+            if let Some(progress) = progress {
+                _ = progress.send(Ok((i * 100 / worlds.len()) as u8));
+            }
+            archive.append_dir_all(world.file_name().unwrap_or_default(), world)?;
+        }
+        archive.into_inner()?.finish()?.sync_all()
+    })();
+    if let Err(e) = result {
+        _ = fs::remove_file(&partial);
+        if let Some(progress) = progress {
+            _ = progress.send(Err(e.to_string()));
+        }
+        return Err(e);
+    }
+    fs::rename(&partial, &path)?;
+    if let Some(progress) = progress {
+        _ = progress.send(Ok(100));
+    }
+    Ok(Backup {
+        id,
+        size: fs::metadata(&path)?.len(),
+        path,
+        time,
+    })
+}
+
+/// restores a backup: the server's current worlds are moved to `<server_dir>/pre-restore_<time>/`
+/// (where they can't be mistaken for worlds) and the archive is extracted into the server's directory.
+/// If extracting fails, everything it created is removed and the current worlds are moved back.
+/// Returns the directory containing the previous worlds.
+/// If `progress` is set, it receives the percentage of the archive which was extracted,
+/// like a [task callback](crate::tasks::MinecraftServerTaskCallback).
+pub fn restore(
+    server_dir: &Path,
+    backup: &Backup,
+    progress: Option<&mpsc::Sender<Result<u8, String>>>,
+) -> io::Result<PathBuf> {
+    let send = |update: Result<u8, String>| {
+        if let Some(progress) = progress {
+            _ = progress.send(update);
+        }
+    };
+    let worlds = world_dirs(server_dir)?;
+    let time = format_time(SystemTime::now());
+    let mut snapshot = server_dir.join(format!("pre-restore_{time}"));
+    // in case of two restores in the same second
+    let mut i = 1;
+    while let Err(e) = fs::create_dir(&snapshot) {
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+        i += 1;
+        snapshot = server_dir.join(format!("pre-restore_{time}_{i}"));
+    }
+    let mut moved = vec![];
+    for world in &worlds {
+        let to = snapshot.join(world.file_name().unwrap_or_default());
+        if let Err(e) = fs::rename(world, &to) {
+            move_back(&moved);
+            _ = fs::remove_dir(&snapshot);
+            send(Err(e.to_string()));
+            return Err(e);
+        }
+        moved.push((world.clone(), to));
+    }
+    // the top-level files and directories in the archive, and those of them which didn't exist before
+    let mut seen = HashSet::new();
+    let mut created = vec![];
+    let result = (|| -> io::Result<()> {
+        let file = File::open(&backup.path)?;
+        let size = file.metadata()?.len().max(1);
+        let mut last = None;
+        let reader = ProgressReader {
+            inner: file,
+            read: 0,
+            on_read: |read: u64| {
+                let percent = (read * 100 / size).min(99) as u8;
+                if last != Some(percent) {
+                    last = Some(percent);
+                    send(Ok(percent));
+                }
+            },
+        };
+        let mut archive = tar::Archive::new(zstd::Decoder::new(reader)?);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if let Some(Component::Normal(top)) = entry
+                .path()?
+                .components()
+                .find(|component| !matches!(component, Component::CurDir))
+            {
+                let top = server_dir.join(top);
+                if !seen.contains(&top) {
+                    if !top.exists() {
+                        created.push(top.clone());
+                    }
+                    seen.insert(top);
+                }
+            }
+            // `unpack_in` refuses paths outside of the server's directory
+            entry.unpack_in(server_dir)?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        // remove whatever was extracted (and didn't exist before), so it can't be mistaken for a world
+        for path in &created {
+            if path.is_dir() {
+                _ = fs::remove_dir_all(path);
+            } else {
+                _ = fs::remove_file(path);
+            }
+        }
+        move_back(&moved);
+        _ = fs::remove_dir(&snapshot);
+        send(Err(e.to_string()));
+        return Err(e);
+    }
+    send(Ok(100));
+    Ok(snapshot)
+}
+fn move_back(moved: &[(PathBuf, PathBuf)]) {
+    for (world, to) in moved {
+        if let Err(e) = fs::rename(to, world) {
+            eprintln!(
+                "[WARN/BACKUP] Couldn't move {} back to {}: {e}",
+                to.display(),
+                world.display()
+            );
+        }
+    }
+}
+
+/// calls `on_read` with the number of bytes read so far
+struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    on_read: F,
+}
+impl<R: io::Read, F: FnMut(u64)> io::Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        (self.on_read)(self.read);
+        Ok(n)
+    }
+}
+
+/// all backups in `backup_dir`, newest first
+pub fn list(backup_dir: &Path) -> io::Result<Vec<Backup>> {
+    let mut o = vec![];
+    let entries = match fs::read_dir(backup_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(o),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = file_name.strip_suffix(EXTENSION) else {
+            continue;
+        };
+        let Some(time) = parse_time(id) else {
+            continue;
+        };
+        o.push(Backup {
+            id: id.to_owned(),
+            path: entry.path(),
+            time,
+            size: entry.metadata()?.len(),
+        });
+    }
+    // so `_10` is newer than `_9`
+    o.sort_by_key(|backup| Reverse((backup.time, backup.id.len(), backup.id.clone())));
+    Ok(o)
+}
+
+/// the backups (from a list sorted newest first) which `retention` doesn't keep
+pub fn expired<'a>(backups: &'a [Backup], retention: &Retention) -> Vec<&'a Backup> {
+    let mut keep = HashSet::new();
+    keep.insert(0);
+    for (period, count) in [
+        (3600, retention.hourly),
+        (24 * 3600, retention.daily),
+        (7 * 24 * 3600, retention.weekly),
+    ] {
+        let mut periods = vec![];
+        for (i, backup) in backups.iter().enumerate() {
+            let p = unix_secs(backup.time) / period;
+            if !periods.contains(&p) {
+                if periods.len() == count {
+                    break;
+                }
+                periods.push(p);
+                keep.insert(i);
+            }
+        }
+    }
+    backups
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !keep.contains(i))
+        .map(|(_, backup)| backup)
+        .collect()
+}
+
+/// deletes the backups in `backup_dir` which `retention` doesn't keep and returns their ids
+pub fn prune(backup_dir: &Path, retention: &Retention) -> io::Result<Vec<String>> {
+    let backups = list(backup_dir)?;
+    let mut o = vec![];
+    for backup in expired(&backups, retention) {
+        fs::remove_file(&backup.path)?;
+        o.push(backup.id.clone());
+    }
+    Ok(o)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// `YYYY-MM-DD_hh-mm-ss` (UTC)
+fn format_time(time: SystemTime) -> String {
+    let secs = unix_secs(time);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+/// the time of a backup id (see [`Backup::id`])
+fn parse_time(id: &str) -> Option<SystemTime> {
+    let (date, time) = id.split_once('_')?;
+    let time = match time.split_once('_') {
+        Some((time, i)) => i.parse::<u32>().ok().map(|_| time)?,
+        None => time,
+    };
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let mut time = time.splitn(3, '-').map(str::parse::<u64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (h, m, s) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + h * 3600 + m * 60 + s))
+}
+
+// http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an empty directory for one test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft_manager-backup-{test}-{}",
+            std::process::id()
+        ));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn time(id: &str) -> SystemTime {
+        parse_time(id).unwrap_or_else(|| panic!("{id}"))
+    }
+
+    #[test]
+    fn time_round_trips() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01_00-00-00");
+        assert_eq!(time("1970-01-01_00-00-00"), UNIX_EPOCH);
+        for (id, next) in [
+            // year boundaries
+            ("1970-12-31_23-59-59", "1971-01-01_00-00-00"),
+            ("1999-12-31_23-59-59", "2000-01-01_00-00-00"),
+            // leap days, 2000 is a leap year, 2100 isn't
+            ("2000-02-28_23-59-59", "2000-02-29_00-00-00"),
+            ("2000-02-29_23-59-59", "2000-03-01_00-00-00"),
+            ("2023-02-28_23-59-59", "2023-03-01_00-00-00"),
+            ("2024-02-29_23-59-59", "2024-03-01_00-00-00"),
+            ("2100-02-28_23-59-59", "2100-03-01_00-00-00"),
+            ("2024-07-15_09-05-03", "2024-07-15_09-05-04"),
+        ] {
+            assert_eq!(format_time(time(id)), id);
+            assert_eq!(format_time(time(id) + Duration::from_secs(1)), next);
+        }
+    }
+
+    #[test]
+    fn ids_are_parsed() {
+        let t = time("2024-02-29_12-00-00");
+        assert_eq!(parse_time("2024-02-29_12-00-00_2"), Some(t));
+        assert_eq!(parse_time("2024-02-29_12-00-00_10"), Some(t));
+        for id in [
+            "",
+            "2024-02-29",
+            "2024-02-29_12-00",
+            "2024-02-29_12-00-00_x",
+            "2024-02-29_12-00-00_",
+            "1969-12-31_23-59-59",
+            "latest",
+        ] {
+            assert_eq!(parse_time(id), None, "{id}");
+        }
+    }
+
+    #[test]
+    fn days_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        for days in -800_000..800_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert!((1..=12).contains(&month) && (1..=31).contains(&day));
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn same_second_backups_get_numbered_ids() {
+        let dir = temp_dir("same-second");
+        let server_dir = dir.join("server");
+        fs::create_dir_all(server_dir.join("world")).unwrap();
+        fs::write(server_dir.join("world/level.dat"), "level").unwrap();
+        let backup_dir = dir.join("backups");
+        // retry if the second changes between the backups
+        let ids = loop {
+            let ids: Vec<_> = (0..3)
+                .map(|_| create(&server_dir, &backup_dir, None).unwrap().id)
+                .collect();
+            if ids[1] == format!("{}_2", ids[0]) && ids[2] == format!("{}_3", ids[0]) {
+                break ids;
+            }
+            fs::remove_dir_all(&backup_dir).unwrap();
+        };
+        let listed: Vec<_> = list(&backup_dir)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.id)
+            .collect();
+        assert_eq!(listed, ids.into_iter().rev().collect::<Vec<_>>());
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn retention() {
+        let dir = temp_dir("retention");
+        // 2024-03-13 is a wednesday, unix weeks start on thursdays
+        let ids = [
+            "2024-03-13_12-00-00",
+            "2024-03-13_11-30-00",
+            "2024-03-13_11-00-00",
+            "2024-03-13_10-00-00",
+            "2024-03-12_12-00-00",
+            "2024-03-11_12-00-00",
+            "2024-03-04_12-00-00",
+        ];
+        for id in ids {
+            fs::write(dir.join(format!("{id}{EXTENSION}")), id).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a backup").unwrap();
+        let retention = Retention {
+            hourly: 2,
+            daily: 2,
+            weekly: 2,
+        };
+        let backups = list(&dir).unwrap();
+        let expired_ids: Vec<_> = expired(&backups, &retention)
+            .into_iter()
+            .map(|b| b.id.as_str())
+            .collect();
+        assert_eq!(
+            expired_ids,
+            [
+                "2024-03-13_11-00-00",
+                "2024-03-13_10-00-00",
+                "2024-03-11_12-00-00"
+            ]
+        );
+
+        // the newest backup is always kept
+        let none = Retention {
+            hourly: 0,
+            daily: 0,
+            weekly: 0,
+        };
+        assert_eq!(expired(&backups, &none).len(), ids.len() - 1);
+
+        assert_eq!(prune(&dir, &retention).unwrap(), expired_ids);
+        let kept: Vec<_> = list(&dir).unwrap().into_iter().map(|b| b.id).collect();
+        assert_eq!(
+            kept,
+            [
+                "2024-03-13_12-00-00",
+                "2024-03-13_11-30-00",
+                "2024-03-12_12-00-00",
+                "2024-03-04_12-00-00"
+            ]
+        );
+        assert!(dir.join("notes.txt").exists());
+        _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,529 @@
+//! `mcman`: runs the servers from a servers directory (the same config files as mcdcbot's) without discord.
+
+use std::{
+    env, fs,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use minecraft_manager::{
+    config::{self, LoadedServer},
+    events::{MinecraftServerEventType, MinecraftServerWarning},
+    resources,
+    tasks::MinecraftServerTask,
+    MinecraftServerSettings,
+};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, ExternalPrinter, Helper,
+};
+
+const USAGE: &str = "\
+usage: mcman [--servers <dir>] [--java-path <path>]... <command>
+
+commands:
+  list                      lists the servers
+  start <server> [--quiet]  runs a server with an interactive console (--quiet only shows events, not the log)
+  stop <server>             stops a server which was started with `mcman start`
+  status [server]           shows which servers are running and their resource usage
+
+The servers directory is `--servers`, the `McDcBotServersDir` env variable or `servers`, like for mcdcbot.
+`--java-path` adds a java home or executable to pick from, like mcdcbot's `java_paths=`.";
+
+/// the server process's id, in the server's directory while `mcman start` runs it
+const PID_FILE: &str = ".mcman.pid";
+/// created by `mcman stop` in the server's directory, `mcman start` then stops the server
+const STOP_FILE: &str = ".mcman.stop";
+/// how long `mcman stop` waits for the server to exit
+const STOP_TIMEOUT: Duration = Duration::from_secs(120);
+/// how often the console checks for new events
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+/// completed at the start of a console line (online players are completed after it)
+const COMMANDS: &[&str] = &[
+    "ban",
+    "ban-ip",
+    "banlist",
+    "clear",
+    "deop",
+    "difficulty",
+    "effect",
+    "enchant",
+    "gamemode",
+    "gamerule",
+    "give",
+    "help",
+    "kick",
+    "kill",
+    "list",
+    "mspt",
+    "op",
+    "pardon",
+    "pardon-ip",
+    "reload",
+    "save-all",
+    "save-off",
+    "save-on",
+    "say",
+    "seed",
+    "setworldspawn",
+    "spawnpoint",
+    "stop",
+    "summon",
+    "tell",
+    "tellraw",
+    "tick",
+    "time",
+    "title",
+    "tp",
+    "tps",
+    "weather",
+    "whitelist",
+    "xp",
+];
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    let mut servers_dir =
+        PathBuf::from(env::var("McDcBotServersDir").unwrap_or("servers".to_owned()));
+    if let Some(i) = args.iter().position(|arg| arg == "--servers") {
+        if i + 1 >= args.len() {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+        servers_dir = PathBuf::from(args.remove(i + 1));
+        args.remove(i);
+    }
+    let mut java_paths = vec![];
+    while let Some(i) = args.iter().position(|arg| arg == "--java-path") {
+        if i + 1 >= args.len() {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+        java_paths.push(PathBuf::from(args.remove(i + 1)));
+        args.remove(i);
+    }
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let servers = match load_servers(&servers_dir, &java_paths) {
+        Ok(servers) => servers,
+        Err(e) => {
+            eprintln!(
+                "Couldn't read the servers directory ({}): {e}",
+                servers_dir.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = match args.as_slice() {
+        ["list"] => {
+            list(&servers);
+            Ok(())
+        }
+        ["start", srv] => find(&servers, srv).and_then(|server| start(server, false)),
+        ["start", srv, "--quiet"] | ["start", "--quiet", srv] => {
+            find(&servers, srv).and_then(|server| start(server, true))
+        }
+        ["stop", srv] => find(&servers, srv).and_then(stop),
+        ["status"] => {
+            for server in &servers {
+                status(server);
+            }
+            Ok(())
+        }
+        ["status", srv] => find(&servers, srv).map(status),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// reads all server config files in `dir`, like mcdcbot. Servers with errors are skipped.
+/// `java_paths` (`--java-path`) are searched for java in addition to the default locations.
+fn load_servers(dir: &Path, java_paths: &[PathBuf]) -> std::io::Result<Vec<LoadedServer>> {
+    let loaded = config::load_servers(dir)?;
+    for warning in &loaded.warnings {
+        eprintln!("[WARN/CONFIG] {warning}");
+    }
+    for error in &loaded.errors {
+        eprintln!("[ERR/CONFIG] {error}");
+    }
+    Ok(loaded
+        .servers
+        .into_iter()
+        .map(|mut server| {
+            server.settings = server.settings.with_java_search_paths(java_paths.to_vec());
+            server
+        })
+        .collect())
+}
+
+/// finds a server by its name or one of its aliases (ignoring case)
+fn find<'a>(servers: &'a [LoadedServer], srv: &str) -> Result<&'a LoadedServer, String> {
+    servers
+        .iter()
+        .find(|server| {
+            server
+                .names()
+                .any(|name| name.eq_ignore_ascii_case(srv.trim()))
+        })
+        .ok_or_else(|| format!("Can't find a server called '{srv}' (see `mcman list`)"))
+}
+
+fn list(servers: &[LoadedServer]) {
+    for server in servers {
+        let mut line = server.name.clone();
+        if !server.aliases.is_empty() {
+            line.push_str(&format!(" ({})", server.aliases.join(", ")));
+        }
+        line.push_str(&format!(" - {}", server.settings.server_type));
+        if let Some(description) = &server.description {
+            line.push_str(&format!(": {description}"));
+        }
+        if running_pid(&server.settings).is_some() {
+            line.push_str(" [running]");
+        }
+        println!("{line}");
+    }
+}
+
+/// the process id of the server, if `mcman start` is running it
+fn running_pid(settings: &MinecraftServerSettings) -> Option<u32> {
+    let pid = fs::read_to_string(Path::new(&settings.directory).join(PID_FILE))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    // the pid file is left behind if mcman was killed
+    Path::new(&format!("/proc/{pid}")).exists().then_some(pid)
+}
+
+fn status(server: &LoadedServer) {
+    let Some(pid) = running_pid(&server.settings) else {
+        println!("{}: stopped", server.name);
+        return;
+    };
+    let mut line = format!("{}: running (pid {pid})", server.name);
+    let started = fs::metadata(Path::new(&server.settings.directory).join(PID_FILE))
+        .and_then(|metadata| metadata.modified());
+    if let Ok(uptime) = started.map(|started| started.elapsed().unwrap_or_default()) {
+        line.push_str(&format!(", up for {}", resources::format_duration(uptime)));
+    }
+    if let Ok(stats) = resources::read(pid) {
+        line.push_str(&format!(
+            ", {} MiB memory (-Xmx {} MiB), {} threads, {} cpu time",
+            stats.rss / 1024 / 1024,
+            server.settings.ram_max,
+            stats.threads,
+            resources::format_duration(stats.cpu_time)
+        ));
+    }
+    println!("{line}");
+}
+
+/// asks the `mcman start` which runs the server to stop it, and waits until it has
+fn stop(server: &LoadedServer) -> Result<(), String> {
+    let Some(pid) = running_pid(&server.settings) else {
+        return Err(format!(
+            "{} isn't running (or wasn't started by mcman)",
+            server.name
+        ));
+    };
+    let stop_file = Path::new(&server.settings.directory).join(STOP_FILE);
+    fs::write(&stop_file, "")
+        .map_err(|e| format!("Couldn't create {}: {e}", stop_file.display()))?;
+    println!("Stopping {}...", server.name);
+    let start = Instant::now();
+    while Path::new(&format!("/proc/{pid}")).exists() {
+        if start.elapsed() > STOP_TIMEOUT {
+            _ = fs::remove_file(&stop_file);
+            return Err(format!(
+                "{} is still running after {}s",
+                server.name,
+                STOP_TIMEOUT.as_secs()
+            ));
+        }
+        std::thread::sleep(UPDATE_INTERVAL);
+    }
+    println!("Stopped.");
+    Ok(())
+}
+
+/// runs the server in the foreground. Lines typed into the console are run as commands,
+/// Ctrl-C or Ctrl-D stop the server (and kill it if pressed again).
+fn start(server: &LoadedServer, quiet: bool) -> Result<(), String> {
+    if let Some(pid) = running_pid(&server.settings) {
+        return Err(format!("{} is already running (pid {pid})", server.name));
+    }
+    let dir = Path::new(&server.settings.directory);
+    _ = fs::remove_file(dir.join(STOP_FILE));
+    let color = std::io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let interactive = std::io::stdin().is_terminal();
+
+    let players = Arc::new(Mutex::new(vec![]));
+    let mut editor = Editor::<ConsoleHelper, DefaultHistory>::new().map_err(|e| e.to_string())?;
+    editor.set_helper(Some(ConsoleHelper {
+        players: Arc::clone(&players),
+    }));
+    let history = env::var_os("HOME").map(|home| Path::new(&home).join(".mcman_history"));
+    if let Some(history) = &history {
+        _ = editor.load_history(history);
+    }
+    let mut print: Box<dyn FnMut(String) + Send> = match editor.create_external_printer() {
+        Ok(mut printer) => Box::new(move |text| _ = printer.print(text)),
+        // stdin isn't a terminal, so there is no prompt to print above
+        Err(_) => Box::new(|text| println!("{text}")),
+    };
+
+    let (output, lines) = mpsc::channel();
+    let mut thread = server.settings.clone().spawn_with_output(output);
+    let task_sender = thread.clone_task_sender();
+    let finished = Arc::new(AtomicBool::new(false));
+    // the server's thread is updated in the background while the console waits for input
+    let background = {
+        let finished = Arc::clone(&finished);
+        let (pid_file, stop_file) = (dir.join(PID_FILE), dir.join(STOP_FILE));
+        let mut spawned = false;
+        std::thread::spawn(move || loop {
+            // print the log before the events which were parsed from it
+            let finished_now = thread.is_finished();
+            for line in lines.try_iter() {
+                // the manager's own warnings are shown even with --quiet
+                if line.starts_with("[WARN/") {
+                    print(paint(color, YELLOW, &line));
+                } else if !quiet {
+                    print(line);
+                }
+            }
+            thread.update();
+            for event in thread.handle_new_events() {
+                if let MinecraftServerEventType::Spawned { pid } = &event.event {
+                    spawned = true;
+                    _ = fs::write(&pid_file, pid.to_string());
+                }
+                if let Some(text) = describe(&event.event, color) {
+                    print(text);
+                }
+            }
+            *players.lock().unwrap() = thread.online_players().to_vec();
+            if fs::remove_file(&stop_file).is_ok() {
+                _ = thread
+                    .clone_task_sender()
+                    .send_task(MinecraftServerTask::Stop);
+            }
+            if finished_now {
+                _ = fs::remove_file(&pid_file);
+                let reason = thread.get_stop_reason().ok();
+                // a server which couldn't be started failed like one which crashed
+                let crashed = !spawned || reason.as_ref().is_some_and(|reason| reason.is_crash());
+                let mut text = match reason {
+                    Some(reason) => reason.to_string(),
+                    None => "Stopped.".to_owned(),
+                };
+                if interactive {
+                    // the console can't be interrupted while it waits for input
+                    text.push_str(" (press Enter to exit)");
+                }
+                print(paint(color, if crashed { RED } else { DIM }, &text));
+                finished.store(true, Ordering::Relaxed);
+                if !interactive {
+                    std::process::exit(if crashed { 1 } else { 0 });
+                }
+                return crashed;
+            }
+            std::thread::sleep(UPDATE_INTERVAL);
+        })
+    };
+
+    let prompt = format!("{}> ", server.name);
+    let mut stopping = false;
+    while !finished.load(Ordering::Relaxed) {
+        let line = editor.readline(&prompt);
+        if finished.load(Ordering::Relaxed) {
+            break;
+        }
+        match line {
+            Ok(line) => {
+                let command = line.trim().trim_start_matches('/');
+                if command.is_empty() {
+                    continue;
+                }
+                _ = editor.add_history_entry(line.trim());
+                _ = task_sender.send_task(MinecraftServerTask::RunCommand(command.to_owned()));
+            }
+            // piped input ended, every further read would end immediately too
+            Err(ReadlineError::Eof) if !interactive => {
+                if !stopping {
+                    println!("Stopping the server...");
+                    _ = task_sender.send_task(MinecraftServerTask::Stop);
+                }
+                break;
+            }
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
+                if stopping {
+                    println!("Killing the server...");
+                    _ = task_sender.send_task(MinecraftServerTask::Kill);
+                } else {
+                    println!("Stopping the server... (press Ctrl-C again to kill it)");
+                    _ = task_sender.send_task(MinecraftServerTask::Stop);
+                    stopping = true;
+                }
+            }
+            Err(e) => {
+                // the server keeps running without a console, but it can still be stopped
+                eprintln!(
+                    "The console stopped working ({e}), use `mcman stop` to stop the server."
+                );
+                break;
+            }
+        }
+    }
+    if let Some(history) = &history {
+        _ = editor.save_history(history);
+    }
+    match background.join() {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(format!("{} crashed.", server.name)),
+        Err(_) => Err("The server's thread panicked.".to_owned()),
+    }
+}
+
+/// an event as a (colored) line for the console, `None` if it isn't worth showing
+fn describe(event: &MinecraftServerEventType, color: bool) -> Option<String> {
+    Some(match event {
+        MinecraftServerEventType::Warning(w) => paint(
+            color,
+            RED,
+            &match w {
+                MinecraftServerWarning::CouldNotGetServerProcessStdio => {
+                    "Couldn't get the server process's stdin/stdout".to_owned()
+                }
+                MinecraftServerWarning::CantWriteToStdin(e) => format!("Can't write to stdin: {e}"),
+            },
+        ),
+        MinecraftServerEventType::Spawned { pid } => {
+            paint(color, DIM, &format!("Started the server (pid {pid})"))
+        }
+        MinecraftServerEventType::JoinLeave(e) => paint(
+            color,
+            YELLOW,
+            &format!(
+                "{} {} the game",
+                e.username,
+                if e.joined { "joined" } else { "left" }
+            ),
+        ),
+        MinecraftServerEventType::ChatMessage(e) => format!(
+            "{} {}",
+            paint(color, GREEN, &format!("<{}>", e.author)),
+            e.message
+        ),
+        MinecraftServerEventType::Death(e) => paint(
+            color,
+            RED,
+            &if e.message.is_empty() {
+                format!("{} died", e.username)
+            } else {
+                e.message.clone()
+            },
+        ),
+        MinecraftServerEventType::Saved => paint(color, DIM, "Saved the worlds"),
+        MinecraftServerEventType::Version(version) => {
+            paint(color, DIM, &format!("Minecraft {version}"))
+        }
+        MinecraftServerEventType::Tps(tps) => paint(color, CYAN, &format!("TPS: {tps:.1}")),
+        MinecraftServerEventType::Mspt(mspt) => paint(color, CYAN, &format!("MSPT: {mspt:.1} ms")),
+        MinecraftServerEventType::CantKeepUp { ms_behind } => paint(
+            color,
+            YELLOW,
+            &format!("Can't keep up! ({ms_behind} ms behind)"),
+        ),
+        MinecraftServerEventType::Custom { kind, fields } => paint(
+            color,
+            MAGENTA,
+            &format!(
+                "{kind}: {}",
+                fields
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ),
+    })
+}
+
+fn paint(color: bool, code: &str, text: &str) -> String {
+    if color {
+        format!("{code}{text}{RESET}")
+    } else {
+        text.to_owned()
+    }
+}
+
+/// completes commands at the start of the line and online players after that
+struct ConsoleHelper {
+    players: Arc<Mutex<Vec<String>>>,
+}
+impl Completer for ConsoleHelper {
+    type Candidate = String;
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        if start == 0 || line[..start].trim().is_empty() {
+            // with or without `/`
+            let (start, word) = match word.strip_prefix('/') {
+                Some(word) => (start + 1, word),
+                None => (start, word),
+            };
+            let commands = COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(word))
+                .map(|command| format!("{command} "))
+                .collect();
+            Ok((start, commands))
+        } else {
+            let word = word.to_lowercase();
+            let players = self
+                .players
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|player| player.to_lowercase().starts_with(&word))
+                .cloned()
+                .collect();
+            Ok((start, players))
+        }
+    }
+}
+impl Hinter for ConsoleHelper {
+    type Hint = String;
+}
+impl Highlighter for ConsoleHelper {}
+impl Validator for ConsoleHelper {}
+impl Helper for ConsoleHelper {}
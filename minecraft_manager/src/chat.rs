@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ChatMessage {
     pub author: String,
     pub message: String,
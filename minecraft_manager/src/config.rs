@@ -0,0 +1,498 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    custom_parser::LineParserProtocol, java::JvmPreset, parse_line::LineRule,
+    MinecraftServerSettings, MinecraftServerSettingsFromLinesError, MinecraftServerType,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// an error or warning in a config file
+#[derive(Debug)]
+pub struct ConfigIssue {
+    pub file: PathBuf,
+    /// 1-based line number, if the issue belongs to a specific line
+    pub line: Option<usize>,
+    pub message: String,
+}
+impl ConfigIssue {
+    pub fn new(file: &Path, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_owned(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+impl std::error::Error for ConfigIssue {}
+
+/// a loaded config and the warnings (i.e. unknown keys) from loading it
+pub type Loaded<T> = Result<(T, Vec<ConfigIssue>), ConfigIssue>;
+
+/// if the file should be read as toml (instead of the older `key=value` line format)
+pub fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+/// parses a toml file. Unknown keys are returned as warnings.
+pub fn from_toml<T: DeserializeOwned>(path: &Path, content: &str) -> Loaded<T> {
+    let mut unknown = vec![];
+    let value = serde_ignored::deserialize(toml::Deserializer::new(content), |key| {
+        // `?` is where the key went through an `Option`
+        let key = key.to_string();
+        unknown.push(
+            key.split('.')
+                .filter(|k| *k != "?")
+                .collect::<Vec<_>>()
+                .join("."),
+        )
+    })
+    .map_err(|e: toml::de::Error| {
+        ConfigIssue::new(
+            path,
+            e.span().map(|span| line_at(content, span.start)),
+            e.message(),
+        )
+    })?;
+    let warnings = unknown
+        .into_iter()
+        .map(|key| {
+            let line = find_key(content, key.rsplit('.').next().unwrap_or(&key));
+            ConfigIssue::new(path, line, format!("unknown key `{key}`"))
+        })
+        .collect();
+    Ok((value, warnings))
+}
+
+/// the lines of a file in the `key=value` format, with their line numbers.
+/// Empty lines and comments (`#`) are skipped.
+pub fn lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !(line.trim().is_empty() || line.trim_start().starts_with('#')))
+}
+
+/// `true` or `false`. Anything else is an error (instead of silently being `true`).
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        other => Err(format!("expected `true` or `false`, found `{other}`")),
+    }
+}
+
+/// the line of the first `key = ...` or `key=...` in a file
+pub fn find_key(content: &str, key: &str) -> Option<usize> {
+    content
+        .lines()
+        .position(|line| {
+            line.trim_start()
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with('='))
+        })
+        .map(|i| i + 1)
+}
+
+fn line_at(content: &str, byte: usize) -> usize {
+    content[..byte.min(content.len())].matches('\n').count() + 1
+}
+
+/// a server's config file
+pub struct ServerConfig {
+    pub name: Option<String>,
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    pub settings: MinecraftServerSettings,
+}
+
+/// a server from the servers directory (see [`load_servers`])
+pub struct LoadedServer {
+    /// the config file's name. Identifies the server when reloading.
+    pub file: String,
+    /// `name=`, or the file name
+    pub name: String,
+    /// other names for the server which can be used in commands (`aliases=`, comma separated)
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    pub settings: MinecraftServerSettings,
+}
+impl LoadedServer {
+    /// the server's name and aliases
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+}
+
+#[derive(Default)]
+pub struct LoadedServers {
+    pub servers: Vec<LoadedServer>,
+    pub warnings: Vec<ConfigIssue>,
+    /// files which couldn't be loaded
+    pub errors: Vec<ConfigIssue>,
+}
+
+/// reads all server config files in `dir` (used by both mcdcbot and mcman). Hidden files are ignored.
+/// Names and aliases have to be unique, servers without `aliases=` get a short alias if possible
+/// (a warning is added if they can't).
+pub fn load_servers(dir: &Path) -> std::io::Result<LoadedServers> {
+    let mut o = LoadedServers::default();
+    let mut paths = vec![];
+    for file in std::fs::read_dir(dir)? {
+        paths.push(file?.path());
+    }
+    // so that the same server wins if two use the same name
+    paths.sort();
+    // all names and aliases (lowercase)
+    let mut taken = HashSet::new();
+    for path in paths {
+        let file = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        if file.starts_with('.') {
+            continue;
+        }
+        let (config, warnings) = match server_from_file(&path) {
+            Ok(v) => v,
+            Err(e) => {
+                o.errors.push(e);
+                continue;
+            }
+        };
+        o.warnings.extend(warnings);
+        let name = config.name.unwrap_or_else(|| default_server_name(&path));
+        let mut aliases: Vec<String> = vec![];
+        for alias in config.aliases {
+            let alias = alias.trim().to_owned();
+            if !alias.is_empty() && !alias.eq_ignore_ascii_case(&name) && !aliases.contains(&alias)
+            {
+                aliases.push(alias);
+            }
+        }
+        let server = LoadedServer {
+            file,
+            name,
+            aliases,
+            description: config.description,
+            settings: config.settings,
+        };
+        if let Some(used) = server.names().find(|n| taken.contains(&n.to_lowercase())) {
+            o.errors.push(ConfigIssue::new(
+                &path,
+                None,
+                format!("`{used}` is already the name or an alias of another server"),
+            ));
+            continue;
+        }
+        taken.extend(server.names().map(str::to_lowercase));
+        o.servers.push(server);
+    }
+    for server in &mut o.servers {
+        // a single letter name is already as short as an alias
+        if server.aliases.is_empty() && server.name.trim().chars().count() > 1 {
+            match default_alias(&server.name, &taken) {
+                Some(alias) => {
+                    taken.insert(alias.to_lowercase());
+                    server.aliases.push(alias);
+                }
+                None => o.warnings.push(ConfigIssue::new(
+                    &dir.join(&server.file),
+                    None,
+                    format!(
+                        "`{}` gets no short alias because its first letter and initials are already used, set `aliases=`",
+                        server.name
+                    ),
+                )),
+            }
+        }
+    }
+    Ok(o)
+}
+
+/// a short alias for a server which doesn't have any: its lowercase first letter or,
+/// if that is taken, its initials. `taken` are all (lowercase) names and aliases.
+pub fn default_alias(name: &str, taken: &HashSet<String>) -> Option<String> {
+    let first = name.trim().chars().next()?.to_lowercase().to_string();
+    if !taken.contains(&first) {
+        return Some(first);
+    }
+    let initials = name
+        .split_whitespace()
+        .filter_map(|v| v.chars().next().map(|c| c.to_uppercase()))
+        .flatten()
+        .collect::<String>();
+    if !initials.is_empty() && !taken.contains(&initials.to_lowercase()) {
+        Some(initials)
+    } else {
+        None
+    }
+}
+
+/// the name of a server without `name=`: its file's name (without `.toml`)
+pub fn default_server_name(path: &Path) -> String {
+    if is_toml(path) {
+        path.file_stem()
+    } else {
+        path.file_name()
+    }
+    .unwrap_or_default()
+    .to_string_lossy()
+    .into_owned()
+}
+
+/// loads a server's config file, either toml or the older `key=value` format
+/// (see [`MinecraftServerSettings::from_lines`], plus the top-level keys `name`, `aliases` and `description`)
+pub fn server_from_file(path: &Path) -> Loaded<ServerConfig> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| ConfigIssue::new(path, None, format!("couldn't read the file: {e}")))?;
+    if is_toml(path) {
+        let (raw, warnings) = from_toml::<RawServer>(path, &content)?;
+        let mut line_rules = vec![];
+        for (i, rule) in raw.rule.into_iter().enumerate() {
+            line_rules.push(LineRule::new(&rule.event, &rule.regex).map_err(|e| {
+                // the i-th `[[rule]]` table
+                let line = content
+                    .lines()
+                    .enumerate()
+                    .filter(|(_, line)| line.trim() == "[[rule]]")
+                    .nth(i)
+                    .map(|(line, _)| line + 1);
+                ConfigIssue::new(path, line, format!("invalid rule: {e}"))
+            })?);
+        }
+        let server_type = match (raw.server_type.as_str(), raw.custom) {
+            ("custom", Some(custom)) if custom.parser.is_none() && line_rules.is_empty() => {
+                return Err(ConfigIssue::new(
+                    path,
+                    find_key(&content, "type"),
+                    MinecraftServerSettingsFromLinesError::CustomServerTypeMissingLineParser
+                        .to_string(),
+                ))
+            }
+            ("custom", Some(custom)) => MinecraftServerType::Custom {
+                line_parser_protocol: match custom.protocol {
+                    Some(protocol) => {
+                        LineParserProtocol::from_name(&protocol).ok_or_else(|| {
+                            ConfigIssue::new(
+                                path,
+                                find_key(&content, "protocol"),
+                                MinecraftServerSettingsFromLinesError::UnknownLineParserProtocol(
+                                    protocol,
+                                )
+                                .to_string(),
+                            )
+                        })?
+                    }
+                    None => LineParserProtocol::default(),
+                },
+                name: custom.name,
+                line_parser: custom.parser,
+                line_parser_proc: Default::default(),
+                command_override: custom.command_override,
+            },
+            ("custom", None) => {
+                return Err(ConfigIssue::new(
+                    path,
+                    find_key(&content, "type"),
+                    "`type = \"custom\"` needs a `[custom]` table",
+                ))
+            }
+            (other, _) => MinecraftServerType::from_config_name(other).ok_or_else(|| {
+                ConfigIssue::new(
+                    path,
+                    find_key(&content, "type"),
+                    format!("unknown server type `{other}`"),
+                )
+            })?,
+        };
+        let jvm_preset = match raw.jvm_preset {
+            Some(name) => Some(JvmPreset::from_name(&name).ok_or_else(|| {
+                ConfigIssue::new(
+                    path,
+                    find_key(&content, "jvm_preset"),
+                    MinecraftServerSettingsFromLinesError::UnknownJvmPreset(name).to_string(),
+                )
+            })?),
+            None => None,
+        };
+        let mut settings = MinecraftServerSettings::new(server_type, raw.dir, raw.exec)
+            .with_java_cmd(raw.java_cmd)
+            .with_minecraft_version(raw.version)
+            .with_jvm_preset(jvm_preset)
+            .with_jvm_args(raw.jvm_args)
+            .with_server_args(raw.server_args)
+            .with_env(raw.env.into_iter().collect())
+            .with_line_rules(line_rules);
+        if let Some(ram) = raw.ram {
+            settings = settings.with_ram(ram);
+        }
+        settings.ram_min = raw.ram_min.unwrap_or(settings.ram_min);
+        settings.ram_max = raw.ram_max.unwrap_or(settings.ram_max);
+        if settings.ram_min > settings.ram_max {
+            return Err(ConfigIssue::new(
+                path,
+                find_key(&content, "ram_min"),
+                MinecraftServerSettingsFromLinesError::RamMinAboveMax(
+                    settings.ram_min,
+                    settings.ram_max,
+                )
+                .to_string(),
+            ));
+        }
+        let config = ServerConfig {
+            name: raw.name,
+            aliases: raw.aliases,
+            description: raw.description,
+            settings,
+        };
+        Ok((config, warnings))
+    } else {
+        let mut name = None;
+        let mut aliases = vec![];
+        let mut description = None;
+        let mut line = None;
+        // the bot's keys are removed, everything else is for `from_lines`
+        let mut lines = lines(&content)
+            .filter(|(_, text)| match text.split_once('=') {
+                Some(("name", value)) => {
+                    name = Some(value.trim().to_owned());
+                    false
+                }
+                Some(("aliases", value)) => {
+                    aliases = value
+                        .split(',')
+                        .map(|alias| alias.trim().to_owned())
+                        .filter(|alias| !alias.is_empty())
+                        .collect();
+                    false
+                }
+                Some(("description", value)) => {
+                    description = Some(value.trim().to_owned());
+                    false
+                }
+                _ => true,
+            })
+            .map(|(i, text)| {
+                line = Some(i);
+                text
+            });
+        let result = MinecraftServerSettings::from_lines(&mut lines);
+        drop(lines);
+        match result {
+            Ok(settings) => Ok((
+                ServerConfig {
+                    name,
+                    aliases,
+                    description,
+                    settings,
+                },
+                vec![],
+            )),
+            // these are only noticed at the end of the file
+            Err(
+                e @ (MinecraftServerSettingsFromLinesError::MissingServerType
+                | MinecraftServerSettingsFromLinesError::MissingDirectory
+                | MinecraftServerSettingsFromLinesError::MissingExecutable
+                | MinecraftServerSettingsFromLinesError::CustomServerTypeMissingName
+                | MinecraftServerSettingsFromLinesError::CustomServerTypeMissingLineParser
+                | MinecraftServerSettingsFromLinesError::RamMinAboveMax(..)),
+            ) => Err(ConfigIssue::new(path, None, e.to_string())),
+            Err(e) => Err(ConfigIssue::new(path, line, e.to_string())),
+        }
+    }
+}
+
+/// a server's toml config, before it is checked
+#[derive(Deserialize)]
+struct RawServer {
+    name: Option<String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    description: Option<String>,
+    #[serde(rename = "type")]
+    server_type: String,
+    dir: String,
+    exec: String,
+    ram: Option<u32>,
+    ram_min: Option<u32>,
+    ram_max: Option<u32>,
+    java_cmd: Option<String>,
+    version: Option<String>,
+    jvm_preset: Option<String>,
+    #[serde(default)]
+    jvm_args: Vec<String>,
+    #[serde(default)]
+    server_args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// `[[rule]]` tables
+    #[serde(default)]
+    rule: Vec<RawLineRule>,
+    custom: Option<RawCustomType>,
+}
+#[derive(Deserialize)]
+struct RawCustomType {
+    name: String,
+    parser: Option<String>,
+    /// `legacy` (default) or `json`
+    protocol: Option<String>,
+    command_override: Option<String>,
+}
+#[derive(Deserialize)]
+struct RawLineRule {
+    event: String,
+    regex: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_alias_collisions_are_warned_about() {
+        let dir = std::env::temp_dir().join(format!("mcman-aliases-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, aliases) in [
+            ("a.toml", "[\"m\"]"),
+            ("b.toml", "[\"MS\"]"),
+            ("c.toml", "[]"),
+        ] {
+            std::fs::write(
+                dir.join(file),
+                format!("type = \"vanilla-papermc\"\ndir = \".\"\nexec = \"paper.jar\"\naliases = {aliases}\n"),
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            dir.join("d.toml"),
+            "name = \"My Server\"\ntype = \"vanilla-papermc\"\ndir = \".\"\nexec = \"paper.jar\"\n",
+        )
+        .unwrap();
+        let loaded = load_servers(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let loaded = loaded.unwrap();
+        assert!(loaded.errors.is_empty());
+        // `c` is a single letter and needs no alias
+        assert_eq!(loaded.warnings.len(), 1);
+        assert!(loaded.warnings[0].file.ends_with("d.toml"));
+        assert!(loaded
+            .servers
+            .iter()
+            .all(|server| server.name != "My Server" || server.aliases.is_empty()));
+    }
+}
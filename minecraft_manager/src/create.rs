@@ -0,0 +1,80 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// where the files for a new server come from
+pub enum ServerSource {
+    /// a directory which is copied as a whole. It has to contain the server's executable.
+    Template(PathBuf),
+    /// a server jar which is copied into an otherwise empty directory
+    Jar(PathBuf),
+}
+
+impl ServerSource {
+    /// looks for a template directory `<templates_dir>/<name>` first,
+    /// then for a jar `<jar_cache_dir>/<name>.jar`.
+    pub fn find(
+        name: &str,
+        templates_dir: Option<&Path>,
+        jar_cache_dir: Option<&Path>,
+    ) -> Option<Self> {
+        if let Some(dir) = templates_dir.map(|dir| dir.join(name)) {
+            if dir.is_dir() {
+                return Some(Self::Template(dir));
+            }
+        }
+        if let Some(jar) = jar_cache_dir.map(|dir| dir.join(format!("{name}.jar"))) {
+            if jar.is_file() {
+                return Some(Self::Jar(jar));
+            }
+        }
+        None
+    }
+}
+
+/// creates `directory` and fills it from `source`. `executable` is the name the jar is copied to.
+/// Fails if `directory` already exists.
+pub fn create_server_dir(
+    directory: &Path,
+    source: &ServerSource,
+    executable: &str,
+) -> io::Result<()> {
+    if directory.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", directory.display()),
+        ));
+    }
+    match source {
+        ServerSource::Template(template) => copy_dir(template, directory),
+        ServerSource::Jar(jar) => {
+            std::fs::create_dir_all(directory)?;
+            std::fs::copy(jar, directory.join(executable))?;
+            Ok(())
+        }
+    }
+}
+
+/// writes `eula=true` to the server's `eula.txt`.
+/// Only do this if the user actually agreed to the EULA (https://aka.ms/MinecraftEULA)!
+pub fn accept_eula(directory: &Path) -> io::Result<()> {
+    std::fs::write(
+        directory.join("eula.txt"),
+        "# accepted through minecraft_manager (https://aka.ms/MinecraftEULA)\neula=true\n",
+    )
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
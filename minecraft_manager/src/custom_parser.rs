@@ -0,0 +1,588 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    process::{self, Child, ChildStdin, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::ChatMessage,
+    events::{DeathEvent, JoinLeaveEvent, MinecraftServerEventType},
+    parse_line::{ParseError, ParseOutput},
+    warn,
+};
+
+/// the newest version of the json protocol
+pub const PROTOCOL_VERSION: u32 = 1;
+/// how long a parser has to answer the handshake (it may take a while to start)
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// how long a parser has to answer a line. Parsers which don't are restarted.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+/// how long lines are dropped after the parser first fails. Doubled for each failure in a row.
+const RESTART_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(300);
+/// after this many failures in a row, the parser isn't started again until the server restarts
+const MAX_FAILURES: u32 = 8;
+
+/// how the manager talks to a custom line parser (`protocol=` in the custom type)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineParserProtocol {
+    /// each log line is answered with one line: `c<author>` followed by the message on a second line,
+    /// `j<player>`, `l<player>`, `ec<error>`, or anything else (usually an empty line) if there is no event
+    #[default]
+    Legacy,
+    /// json objects, one per line. After a handshake (`{"type":"hello","protocol":1}` in both directions),
+    /// each log line is sent as `{"type":"line","line":"..."}` and answered with any number of events
+    /// (`chat`, `join`, `leave`, `death`, `custom`, `error`) followed by `{"type":"end"}`
+    Json,
+}
+impl LineParserProtocol {
+    pub const ALL: [Self; 2] = [Self::Legacy, Self::Json];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|protocol| protocol.name() == name.trim())
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Legacy => "legacy",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// what the manager sends to a json parser
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Request<'a> {
+    /// sent once after the parser was started, with the newest protocol version the manager supports
+    Hello { protocol: u32 },
+    /// a line from the server's stdout
+    Line { line: &'a str },
+}
+/// what a json parser sends back
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Response {
+    /// the answer to [`Request::Hello`], with the protocol version the parser uses
+    Hello {
+        protocol: u32,
+    },
+    Chat {
+        player: String,
+        message: String,
+    },
+    Join {
+        player: String,
+    },
+    Leave {
+        player: String,
+    },
+    Death {
+        player: String,
+        message: Option<String>,
+    },
+    Error {
+        message: String,
+    },
+    /// a [`MinecraftServerEventType::Custom`] event. Fields which aren't strings are converted to json.
+    Custom {
+        kind: String,
+        #[serde(default)]
+        fields: BTreeMap<String, serde_json::Value>,
+    },
+    /// there are no more events for this line
+    End,
+}
+
+/// a running custom line parser (see [`crate::MinecraftServerType::Custom`])
+pub struct LineParserProcess {
+    child: Child,
+    stdin: ChildStdin,
+    /// the parser's stdout lines. They are read on another thread so waiting for them can time out.
+    stdout: mpsc::Receiver<String>,
+}
+
+impl LineParserProcess {
+    /// starts the parser and, for the json protocol, does the handshake
+    pub fn spawn(command: &str, protocol: LineParserProtocol) -> Result<Self, String> {
+        let mut child = process::Command::new(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Can't spawn command '{command}': {e}"))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            _ = child.kill();
+            return Err("No stdin/stdout handles!".to_owned());
+        };
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut o = Self {
+            child,
+            stdin,
+            stdout: receiver,
+        };
+        if protocol == LineParserProtocol::Json {
+            if let Err(e) = o.handshake() {
+                o.kill();
+                return Err(e);
+            }
+        }
+        Ok(o)
+    }
+    fn handshake(&mut self) -> Result<(), String> {
+        self.send(&Request::Hello {
+            protocol: PROTOCOL_VERSION,
+        })?;
+        match self.recv_json(Instant::now() + HANDSHAKE_TIMEOUT)? {
+            Response::Hello { protocol } if (1..=PROTOCOL_VERSION).contains(&protocol) => Ok(()),
+            Response::Hello { protocol } => Err(format!(
+                "The parser uses protocol version {protocol}, only versions up to {PROTOCOL_VERSION} are supported"
+            )),
+            _ => Err("The parser didn't answer the handshake with `hello`".to_owned()),
+        }
+    }
+
+    pub fn has_exited(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(Some(_)))
+    }
+    pub fn kill(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{line}").map_err(|e| format!("Can't write to stdin: {e}"))
+    }
+    fn send(&mut self, request: &Request) -> Result<(), String> {
+        let json = serde_json::to_string(request).map_err(|e| e.to_string())?;
+        self.write_line(&json)
+    }
+    /// the parser's next line, if it arrives before `deadline`
+    fn recv(&self, deadline: Instant) -> Result<String, String> {
+        match self
+            .stdout
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err("The parser didn't answer in time".to_owned()),
+            Err(RecvTimeoutError::Disconnected) => Err("The parser closed its stdout".to_owned()),
+        }
+    }
+    fn recv_json(&self, deadline: Instant) -> Result<Response, String> {
+        let line = self.recv(deadline)?;
+        serde_json::from_str(&line).map_err(|e| format!("Invalid response `{line}`: {e}"))
+    }
+
+    /// sends a log line to the parser and returns its answer.
+    /// Fails if the parser can't be used anymore (i.e. it didn't answer in time).
+    /// Warnings are sent to `output` if it is set (see [`crate::MinecraftServerSettings::spawn_with_output`]).
+    fn parse(
+        &mut self,
+        protocol: LineParserProtocol,
+        line: &str,
+        output: Option<&mpsc::Sender<String>>,
+    ) -> Result<ParseOutput, String> {
+        // would be mistaken for the answer to this line
+        let stale = self.stdout.try_iter().count();
+        if stale > 0 {
+            warn(
+                output,
+                format!(
+                    "[WARN/CUSTOM-LINE-PARSER] Ignored {stale} unexpected line(s) from the parser."
+                ),
+            );
+        }
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        match protocol {
+            LineParserProtocol::Legacy => {
+                self.write_line(line)?;
+                self.parse_legacy(deadline)
+            }
+            LineParserProtocol::Json => {
+                self.send(&Request::Line { line })?;
+                self.parse_json(deadline, output)
+            }
+        }
+    }
+    fn parse_legacy(&self, deadline: Instant) -> Result<ParseOutput, String> {
+        let buf = self.recv(deadline)?;
+        let buf = buf.trim_end_matches('\r');
+        let Some(kind) = buf.chars().next() else {
+            return Ok(ParseOutput::Nothing);
+        };
+        let rest = &buf[kind.len_utf8()..];
+        Ok(match kind {
+            'c' => ParseOutput::Event(MinecraftServerEventType::ChatMessage(ChatMessage {
+                author: rest.to_owned(),
+                message: self.recv(deadline)?.trim_end_matches('\r').to_owned(),
+            })),
+            'j' | 'l' => ParseOutput::Event(MinecraftServerEventType::JoinLeave(JoinLeaveEvent {
+                username: rest.to_owned(),
+                joined: kind == 'j',
+            })),
+            'e' => ParseOutput::Error(ParseError::Custom(
+                rest.strip_prefix('c').unwrap_or_default().to_owned(),
+            )),
+            _ => ParseOutput::Nothing,
+        })
+    }
+    fn parse_json(
+        &self,
+        deadline: Instant,
+        output: Option<&mpsc::Sender<String>>,
+    ) -> Result<ParseOutput, String> {
+        let mut events = vec![];
+        let mut error = None;
+        loop {
+            let line = self.recv(deadline)?;
+            // one broken line doesn't make the rest of the answer unusable
+            let response = match serde_json::from_str(&line) {
+                Ok(response) => response,
+                Err(e) => {
+                    warn(
+                        output,
+                        format!("[WARN/CUSTOM-LINE-PARSER] Invalid response `{line}`: {e}"),
+                    );
+                    continue;
+                }
+            };
+            let event = match response {
+                Response::End => break,
+                Response::Hello { .. } => {
+                    return Err("The parser sent `hello` after the handshake".to_owned())
+                }
+                Response::Error { message } => {
+                    error = Some(message);
+                    continue;
+                }
+                Response::Chat { player, message } => {
+                    MinecraftServerEventType::ChatMessage(ChatMessage {
+                        author: player,
+                        message,
+                    })
+                }
+                Response::Join { player } => MinecraftServerEventType::JoinLeave(JoinLeaveEvent {
+                    username: player,
+                    joined: true,
+                }),
+                Response::Leave { player } => MinecraftServerEventType::JoinLeave(JoinLeaveEvent {
+                    username: player,
+                    joined: false,
+                }),
+                Response::Custom { kind, fields } => MinecraftServerEventType::Custom {
+                    kind,
+                    fields: fields
+                        .into_iter()
+                        .map(|(key, value)| match value {
+                            serde_json::Value::String(value) => (key, value),
+                            value => (key, value.to_string()),
+                        })
+                        .collect(),
+                },
+                Response::Death { player, message } => {
+                    MinecraftServerEventType::Death(DeathEvent {
+                        message: message.unwrap_or_else(|| format!("{player} died")),
+                        username: player,
+                    })
+                }
+            };
+            events.push(event);
+        }
+        Ok(if !events.is_empty() {
+            ParseOutput::Events(events)
+        } else if let Some(error) = error {
+            ParseOutput::Error(ParseError::Custom(error))
+        } else {
+            ParseOutput::Nothing
+        })
+    }
+}
+
+/// a custom line parser (see [`crate::MinecraftServerType::Custom`]), which is started when it's needed.
+/// After the parser fails, lines are dropped for a while, so a broken parser can't hold up the server's thread.
+#[derive(Default)]
+pub struct LineParser {
+    proc: Option<LineParserProcess>,
+    /// failures since the parser last answered a line
+    failures: u32,
+    /// lines are dropped until then
+    retry_at: Option<Instant>,
+    /// where warnings are sent instead of stderr (see [`Self::set_output`])
+    output: Option<mpsc::Sender<String>>,
+}
+
+impl LineParser {
+    /// parses the line with the custom parser, which is started if it isn't running.
+    /// If the parser fails (i.e. it doesn't answer in time), it is killed and started again after a backoff.
+    pub fn parse_line(
+        &mut self,
+        command: &str,
+        protocol: LineParserProtocol,
+        line: &str,
+    ) -> ParseOutput {
+        if self.failures >= MAX_FAILURES
+            || self
+                .retry_at
+                .is_some_and(|retry_at| Instant::now() < retry_at)
+        {
+            return ParseOutput::Nothing;
+        }
+        if self.proc.as_mut().is_none_or(|proc| proc.has_exited()) {
+            match LineParserProcess::spawn(command, protocol) {
+                Ok(proc) => self.proc = Some(proc),
+                Err(e) => {
+                    self.failed(&e);
+                    return ParseOutput::Nothing;
+                }
+            }
+        }
+        let Some(proc) = &mut self.proc else {
+            return ParseOutput::Nothing;
+        };
+        match proc.parse(
+            protocol,
+            line.trim_end_matches(['\n', '\r']),
+            self.output.as_ref(),
+        ) {
+            Ok(output) => {
+                self.failures = 0;
+                output
+            }
+            Err(e) => {
+                self.failed(&e);
+                ParseOutput::Nothing
+            }
+        }
+    }
+    fn failed(&mut self, error: &str) {
+        self.kill();
+        self.failures += 1;
+        if self.failures >= MAX_FAILURES {
+            warn(
+                self.output.as_ref(),
+                format!("[WARN/CUSTOM-LINE-PARSER] {error}. The parser failed {MAX_FAILURES} times in a row, not restarting it until the server restarts."),
+            );
+            return;
+        }
+        let backoff = RESTART_BACKOFF
+            .saturating_mul(1 << (self.failures - 1))
+            .min(MAX_RESTART_BACKOFF);
+        self.retry_at = Some(Instant::now() + backoff);
+        warn(
+            self.output.as_ref(),
+            format!(
+                "[WARN/CUSTOM-LINE-PARSER] {error}, ignoring lines for {}s before restarting it.",
+                backoff.as_secs()
+            ),
+        );
+    }
+    /// sends the parser's warnings to `output` instead of stderr, like the server's log lines
+    pub fn set_output(&mut self, output: Option<mpsc::Sender<String>>) {
+        self.output = output;
+    }
+    /// stops the parser process, if it is running
+    pub fn kill(&mut self) {
+        if let Some(mut proc) = self.proc.take() {
+            proc.kill();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// an executable shell script which is removed when it's dropped
+    struct Script(PathBuf);
+    impl Script {
+        fn new(name: &str, body: &str) -> Self {
+            use std::os::unix::fs::PermissionsExt;
+            let path =
+                std::env::temp_dir().join(format!("mcman-parser-{}-{name}.sh", std::process::id()));
+            std::fs::write(&path, format!("#!/bin/sh\n{body}")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            Self(path)
+        }
+        fn command(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+    impl Drop for Script {
+        fn drop(&mut self) {
+            _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    const JSON_HELLO: &str = "read hello\necho '{\"type\":\"hello\",\"protocol\":1}'\n";
+
+    #[test]
+    fn json_handshake_and_events() {
+        let script = Script::new(
+            "json",
+            &format!(
+                "{JSON_HELLO}while read line; do
+  echo '{{\"type\":\"join\",\"player\":\"Steve\"}}'
+  echo 'not json'
+  echo '{{\"type\":\"chat\",\"player\":\"Steve\",\"message\":\"hi\"}}'
+  echo '{{\"type\":\"end\"}}'
+done
+"
+            ),
+        );
+        let mut proc =
+            LineParserProcess::spawn(script.command(), LineParserProtocol::Json).unwrap();
+        let (output, warnings) = mpsc::channel();
+        let parsed = proc.parse(LineParserProtocol::Json, "a line", Some(&output));
+        proc.kill();
+        let Ok(ParseOutput::Events(events)) = parsed else {
+            panic!("expected events");
+        };
+        assert!(matches!(
+            &events[..],
+            [
+                MinecraftServerEventType::JoinLeave(JoinLeaveEvent { username, joined: true }),
+                MinecraftServerEventType::ChatMessage(ChatMessage { author, message }),
+            ] if username == "Steve" && author == "Steve" && message == "hi"
+        ));
+        // the broken line is skipped with a warning
+        let warning = warnings.try_recv().unwrap();
+        assert!(warning.contains("Invalid response `not json`"), "{warning}");
+    }
+
+    #[test]
+    fn json_handshake_checks_the_version() {
+        let script = Script::new(
+            "version",
+            "read hello\necho '{\"type\":\"hello\",\"protocol\":2}'\ncat > /dev/null\n",
+        );
+        let Err(e) = LineParserProcess::spawn(script.command(), LineParserProtocol::Json) else {
+            panic!("the handshake should fail");
+        };
+        assert!(e.contains("protocol version 2"), "{e}");
+        let script = Script::new("no-hello", "read hello\necho '{\"type\":\"end\"}'\n");
+        assert!(LineParserProcess::spawn(script.command(), LineParserProtocol::Json).is_err());
+    }
+
+    #[test]
+    fn silent_parser_times_out() {
+        let script = Script::new("silent", "cat > /dev/null\n");
+        let mut proc =
+            LineParserProcess::spawn(script.command(), LineParserProtocol::Legacy).unwrap();
+        let start = Instant::now();
+        let parsed = proc.parse(LineParserProtocol::Legacy, "a line", None);
+        proc.kill();
+        assert!(matches!(parsed, Err(e) if e.contains("didn't answer in time")));
+        assert!(start.elapsed() < RESPONSE_TIMEOUT + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn legacy_answers() {
+        let script = Script::new(
+            "legacy",
+            "while read line; do
+  case \"$line\" in
+    chat) printf 'cSteve\\nhello there\\n';;
+    join) echo jSteve;;
+    leave) echo lAlex;;
+    error) echo ecbroken;;
+    twice) printf 'jSteve\\njAlex\\n';;
+    *) echo;;
+  esac
+done
+",
+        );
+        let mut proc =
+            LineParserProcess::spawn(script.command(), LineParserProtocol::Legacy).unwrap();
+        let (output, warnings) = mpsc::channel();
+        let mut parse = |line| {
+            proc.parse(LineParserProtocol::Legacy, line, Some(&output))
+                .unwrap()
+        };
+        assert!(matches!(
+            parse("chat"),
+            ParseOutput::Event(MinecraftServerEventType::ChatMessage(ChatMessage { author, message }))
+                if author == "Steve" && message == "hello there"
+        ));
+        assert!(matches!(
+            parse("join"),
+            ParseOutput::Event(MinecraftServerEventType::JoinLeave(JoinLeaveEvent { username, joined: true }))
+                if username == "Steve"
+        ));
+        assert!(matches!(
+            parse("leave"),
+            ParseOutput::Event(MinecraftServerEventType::JoinLeave(JoinLeaveEvent { username, joined: false }))
+                if username == "Alex"
+        ));
+        assert!(matches!(
+            parse("error"),
+            ParseOutput::Error(ParseError::Custom(e)) if e == "broken"
+        ));
+        assert!(matches!(parse("other"), ParseOutput::Nothing));
+        // the second answer isn't mistaken for the answer to the next line
+        assert!(matches!(parse("twice"), ParseOutput::Event(_)));
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(matches!(parse("other"), ParseOutput::Nothing));
+        proc.kill();
+        let warning = warnings.try_recv().unwrap();
+        assert!(warning.contains("Ignored 1 unexpected line"), "{warning}");
+    }
+
+    #[test]
+    fn failing_parser_backs_off() {
+        let (output, warnings) = mpsc::channel();
+        let mut parser = LineParser::default();
+        parser.set_output(Some(output));
+        let missing = "/nonexistent/mcman-parser";
+        let parse = |parser: &mut LineParser| {
+            matches!(
+                parser.parse_line(missing, LineParserProtocol::Legacy, "a line"),
+                ParseOutput::Nothing
+            )
+        };
+        assert!(parse(&mut parser));
+        assert_eq!(parser.failures, 1);
+        let retry_at = parser.retry_at.unwrap();
+        assert!(retry_at > Instant::now() + RESTART_BACKOFF - Duration::from_secs(1));
+        assert!(warnings
+            .try_recv()
+            .unwrap()
+            .contains("ignoring lines for 5s"));
+        // lines are dropped without trying again
+        assert!(parse(&mut parser));
+        assert_eq!(parser.failures, 1);
+        assert!(warnings.try_recv().is_err());
+        // the backoff doubles
+        parser.retry_at = Some(Instant::now());
+        assert!(parse(&mut parser));
+        assert_eq!(parser.failures, 2);
+        assert!(warnings
+            .try_recv()
+            .unwrap()
+            .contains("ignoring lines for 10s"));
+        // and the parser is given up on eventually
+        parser.failures = MAX_FAILURES - 1;
+        parser.retry_at = None;
+        assert!(parse(&mut parser));
+        assert_eq!(parser.failures, MAX_FAILURES);
+        assert!(warnings.try_recv().unwrap().contains("not restarting it"));
+        parser.retry_at = None;
+        assert!(parse(&mut parser));
+        assert_eq!(parser.failures, MAX_FAILURES);
+    }
+}
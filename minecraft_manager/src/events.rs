@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use crate::chat::ChatMessage;
 
 #[derive(Debug)]
@@ -9,8 +11,31 @@ pub struct MinecraftServerEvent {
 #[derive(Debug)]
 pub enum MinecraftServerEventType {
     Warning(MinecraftServerWarning),
+    /// the server process was started
+    Spawned {
+        pid: u32,
+    },
     JoinLeave(JoinLeaveEvent),
     ChatMessage(ChatMessage),
+    /// a player died (only from [`crate::parse_line::LineRule`]s)
+    Death(DeathEvent),
+    /// the worlds were saved (`Saved the game`, i.e. after `save-all`)
+    Saved,
+    /// the minecraft version the server is running (from `Starting minecraft server version X`)
+    Version(String),
+    /// ticks per second over the last minute (from paper's `tps`)
+    Tps(f32),
+    /// average milliseconds per tick (from paper's `mspt` or vanilla's `tick query`)
+    Mspt(f32),
+    /// `Can't keep up! Is the server overloaded? Running 2042ms or 40 ticks behind`
+    CantKeepUp {
+        ms_behind: u64,
+    },
+    /// anything else a custom line parser or a `custom:<kind>` rule reports, i.e. a boss kill announced by a datapack
+    Custom {
+        kind: String,
+        fields: BTreeMap<String, String>,
+    },
 }
 
 #[derive(Debug)]
@@ -25,3 +50,10 @@ pub struct JoinLeaveEvent {
     pub username: String,
     pub joined: bool,
 }
+
+#[derive(Debug)]
+pub struct DeathEvent {
+    pub username: String,
+    /// i.e. `Steve was slain by Zombie`
+    pub message: String,
+}
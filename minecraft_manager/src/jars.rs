@@ -0,0 +1,241 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// where server jars which aren't cached yet are downloaded from
+pub trait JarMirror: Send + Sync {
+    /// saves the jar called `<name>.jar` (i.e. `vanilla-papermc-1.20.4`) to `to`
+    fn fetch(&self, name: &str, to: &Path) -> io::Result<()>;
+}
+
+/// a mirror which is just a directory containing `<name>.jar` files
+pub struct DirectoryMirror(pub PathBuf);
+
+impl JarMirror for DirectoryMirror {
+    fn fetch(&self, name: &str, to: &Path) -> io::Result<()> {
+        let jar = self.0.join(format!("{name}.jar"));
+        if !jar.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} doesn't exist", jar.display()),
+            ));
+        }
+        fs::copy(jar, to)?;
+        Ok(())
+    }
+}
+
+/// a directory of server jars named `<type>-<version>.jar`
+/// (the same as the `jar_cache_dir` used by [`crate::create::ServerSource::find`])
+pub struct JarCache {
+    pub dir: PathBuf,
+}
+
+impl JarCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.jar"))
+    }
+    /// the names of all cached jars, sorted
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        let mut o = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let file_name = entry?.file_name();
+            if let Some(name) = file_name.to_string_lossy().strip_suffix(".jar") {
+                o.push(name.to_owned());
+            }
+        }
+        o.sort();
+        Ok(o)
+    }
+    /// the cached jar called `name`. If it isn't cached yet, it is fetched from the mirror first.
+    pub fn get(&self, name: &str, mirror: Option<&dyn JarMirror>) -> io::Result<PathBuf> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{name}` is not a valid jar name"),
+            ));
+        }
+        let path = self.path(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+        let Some(mirror) = mirror else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("`{name}.jar` isn't cached and there is no mirror to download it from"),
+            ));
+        };
+        fs::create_dir_all(&self.dir)?;
+        // only has the real name once it's complete
+        let partial = self.dir.join(format!("{name}.jar.partial"));
+        if let Err(e) = mirror.fetch(name, &partial) {
+            _ = fs::remove_file(&partial);
+            return Err(e);
+        }
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+    /// the name of the cached jar which has the same content as `jar`, if any
+    pub fn identify(&self, jar: &Path) -> io::Result<Option<String>> {
+        let size = fs::metadata(jar)?.len();
+        for name in self.list()? {
+            let cached = self.path(&name);
+            if fs::metadata(&cached)?.len() == size && same_content(jar, &cached)? {
+                return Ok(Some(name));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// replaces the server's executable with `jar`. The previous executable is kept as `<executable>.previous`.
+pub fn install(jar: &Path, server_dir: &Path, executable: &str) -> io::Result<()> {
+    let target = server_dir.join(executable);
+    if target.exists() {
+        let previous = server_dir.join(format!("{executable}.previous"));
+        // `rename` doesn't replace existing files on windows
+        match fs::remove_file(&previous) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        fs::rename(&target, previous)?;
+    }
+    fs::copy(jar, target)?;
+    Ok(())
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut buf_a, mut buf_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an empty directory for one test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft_manager-jars-{test}-{}",
+            std::process::id()
+        ));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn get_fetches_from_the_mirror_once() {
+        let dir = temp_dir("get");
+        let mirror_dir = dir.join("mirror");
+        fs::create_dir(&mirror_dir).unwrap();
+        fs::write(mirror_dir.join("vanilla-mojang-1.20.4.jar"), "jar").unwrap();
+        let mirror = DirectoryMirror(mirror_dir.clone());
+        let cache = JarCache::new(dir.join("cache"));
+
+        let path = cache.get("vanilla-mojang-1.20.4", Some(&mirror)).unwrap();
+        assert_eq!(path, cache.path("vanilla-mojang-1.20.4"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "jar");
+        assert_eq!(cache.list().unwrap(), ["vanilla-mojang-1.20.4"]);
+        assert!(!dir.join("cache/vanilla-mojang-1.20.4.jar.partial").exists());
+
+        // cached now, so the mirror isn't needed
+        fs::remove_file(mirror_dir.join("vanilla-mojang-1.20.4.jar")).unwrap();
+        assert_eq!(cache.get("vanilla-mojang-1.20.4", None).unwrap(), path);
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn get_fails_without_the_jar() {
+        let dir = temp_dir("get-missing");
+        let mirror = DirectoryMirror(dir.join("mirror"));
+        let cache = JarCache::new(dir.join("cache"));
+        let e = cache.get("purpur-1.20.4", Some(&mirror)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            cache.get("purpur-1.20.4", None).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        // no `.partial` is left behind
+        assert!(cache.list().unwrap().is_empty());
+        assert_eq!(fs::read_dir(&cache.dir).unwrap().count(), 0);
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn get_rejects_paths() {
+        let dir = temp_dir("get-paths");
+        let cache = JarCache::new(dir.join("cache"));
+        let mirror = DirectoryMirror(dir.clone());
+        for name in ["", "../secret", ".hidden", "a\\b"] {
+            assert_eq!(
+                cache.get(name, Some(&mirror)).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "{name}"
+            );
+        }
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn install_keeps_the_previous_executable() {
+        let dir = temp_dir("install");
+        fs::write(dir.join("new.jar"), "new").unwrap();
+        fs::write(dir.join("newer.jar"), "newer").unwrap();
+        let server_dir = dir.join("server");
+        fs::create_dir(&server_dir).unwrap();
+
+        // nothing to keep yet
+        install(&dir.join("new.jar"), &server_dir, "server.jar").unwrap();
+        assert_eq!(
+            fs::read_to_string(server_dir.join("server.jar")).unwrap(),
+            "new"
+        );
+        assert!(!server_dir.join("server.jar.previous").exists());
+
+        fs::write(server_dir.join("server.jar.previous"), "old").unwrap();
+        install(&dir.join("newer.jar"), &server_dir, "server.jar").unwrap();
+        assert_eq!(
+            fs::read_to_string(server_dir.join("server.jar")).unwrap(),
+            "newer"
+        );
+        // the old `.previous` is overwritten
+        assert_eq!(
+            fs::read_to_string(server_dir.join("server.jar.previous")).unwrap(),
+            "new"
+        );
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn identify_finds_the_same_jar() {
+        let dir = temp_dir("identify");
+        let cache = JarCache::new(dir.join("cache"));
+        fs::create_dir(&cache.dir).unwrap();
+        fs::write(cache.path("a-1"), "aaa").unwrap();
+        fs::write(cache.path("b-1"), "bbb").unwrap();
+        fs::write(dir.join("server.jar"), "bbb").unwrap();
+        fs::write(dir.join("other.jar"), "ccc").unwrap();
+        assert_eq!(
+            cache.identify(&dir.join("server.jar")).unwrap().as_deref(),
+            Some("b-1")
+        );
+        assert_eq!(cache.identify(&dir.join("other.jar")).unwrap(), None);
+        _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// where JVMs are usually installed on linux
+const JVM_DIR: &str = "/usr/lib/jvm";
+
+/// an installed java runtime
+#[derive(Clone, Debug)]
+pub struct JavaInstallation {
+    /// the `java` executable
+    pub path: PathBuf,
+    /// the major version, i.e. 8 for `1.8.0_392` or 17 for `17.0.9`
+    pub version: u32,
+}
+
+/// finds java installations in `JAVA_HOME`, `/usr/lib/jvm/*` and `extra_paths`
+/// (each either a java home directory or a `java` executable). Sorted newest first.
+pub fn discover(extra_paths: &[PathBuf]) -> Vec<JavaInstallation> {
+    let mut candidates = vec![];
+    if let Some(java_home) = std::env::var_os("JAVA_HOME") {
+        candidates.push(PathBuf::from(java_home));
+    }
+    if let Ok(entries) = fs::read_dir(JVM_DIR) {
+        let mut dirs = entries.flatten().map(|e| e.path()).collect::<Vec<_>>();
+        dirs.sort();
+        candidates.extend(dirs);
+    }
+    candidates.extend(extra_paths.iter().cloned());
+    let mut o: Vec<JavaInstallation> = vec![];
+    for candidate in candidates {
+        let (java, home) = if candidate.is_dir() {
+            (candidate.join("bin").join("java"), Some(candidate))
+        } else {
+            (candidate, None)
+        };
+        if !java.is_file() {
+            continue;
+        }
+        // i.e. `/usr/lib/jvm/default` is often a symlink to another jvm
+        let canonical = fs::canonicalize(&java).unwrap_or_else(|_| java.clone());
+        if o.iter()
+            .any(|j| fs::canonicalize(&j.path).unwrap_or_else(|_| j.path.clone()) == canonical)
+        {
+            continue;
+        }
+        let version = home
+            .and_then(|home| version_from_release_file(&home))
+            .or_else(|| version_from_executable(&java));
+        if let Some(version) = version {
+            o.push(JavaInstallation {
+                path: java,
+                version,
+            });
+        }
+    }
+    o.sort_by_key(|j| std::cmp::Reverse(j.version));
+    o
+}
+
+/// `JAVA_VERSION="17.0.9"` from `<java home>/release`
+fn version_from_release_file(home: &Path) -> Option<u32> {
+    let release = fs::read_to_string(home.join("release")).ok()?;
+    release
+        .lines()
+        .find_map(|line| parse_version(line.strip_prefix("JAVA_VERSION=")?.trim_matches('"')))
+}
+/// `java -version` prints i.e. `openjdk version "17.0.9" 2023-10-17` (to stderr)
+fn version_from_executable(java: &Path) -> Option<u32> {
+    let output = Command::new(java).arg("-version").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stderr);
+    let (_, rest) = text.split_once("version \"")?;
+    parse_version(rest.split('"').next()?)
+}
+
+/// the major version of a java version string, i.e. 8 for `1.8.0_392`, 17 for `17.0.9` and 21 for `21`
+pub fn parse_version(version: &str) -> Option<u32> {
+    let mut parts = version.split(['.', '_', '-', '+']);
+    match parts.next()?.parse().ok()? {
+        1 => parts.next()?.parse().ok(),
+        major => Some(major),
+    }
+}
+
+/// the java versions a minecraft version runs on: at least `.0`, and at most `.1` (if there is a limit).
+/// `None` if the version isn't known.
+pub fn required_version(minecraft_version: &str) -> Option<(u32, Option<u32>)> {
+    let mut parts = minecraft_version.trim().split(['.', '-', ' ']);
+    if parts.next()? != "1" {
+        return None;
+    }
+    let minor: u32 = parts.next()?.parse().ok()?;
+    let patch: u32 = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    Some(match (minor, patch) {
+        (0..=16, _) => (8, Some(16)),
+        (17, _) => (16, None),
+        (18..=19, _) | (20, 0..=4) => (17, None),
+        _ => (21, None),
+    })
+}
+
+/// picks the newest installed java which can run the minecraft version.
+/// If the version isn't known, the newest java is used.
+pub fn select<'a>(
+    installed: &'a [JavaInstallation],
+    minecraft_version: &str,
+) -> Result<&'a JavaInstallation, NoCompatibleJava> {
+    let required = required_version(minecraft_version);
+    let (min, max) = required.unwrap_or((0, None));
+    installed
+        .iter()
+        .filter(|j| j.version >= min && max.is_none_or(|max| j.version <= max))
+        .max_by_key(|j| j.version)
+        .ok_or_else(|| {
+            let mut found = installed.iter().map(|j| j.version).collect::<Vec<_>>();
+            found.sort_by_key(|v| std::cmp::Reverse(*v));
+            found.dedup();
+            NoCompatibleJava {
+                minecraft_version: minecraft_version.to_owned(),
+                required,
+                found,
+            }
+        })
+}
+
+/// there is no installed java which can run the server's minecraft version
+#[derive(Debug)]
+pub struct NoCompatibleJava {
+    pub minecraft_version: String,
+    pub required: Option<(u32, Option<u32>)>,
+    /// the versions of all java installations which were found
+    pub found: Vec<u32>,
+}
+impl Display for NoCompatibleJava {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let version = &self.minecraft_version;
+        let found = if self.found.is_empty() {
+            "no Java was found".to_owned()
+        } else {
+            let found = self.found.iter().map(u32::to_string).collect::<Vec<_>>();
+            format!("found Java {}", found.join(", "))
+        };
+        match self.required {
+            Some((min, Some(max))) => write!(
+                f,
+                "No compatible Java for Minecraft {version} (needs Java {min} to {max}, {found})"
+            ),
+            Some((min, None)) => write!(
+                f,
+                "No compatible Java for Minecraft {version} (needs Java {min} or newer, {found})"
+            ),
+            None => write!(f, "No Java to run Minecraft {version} ({found})"),
+        }
+    }
+}
+impl std::error::Error for NoCompatibleJava {}
+
+/// built-in sets of JVM arguments which can be selected by name (`jvm_preset=`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JvmPreset {
+    /// Aikar's flags (https://docs.papermc.io/paper/aikars-flags), tuned G1GC for minecraft servers
+    Aikar,
+    /// just the G1 garbage collector
+    G1gc,
+}
+impl JvmPreset {
+    pub const ALL: [Self; 2] = [Self::Aikar, Self::G1gc];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.name() == name.trim())
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Aikar => "aikar",
+            Self::G1gc => "g1gc",
+        }
+    }
+    pub fn args(&self) -> &'static [&'static str] {
+        match self {
+            Self::Aikar => &[
+                "-XX:+UseG1GC",
+                "-XX:+ParallelRefProcEnabled",
+                "-XX:MaxGCPauseMillis=200",
+                "-XX:+UnlockExperimentalVMOptions",
+                "-XX:+DisableExplicitGC",
+                "-XX:+AlwaysPreTouch",
+                "-XX:G1NewSizePercent=30",
+                "-XX:G1MaxNewSizePercent=40",
+                "-XX:G1HeapRegionSize=8M",
+                "-XX:G1ReservePercent=20",
+                "-XX:G1HeapWastePercent=5",
+                "-XX:G1MixedGCCountTarget=4",
+                "-XX:InitiatingHeapOccupancyPercent=15",
+                "-XX:G1MixedGCLiveThresholdPercent=90",
+                "-XX:G1RSetUpdatingPauseTimePercent=5",
+                "-XX:SurvivorRatio=32",
+                "-XX:+PerfDisableSharedMem",
+                "-XX:MaxTenuringThreshold=1",
+                "-Dusing.aikars.flags=https://mcflags.emc.gs",
+                "-Daikars.new.flags=true",
+            ],
+            Self::G1gc => &["-XX:+UseG1GC"],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed(versions: &[u32]) -> Vec<JavaInstallation> {
+        versions
+            .iter()
+            .map(|&version| JavaInstallation {
+                path: PathBuf::from(format!("/usr/lib/jvm/java-{version}/bin/java")),
+                version,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn versions_are_parsed() {
+        for (version, major) in [
+            // `java -version`
+            ("1.8.0_392", Some(8)),
+            ("1.8.0_392-b08", Some(8)),
+            ("17.0.9", Some(17)),
+            ("21", Some(21)),
+            ("21-ea", Some(21)),
+            ("21.0.1+12-LTS", Some(21)),
+            ("", None),
+            ("1", None),
+            ("openjdk", None),
+        ] {
+            assert_eq!(parse_version(version), major, "{version}");
+        }
+    }
+
+    #[test]
+    fn release_files_are_parsed() {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft_manager-java-release-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for (release, major) in [
+            ("JAVA_VERSION=\"1.8.0_392\"\n", Some(8)),
+            (
+                "IMPLEMENTOR=\"Eclipse Adoptium\"\nJAVA_VERSION=\"17.0.9\"\nOS_NAME=\"Linux\"\n",
+                Some(17),
+            ),
+            ("JAVA_VERSION=\"21\"\n", Some(21)),
+            ("IMPLEMENTOR=\"Eclipse Adoptium\"\n", None),
+        ] {
+            fs::write(dir.join("release"), release).unwrap();
+            assert_eq!(version_from_release_file(&dir), major, "{release}");
+        }
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn required_versions() {
+        for (minecraft_version, required) in [
+            ("1.8.9", Some((8, Some(16)))),
+            ("1.16", Some((8, Some(16)))),
+            ("1.16.5", Some((8, Some(16)))),
+            ("1.17", Some((16, None))),
+            ("1.17.1", Some((16, None))),
+            ("1.18", Some((17, None))),
+            ("1.20.4", Some((17, None))),
+            ("1.20.5", Some((21, None))),
+            ("1.21", Some((21, None))),
+            ("1.20.4-pre1", Some((17, None))),
+            (" 1.20.5\n", Some((21, None))),
+            ("24w14a", None),
+            ("", None),
+        ] {
+            assert_eq!(
+                required_version(minecraft_version),
+                required,
+                "{minecraft_version}"
+            );
+        }
+    }
+
+    #[test]
+    fn newest_compatible_java_is_selected() {
+        let all = installed(&[21, 17, 16, 11, 8]);
+        for (minecraft_version, version) in [
+            ("1.16.5", 16),
+            ("1.17", 21),
+            ("1.20.4", 21),
+            ("1.20.5", 21),
+            ("24w14a", 21),
+        ] {
+            assert_eq!(
+                select(&all, minecraft_version).unwrap().version,
+                version,
+                "{minecraft_version}"
+            );
+        }
+        let old = installed(&[17, 11, 8]);
+        assert_eq!(select(&old, "1.16").unwrap().version, 11);
+        assert_eq!(select(&old, "1.17").unwrap().version, 17);
+        assert_eq!(select(&old, "1.20.4").unwrap().version, 17);
+        let e = select(&old, "1.20.5").unwrap_err();
+        assert_eq!(e.required, Some((21, None)));
+        assert_eq!(e.found, [17, 11, 8]);
+        assert_eq!(
+            e.to_string(),
+            "No compatible Java for Minecraft 1.20.5 (needs Java 21 or newer, found Java 17, 11, 8)"
+        );
+        let e = select(&installed(&[21, 21, 17]), "1.12.2").unwrap_err();
+        assert_eq!(e.found, [21, 17]);
+        assert_eq!(
+            e.to_string(),
+            "No compatible Java for Minecraft 1.12.2 (needs Java 8 to 16, found Java 21, 17)"
+        );
+        assert_eq!(
+            select(&[], "24w14a").unwrap_err().to_string(),
+            "No Java to run Minecraft 24w14a (no Java was found)"
+        );
+    }
+}
@@ -1,17 +1,30 @@
+pub mod backup;
 pub mod chat;
+pub mod config;
+pub mod create;
+pub mod custom_parser;
 pub mod events;
+pub mod jars;
+pub mod java;
 pub mod parse_line;
+pub mod performance;
+pub mod properties;
+pub mod resources;
 pub mod tasks;
+pub mod text;
 pub mod thread;
 pub mod threaded;
 
 use std::{
     fmt::Display,
-    io::BufReader,
-    process::{Child, ChildStdin, ChildStdout, Command},
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{mpsc, Arc, Mutex},
 };
 
+use custom_parser::{LineParser, LineParserProtocol};
+use java::JvmPreset;
+use parse_line::LineRule;
 use thread::MinecraftServerThread;
 
 #[derive(Clone)]
@@ -19,17 +32,76 @@ pub struct MinecraftServerSettings {
     pub server_type: MinecraftServerType,
     pub directory: String,
     pub executable: String,
-    /// the amount of dedicated wam for the JVM in [TODO!] (-Xm{s,x}...M)
-    pub dedicated_wam: u32,
+    /// the JVM's initial heap size in MB (`-Xms`)
+    pub ram_min: u32,
+    /// the JVM's maximum heap size in MB (`-Xmx`)
+    pub ram_max: u32,
     pub java_cmd: Option<String>,
+    /// JVM arguments from a built-in preset, added before `jvm_args`
+    pub jvm_preset: Option<JvmPreset>,
+    /// extra arguments for the JVM (before `-jar`)
+    pub jvm_args: Vec<String>,
+    /// extra arguments for the server (after `nogui`)
+    pub server_args: Vec<String>,
+    /// environment variables for the server process
+    pub env: Vec<(String, String)>,
+    /// the minecraft version (`version=`), used to pick a compatible java if `java_cmd` isn't set
+    pub minecraft_version: Option<String>,
+    /// java homes or executables to consider in addition to `JAVA_HOME` and `/usr/lib/jvm/*`
+    pub java_search_paths: Vec<PathBuf>,
+    /// regex rules which are checked (in order) before the server type's own parsing (`rule=`)
+    pub line_rules: Vec<LineRule>,
 }
+/// shows the command the server is started with (including environment variables)
 impl Display for MinecraftServerSettings {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{} @ {} :: {} @ {}MB",
-            self.server_type, self.directory, self.executable, self.dedicated_wam
-        )
+        write!(f, "{} @ {} ::", self.server_type, self.directory)?;
+        for (key, value) in &self.env {
+            write!(f, " {key}={}", quote(value))?;
+        }
+        // java isn't looked for and `run.sh` isn't read here, that only happens in `get_command`
+        match &self.server_type {
+            MinecraftServerType::Custom {
+                command_override: Some(cmd),
+                ..
+            } if self.java_cmd.is_none() => write!(f, " {}", quote(cmd))?,
+            _ => match (&self.java_cmd, &self.minecraft_version) {
+                (Some(java_cmd), _) => write!(f, " {}", quote(java_cmd))?,
+                (None, Some(version)) => write!(f, " <java for {version}>")?,
+                (None, None) => write!(f, " java")?,
+            },
+        }
+        for arg in self.jvm_args_before_launch() {
+            write!(f, " {}", quote(&arg))?;
+        }
+        match self.run_script() {
+            Some(_) => write!(f, " <@ argument files from {}>", self.executable)?,
+            None => {
+                for arg in self.launch_args().unwrap_or_default() {
+                    write!(f, " {}", quote(&arg))?;
+                }
+            }
+        }
+        for arg in self.args_after_launch() {
+            write!(f, " {}", quote(&arg))?;
+        }
+        Ok(())
+    }
+}
+/// prints a warning from the manager (not a log line of the server) to `output` if it is set
+/// (see [`MinecraftServerSettings::spawn_with_output`]), otherwise to stderr
+fn warn(output: Option<&mpsc::Sender<String>>, warning: String) {
+    match output {
+        Some(output) => _ = output.send(warning),
+        None => eprintln!("{warning}"),
+    }
+}
+/// adds quotes to arguments containing spaces, so the command can be copied into a shell
+fn quote(arg: &str) -> std::borrow::Cow<'_, str> {
+    if arg.is_empty() || arg.contains(char::is_whitespace) {
+        format!("'{}'", arg.replace('\'', "'\\''")).into()
+    } else {
+        arg.into()
     }
 }
 impl MinecraftServerSettings {
@@ -42,7 +114,15 @@ impl MinecraftServerSettings {
         let mut directory = Err(MinecraftServerSettingsFromLinesError::MissingDirectory);
         let mut executable = Err(MinecraftServerSettingsFromLinesError::MissingExecutable);
         let mut ram = None;
+        let mut ram_min = None;
+        let mut ram_max = None;
         let mut java_cmd = None;
+        let mut jvm_preset = None;
+        let mut jvm_args = vec![];
+        let mut server_args = vec![];
+        let mut env = vec![];
+        let mut minecraft_version = None;
+        let mut line_rules = vec![];
         let mut extra_line = None;
         loop {
             if let Some(line) = if let Some(l) = extra_line.take() {
@@ -54,11 +134,10 @@ impl MinecraftServerSettings {
                     match key {
                         "type" => {
                             server_type = Ok(match value.trim() {
-                                "vanilla-mojang" => MinecraftServerType::VanillaMojang,
-                                "vanilla-papermc" => MinecraftServerType::VanillaPaperMC,
                                 "custom" => {
                                     let mut name = Err(MinecraftServerSettingsFromLinesError::CustomServerTypeMissingName);
-                                    let mut line_parser = Err(MinecraftServerSettingsFromLinesError::CustomServerTypeMissingLineParser);
+                                    let mut line_parser = None;
+                                    let mut line_parser_protocol = LineParserProtocol::default();
                                     let mut command_override = None;
                                     loop {
                                         if let Some(line) = lines.next() {
@@ -69,7 +148,10 @@ impl MinecraftServerSettings {
                                                     {
                                                         match key {
                                                             "name" => name = Ok(val.to_owned()),
-                                                            "parser" => line_parser = Ok(val.to_owned()),
+                                                            "parser" => line_parser = Some(val.to_owned()),
+                                                            "protocol" => line_parser_protocol = LineParserProtocol::from_name(val).ok_or_else(|| {
+                                                                MinecraftServerSettingsFromLinesError::UnknownLineParserProtocol(val.trim().to_owned())
+                                                            })?,
                                                             "command-override" => command_override = Some(val.to_owned()),
                                                             _ =>
                                                             return Err(MinecraftServerSettingsFromLinesError::CustomTypeUnknownKey(
@@ -92,32 +174,68 @@ impl MinecraftServerSettings {
                                     }
                                     MinecraftServerType::Custom {
                                         name: name?,
-                                        line_parser: line_parser?,
-                                        line_parser_proc: Arc::new(Mutex::new(None)),
+                                        line_parser,
+                                        line_parser_protocol,
+                                        line_parser_proc: Default::default(),
                                         command_override,
                                     }
                                 }
-                                other => {
-                                    return Err(
+                                other => MinecraftServerType::from_config_name(other).ok_or_else(
+                                    || {
                                         MinecraftServerSettingsFromLinesError::UnknownServerType(
                                             other.to_owned(),
-                                        ),
-                                    )
-                                }
+                                        )
+                                    },
+                                )?,
                             });
                         }
                         "dir" => directory = Ok(value.to_owned()),
                         "exec" => executable = Ok(value.to_owned()),
-                        "ram" => {
-                            if let Ok(v) = value.trim().parse() {
-                                ram = Some(v);
-                            } else {
+                        "ram" | "ram_min" | "ram_max" => {
+                            let Ok(v) = value.trim().parse() else {
                                 return Err(MinecraftServerSettingsFromLinesError::RamNotAnInt(
+                                    key.to_owned(),
                                     value.to_owned(),
                                 ));
-                            }
+                            };
+                            *match key {
+                                "ram" => &mut ram,
+                                "ram_min" => &mut ram_min,
+                                _ => &mut ram_max,
+                            } = Some(v);
+                        }
+                        "jvm_preset" => {
+                            jvm_preset = Some(JvmPreset::from_name(value).ok_or_else(|| {
+                                MinecraftServerSettingsFromLinesError::UnknownJvmPreset(
+                                    value.trim().to_owned(),
+                                )
+                            })?)
+                        }
+                        // space separated
+                        "jvm_args" => jvm_args.extend(value.split_whitespace().map(str::to_owned)),
+                        "server_args" => {
+                            server_args.extend(value.split_whitespace().map(str::to_owned))
                         }
+                        // `env=KEY=value`, once for each variable
+                        "env" => match value.split_once('=') {
+                            Some((k, v)) if !k.trim().is_empty() => {
+                                env.push((k.trim().to_owned(), v.to_owned()))
+                            }
+                            _ => {
+                                return Err(MinecraftServerSettingsFromLinesError::EnvNotKeyValue(
+                                    value.to_owned(),
+                                ))
+                            }
+                        },
                         "java_cmd" => java_cmd = Some(value.to_owned()),
+                        "version" => minecraft_version = Some(value.trim().to_owned()),
+                        // `rule=<event> <regex>`, once for each rule
+                        "rule" => line_rules.push(LineRule::from_config(value).map_err(|e| {
+                            MinecraftServerSettingsFromLinesError::InvalidLineRule(
+                                value.to_owned(),
+                                e,
+                            )
+                        })?),
                         k => {
                             return Err(MinecraftServerSettingsFromLinesError::UnknownKey(
                                 k.to_owned(),
@@ -135,14 +253,95 @@ impl MinecraftServerSettings {
                 break;
             }
         }
-        let mut o = Self::new(server_type?, directory?, executable?);
+        let server_type = server_type?;
+        if let MinecraftServerType::Custom {
+            line_parser: None, ..
+        } = server_type
+        {
+            if line_rules.is_empty() {
+                return Err(
+                    MinecraftServerSettingsFromLinesError::CustomServerTypeMissingLineParser,
+                );
+            }
+        }
+        let mut o = Self::new(server_type, directory?, executable?).with_line_rules(line_rules);
         if let Some(ram) = ram {
             o = o.with_ram(ram);
         }
+        o.ram_min = ram_min.unwrap_or(o.ram_min);
+        o.ram_max = ram_max.unwrap_or(o.ram_max);
+        if o.ram_min > o.ram_max {
+            return Err(MinecraftServerSettingsFromLinesError::RamMinAboveMax(
+                o.ram_min, o.ram_max,
+            ));
+        }
+        o = o
+            .with_jvm_preset(jvm_preset)
+            .with_jvm_args(jvm_args)
+            .with_server_args(server_args)
+            .with_env(env);
         if let Some(java_cmd) = java_cmd {
             o = o.with_java_cmd(Some(java_cmd));
         }
-        Ok(o)
+        Ok(o.with_minecraft_version(minecraft_version))
+    }
+
+    /// the opposite of [`Self::from_lines`]. The result ends with a newline.
+    pub fn to_lines(&self) -> String {
+        let mut o = format!("type={}\n", self.server_type.config_name());
+        if let MinecraftServerType::Custom {
+            name,
+            line_parser,
+            line_parser_protocol,
+            command_override,
+            ..
+        } = &self.server_type
+        {
+            o.push_str(&format!("  name={name}\n"));
+            if let Some(line_parser) = line_parser {
+                o.push_str(&format!("  parser={line_parser}\n"));
+            }
+            if *line_parser_protocol != LineParserProtocol::default() {
+                o.push_str(&format!("  protocol={}\n", line_parser_protocol.name()));
+            }
+            if let Some(command_override) = command_override {
+                o.push_str(&format!("  command-override={command_override}\n"));
+            }
+        }
+        o.push_str(&format!(
+            "dir={}\nexec={}\n",
+            self.directory, self.executable
+        ));
+        if self.ram_min == self.ram_max {
+            o.push_str(&format!("ram={}\n", self.ram_max));
+        } else {
+            o.push_str(&format!(
+                "ram_min={}\nram_max={}\n",
+                self.ram_min, self.ram_max
+            ));
+        }
+        if let Some(preset) = self.jvm_preset {
+            o.push_str(&format!("jvm_preset={}\n", preset.name()));
+        }
+        if !self.jvm_args.is_empty() {
+            o.push_str(&format!("jvm_args={}\n", self.jvm_args.join(" ")));
+        }
+        if !self.server_args.is_empty() {
+            o.push_str(&format!("server_args={}\n", self.server_args.join(" ")));
+        }
+        for (key, value) in &self.env {
+            o.push_str(&format!("env={key}={value}\n"));
+        }
+        if let Some(java_cmd) = &self.java_cmd {
+            o.push_str(&format!("java_cmd={java_cmd}\n"));
+        }
+        if let Some(version) = &self.minecraft_version {
+            o.push_str(&format!("version={version}\n"));
+        }
+        for rule in &self.line_rules {
+            o.push_str(&format!("rule={}\n", rule.to_config()));
+        }
+        o
     }
 }
 #[derive(Debug)]
@@ -152,67 +351,263 @@ pub enum MinecraftServerSettingsFromLinesError {
     UnknownServerType(String),
     MissingDirectory,
     MissingExecutable,
-    RamNotAnInt(String),
+    /// the key (`ram`, `ram_min` or `ram_max`) and the value
+    RamNotAnInt(String, String),
+    RamMinAboveMax(u32, u32),
+    UnknownJvmPreset(String),
+    EnvNotKeyValue(String),
+    /// the value of `rule=` and what's wrong with it
+    InvalidLineRule(String, String),
     CustomTypeUnknownKey(String),
     CustomServerTypeMissingName,
     CustomServerTypeMissingLineParser,
+    UnknownLineParserProtocol(String),
+}
+impl Display for MinecraftServerSettingsFromLinesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownKey(key) => write!(f, "unknown key `{key}`"),
+            Self::MissingServerType => write!(f, "missing `type=`"),
+            Self::UnknownServerType(t) => write!(f, "unknown server type `{t}`"),
+            Self::MissingDirectory => write!(f, "missing `dir=`"),
+            Self::MissingExecutable => write!(f, "missing `exec=`"),
+            Self::RamNotAnInt(key, ram) => write!(f, "`{key}={ram}` is not a number"),
+            Self::RamMinAboveMax(min, max) => {
+                write!(f, "`ram_min={min}` is more than `ram_max={max}`")
+            }
+            Self::UnknownJvmPreset(preset) => write!(
+                f,
+                "unknown jvm preset `{preset}` (available: {})",
+                JvmPreset::ALL.map(|p| p.name()).join(", ")
+            ),
+            Self::EnvNotKeyValue(env) => write!(f, "`env={env}` should be `env=KEY=value`"),
+            Self::InvalidLineRule(rule, e) => write!(f, "`rule={rule}`: {e}"),
+            Self::CustomTypeUnknownKey(key) => write!(f, "unknown key `{key}` in custom type"),
+            Self::CustomServerTypeMissingName => write!(f, "custom type is missing `name=`"),
+            Self::CustomServerTypeMissingLineParser => {
+                write!(f, "custom type is missing `parser=` (or `rule=` lines)")
+            }
+            Self::UnknownLineParserProtocol(protocol) => write!(
+                f,
+                "unknown parser protocol `{protocol}` (available: {})",
+                LineParserProtocol::ALL.map(|p| p.name()).join(", ")
+            ),
+        }
+    }
 }
+impl std::error::Error for MinecraftServerSettingsFromLinesError {}
 
 impl MinecraftServerSettings {
     pub fn spawn(self) -> MinecraftServerThread {
         MinecraftServerThread::start(self)
     }
+    /// like [`Self::spawn`], but the server's log lines and the manager's `[WARN/...]` lines
+    /// are sent to `output` instead of being printed to stderr
+    pub fn spawn_with_output(self, output: mpsc::Sender<String>) -> MinecraftServerThread {
+        MinecraftServerThread::start_with_output(self, Some(output))
+    }
 
     pub fn new(server_type: MinecraftServerType, directory: String, executable: String) -> Self {
         Self {
             server_type,
             directory,
             executable,
-            dedicated_wam: 1024,
+            ram_min: 1024,
+            ram_max: 1024,
             java_cmd: None,
+            jvm_preset: None,
+            jvm_args: vec![],
+            server_args: vec![],
+            env: vec![],
+            minecraft_version: None,
+            java_search_paths: vec![],
+            line_rules: vec![],
         }
     }
+    /// sets both the initial and the maximum heap size
     pub fn with_ram(mut self, ram_mb: u32) -> Self {
-        self.dedicated_wam = ram_mb;
+        self.ram_min = ram_mb;
+        self.ram_max = ram_mb;
+        self
+    }
+    pub fn with_jvm_preset(mut self, jvm_preset: Option<JvmPreset>) -> Self {
+        self.jvm_preset = jvm_preset;
+        self
+    }
+    pub fn with_jvm_args(mut self, jvm_args: Vec<String>) -> Self {
+        self.jvm_args = jvm_args;
+        self
+    }
+    pub fn with_server_args(mut self, server_args: Vec<String>) -> Self {
+        self.server_args = server_args;
+        self
+    }
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
         self
     }
     pub fn with_java_cmd(mut self, java_cmd: Option<String>) -> Self {
         self.java_cmd = java_cmd;
         self
     }
+    pub fn with_minecraft_version(mut self, minecraft_version: Option<String>) -> Self {
+        self.minecraft_version = minecraft_version;
+        self
+    }
+    pub fn with_java_search_paths(mut self, java_search_paths: Vec<PathBuf>) -> Self {
+        self.java_search_paths = java_search_paths;
+        self
+    }
+    pub fn with_line_rules(mut self, line_rules: Vec<LineRule>) -> Self {
+        self.line_rules = line_rules;
+        self
+    }
 
-    pub fn get_command(&self) -> Command {
-        let mut cmd = Command::new(if let Some(c) = &self.java_cmd {
-            c.as_str()
-        } else {
-            match &self.server_type {
-                MinecraftServerType::VanillaMojang => "java", // "/usr/lib/jvm/openjdk17/bin/java",
-                MinecraftServerType::VanillaPaperMC => "java", // "/usr/lib/jvm/openjdk17/bin/java",
-                MinecraftServerType::Custom {
-                    command_override, ..
-                } => {
-                    if let Some(cmd) = command_override {
-                        cmd
-                    } else {
-                        "java"
-                    }
-                }
-            }
-        });
-        cmd.current_dir(&self.directory);
-        // match &self.server_type {
-        //     MinecraftServerType::VanillaMojang | MinecraftServerType::VanillaPaperMC =>
-        cmd.args([
-            format!("-Xms{}M", self.dedicated_wam),
-            format!("-Xmx{}M", self.dedicated_wam),
+    /// the java executable to run the server with: `java_cmd` if it is set,
+    /// otherwise the newest installed java which supports the minecraft version.
+    /// If the minecraft version isn't known, `java` (from `PATH`) is used.
+    pub fn java(&self) -> Result<PathBuf, java::NoCompatibleJava> {
+        if let Some(java_cmd) = &self.java_cmd {
+            return Ok(PathBuf::from(java_cmd));
+        }
+        let Some(version) = &self.minecraft_version else {
+            return Ok(PathBuf::from("java"));
+        };
+        let installed = java::discover(&self.java_search_paths);
+        java::select(&installed, version).map(|java| java.path.clone())
+    }
+
+    /// the server's `server.properties` file
+    pub fn properties_path(&self) -> PathBuf {
+        Path::new(&self.directory).join("server.properties")
+    }
+
+    /// the program which starts the server: `command-override` for custom servers, otherwise [`Self::java`]
+    pub fn program(&self) -> Result<PathBuf, java::NoCompatibleJava> {
+        match &self.server_type {
+            MinecraftServerType::Custom {
+                command_override: Some(cmd),
+                ..
+            } if self.java_cmd.is_none() => Ok(PathBuf::from(cmd)),
+            _ => self.java(),
+        }
+    }
+    /// the arguments for [`Self::program`]. Fails if the (neo)forge `run.sh` can't be read
+    /// or doesn't pass any `@` argument files to java.
+    pub fn args(&self) -> Result<Vec<String>, GetCommandError> {
+        let mut args = self.jvm_args_before_launch();
+        args.extend(self.launch_args()?);
+        args.extend(self.args_after_launch());
+        Ok(args)
+    }
+    fn jvm_args_before_launch(&self) -> Vec<String> {
+        let mut args = vec![
+            format!("-Xms{}M", self.ram_min),
+            format!("-Xmx{}M", self.ram_max),
+        ];
+        if let Some(preset) = self.jvm_preset {
+            args.extend(preset.args().iter().map(|arg| arg.to_string()));
+        }
+        args.extend(self.jvm_args.iter().cloned());
+        args.extend([
             "-Dsun.stdout.encoding=UTF-8".to_owned(),
             "-Dsun.stderr.encoding=UTF-8".to_owned(),
             "-DFile.Encoding=UTF-8".to_owned(),
-            "-jar".to_string(),
-            self.executable.to_string(),
-            "nogui".to_string(),
         ]);
-        cmd
+        args
+    }
+    fn args_after_launch(&self) -> Vec<String> {
+        let mut args = vec![];
+        // proxies don't have a gui
+        if !self.server_type.is_proxy() {
+            args.push("nogui".to_owned());
+        }
+        args.extend(self.server_args.iter().cloned());
+        args
+    }
+    /// the `run.sh` script of newer (neo)forge servers, if `exec=` is one
+    fn run_script(&self) -> Option<PathBuf> {
+        (matches!(
+            self.server_type,
+            MinecraftServerType::Forge | MinecraftServerType::NeoForge
+        ) && self.executable.ends_with(".sh"))
+        .then(|| Path::new(&self.directory).join(&self.executable))
+    }
+    /// `-jar <executable>`, or for newer (neo)forge servers the `@` argument files
+    /// (`@user_jvm_args.txt`, `@libraries/.../unix_args.txt`), either as the executable
+    /// or taken from the `run.sh` script which forge's installer creates
+    fn launch_args(&self) -> Result<Vec<String>, GetCommandError> {
+        if let Some(path) = self.run_script() {
+            let script = std::fs::read_to_string(&path)
+                .map_err(|e| GetCommandError::CantReadRunScript(path.clone(), e))?;
+            let args_files = script_args_files(&script);
+            if args_files.is_empty() {
+                return Err(GetCommandError::NoArgsFilesInRunScript(path));
+            }
+            return Ok(args_files);
+        }
+        if matches!(
+            self.server_type,
+            MinecraftServerType::Forge | MinecraftServerType::NeoForge
+        ) && self.executable.starts_with('@')
+        {
+            return Ok(vec![self.executable.clone()]);
+        }
+        Ok(vec!["-jar".to_owned(), self.executable.clone()])
+    }
+
+    /// fails if no installed java can run the server (see [`Self::java`]) or its arguments can't be built (see [`Self::args`])
+    pub fn get_command(&self) -> Result<Command, GetCommandError> {
+        let mut cmd = Command::new(self.program()?);
+        cmd.current_dir(&self.directory);
+        cmd.args(self.args()?);
+        cmd.envs(self.env.iter().map(|(key, value)| (key, value)));
+        Ok(cmd)
+    }
+}
+
+/// the `@` argument files which a (neo)forge `run.sh` passes to java, like
+/// `@user_jvm_args.txt @libraries/net/minecraftforge/forge/.../unix_args.txt`
+fn script_args_files(script: &str) -> Vec<String> {
+    script
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(|arg| arg.trim_matches(['"', '\'']))
+        // `"$@"` passes the script's own arguments on
+        .filter(|arg| arg.starts_with('@') && arg.len() > 1)
+        .map(str::to_owned)
+        .collect()
+}
+
+/// why [`MinecraftServerSettings::get_command`] couldn't build the server's command
+#[derive(Debug)]
+pub enum GetCommandError {
+    NoCompatibleJava(java::NoCompatibleJava),
+    /// the (neo)forge run script (`exec=run.sh`) couldn't be read
+    CantReadRunScript(PathBuf, std::io::Error),
+    /// the (neo)forge run script doesn't pass any `@` argument files to java
+    NoArgsFilesInRunScript(PathBuf),
+}
+impl Display for GetCommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoCompatibleJava(e) => write!(f, "{e}"),
+            Self::CantReadRunScript(path, e) => {
+                write!(f, "Couldn't read {}: {e}", path.display())
+            }
+            Self::NoArgsFilesInRunScript(path) => write!(
+                f,
+                "{} doesn't pass any `@` argument files (like `@libraries/.../unix_args.txt`) to java, set `exec=` to the server jar or the argument file instead",
+                path.display()
+            ),
+        }
+    }
+}
+impl std::error::Error for GetCommandError {}
+impl From<java::NoCompatibleJava> for GetCommandError {
+    fn from(e: java::NoCompatibleJava) -> Self {
+        Self::NoCompatibleJava(e)
     }
 }
 
@@ -220,76 +615,165 @@ impl MinecraftServerSettings {
 pub enum MinecraftServerType {
     VanillaMojang,
     VanillaPaperMC,
+    Spigot,
+    Purpur,
+    Fabric,
+    /// for newer versions, set `exec=run.sh` (or the `@libraries/.../unix_args.txt` file) instead of a jar
+    Forge,
+    NeoForge,
+    /// a Velocity proxy
+    Velocity,
+    /// a BungeeCord or Waterfall proxy
+    BungeeCord,
     Custom {
         /// your custom server type's name
         name: String,
         /// each time a line is received from the mc server's stdout, it is sent to this programs stdin.
-        /// if the program has terminated, it is started again.
-        /// for best performance, the program should read stdin lines in a loop and never exit
-        line_parser: String,
-        line_parser_proc: Arc<Mutex<Option<(Child, ChildStdin, BufReader<ChildStdout>)>>>,
+        /// if the program has terminated or fails, it is started again (after a backoff, see [`LineParser`]).
+        /// for best performance, the program should read stdin lines in a loop and never exit.
+        /// If this isn't set, only the server's `rule=`s are used.
+        line_parser: Option<String>,
+        /// how the manager talks to the line parser (`protocol=legacy` or `protocol=json`)
+        line_parser_protocol: LineParserProtocol,
+        line_parser_proc: Arc<Mutex<LineParser>>,
         /// instead of running java -jar [...], use this to run a shell script which then starts the server.
         /// things like ram etc will be ignored if this is used.
         command_override: Option<String>,
     },
 }
+impl MinecraftServerType {
+    /// all types except `custom`
+    pub const BUILT_IN: [Self; 9] = [
+        Self::VanillaMojang,
+        Self::VanillaPaperMC,
+        Self::Spigot,
+        Self::Purpur,
+        Self::Fabric,
+        Self::Forge,
+        Self::NeoForge,
+        Self::Velocity,
+        Self::BungeeCord,
+    ];
+
+    /// the value of `type=` in the server's config
+    pub fn config_name(&self) -> &'static str {
+        match self {
+            Self::VanillaMojang => "vanilla-mojang",
+            Self::VanillaPaperMC => "vanilla-papermc",
+            Self::Spigot => "spigot",
+            Self::Purpur => "purpur",
+            Self::Fabric => "fabric",
+            Self::Forge => "forge",
+            Self::NeoForge => "neoforge",
+            Self::Velocity => "velocity",
+            Self::BungeeCord => "bungeecord",
+            Self::Custom { .. } => "custom",
+        }
+    }
+    /// the built-in type called `name` (see [`Self::config_name`]). `custom` needs more settings, so it isn't included.
+    pub fn from_config_name(name: &str) -> Option<Self> {
+        Self::BUILT_IN
+            .into_iter()
+            .find(|server_type| server_type.config_name() == name.trim())
+    }
+    /// proxies don't have worlds or a `nogui` argument, and are stopped with `end`
+    pub fn is_proxy(&self) -> bool {
+        matches!(self, Self::Velocity | Self::BungeeCord)
+    }
+    /// the console commands which make the server log its tick rate (see [`parse_line`] and [`performance`]).
+    /// Vanilla's `tick query` only exists since 1.20.3, so nothing is sent to older (known) versions.
+    pub fn tick_query_commands(&self, minecraft_version: Option<&str>) -> &'static [&'static str] {
+        match self {
+            Self::VanillaPaperMC | Self::Purpur => &["tps", "mspt"],
+            Self::Spigot => &["tps"],
+            Self::VanillaMojang | Self::Fabric | Self::Forge | Self::NeoForge => {
+                let version = minecraft_version.map(|version| {
+                    version
+                        .split(['.', '-', ' '])
+                        .map(|part| part.parse::<u32>().unwrap_or(0))
+                        .chain(std::iter::repeat(0))
+                        .take(3)
+                        .collect::<Vec<_>>()
+                });
+                match version {
+                    Some(version) if version < vec![1, 20, 3] => &[],
+                    _ => &["tick query"],
+                }
+            }
+            Self::Velocity | Self::BungeeCord | Self::Custom { .. } => &[],
+        }
+    }
+    /// the console command which shuts the server down
+    pub fn stop_command(&self) -> &'static str {
+        if self.is_proxy() {
+            "end"
+        } else {
+            "stop"
+        }
+    }
+}
 impl Display for MinecraftServerType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::VanillaMojang => write!(f, "vanilla-mojang"),
-            Self::VanillaPaperMC => write!(f, "vanilla-papermc"),
             Self::Custom {
                 name: identifier, ..
             } => write!(f, "custom ({identifier})"),
+            other => write!(f, "{}", other.config_name()),
         }
     }
 }
 
-pub fn test() {
-    // create minecraft server config
-    let minecraft_server_settings = MinecraftServerSettings {
-        server_type: MinecraftServerType::VanillaPaperMC,
-        directory: "/home/mark/Dokumente/minecraft_server/1".to_string(),
-        executable: "paper-1.19-81.jar".to_string(),
-        dedicated_wam: 1024,
-        java_cmd: None,
-    };
-    // start server
-    let mut thread = minecraft_server_settings.spawn();
-    // handle stdin
-    if false {
-        let sender = thread.clone_task_sender();
-        std::thread::spawn(move || {
-            let stdin = std::io::stdin();
-            loop {
-                let mut line = String::new();
-                if let Ok(_) = stdin.read_line(&mut line) {
-                    if line.trim().is_empty() {
-                        std::thread::sleep(std::time::Duration::from_secs(300));
-                        continue;
-                    }
-                    if let Err(_) = sender.send_task(tasks::MinecraftServerTask::RunCommand(line)) {
-                        break;
-                    }
-                } else {
-                    break;
-                }
-            }
-        });
-    }
-    // handle stdout
-    loop {
-        if !thread.is_finished() {
-            thread.update();
-            for event in thread.handle_new_events() {
-                eprintln!("Event: {event:?}");
-            }
-            std::thread::sleep(std::time::Duration::from_millis(100));
-        } else {
-            if let Ok(stop_reason) = thread.get_stop_reason() {
-                eprintln!("Thread stopped: {stop_reason}");
-            }
-            break;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_script_args_files() {
+        let script = "#!/usr/bin/env sh\n\
+            # Add custom JVM arguments to the user_jvm_args.txt\n\
+            # @not_an_argument.txt\n\
+            java @user_jvm_args.txt @libraries/net/minecraftforge/forge/1.20.1-47.2.0/unix_args.txt \"$@\"\n";
+        assert_eq!(
+            script_args_files(script),
+            [
+                "@user_jvm_args.txt",
+                "@libraries/net/minecraftforge/forge/1.20.1-47.2.0/unix_args.txt"
+            ]
+        );
+        assert!(script_args_files("java -jar server.jar \"$@\"\n").is_empty());
+    }
+
+    #[test]
+    fn display_doesnt_look_for_java_or_read_run_script() {
+        let settings = MinecraftServerSettings::new(
+            MinecraftServerType::Forge,
+            "/nonexistent".to_owned(),
+            "run.sh".to_owned(),
+        )
+        .with_minecraft_version(Some("1.20.1".to_owned()))
+        .with_java_search_paths(vec![PathBuf::from("/nonexistent")]);
+        assert_eq!(
+            settings.to_string(),
+            "forge @ /nonexistent :: <java for 1.20.1> -Xms1024M -Xmx1024M -Dsun.stdout.encoding=UTF-8 \
+            -Dsun.stderr.encoding=UTF-8 -DFile.Encoding=UTF-8 <@ argument files from run.sh> nogui"
+        );
+    }
+
+    #[test]
+    fn run_script_without_args_files_fails() {
+        let dir = std::env::temp_dir().join(format!("mcman-run-script-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("run.sh"), "java -jar server.jar \"$@\"\n").unwrap();
+        let settings = MinecraftServerSettings::new(
+            MinecraftServerType::Forge,
+            dir.to_string_lossy().into_owned(),
+            "run.sh".to_owned(),
+        );
+        let result = settings.args();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            result,
+            Err(GetCommandError::NoArgsFilesInRunScript(_))
+        ));
     }
 }
@@ -1,7 +1,4 @@
-use std::{
-    io::{BufRead, BufReader, Write},
-    process::{self, Stdio},
-};
+use regex::Regex;
 
 use crate::{
     chat::ChatMessage,
@@ -13,6 +10,8 @@ pub enum ParseOutput {
     Nothing,
     Error(ParseError),
     Event(MinecraftServerEventType),
+    /// more than one event from one line (only from custom line parsers)
+    Events(Vec<MinecraftServerEventType>),
 }
 
 pub enum ParseError {
@@ -24,191 +23,623 @@ pub fn parse_line(line: &str, settings: &MinecraftServerSettings) -> ParseOutput
     if line.trim().is_empty() {
         return ParseOutput::Nothing;
     }
+    // rules come first, so they can also replace the type's own parsing
+    if let Some(event) = settings
+        .line_rules
+        .iter()
+        .find_map(|rule| rule.apply(line.trim_end()))
+    {
+        return ParseOutput::Event(event);
+    }
+    if let Some(event) = tick_message(line.trim_end()) {
+        return ParseOutput::Event(event);
+    }
     match &settings.server_type {
         MinecraftServerType::Custom {
-            line_parser,
+            line_parser: Some(line_parser),
+            line_parser_protocol,
             line_parser_proc,
             ..
-        } => {
-            let mut proc = line_parser_proc.lock().unwrap();
-            let proc = &mut *proc;
-            let make_new_proc = if let Some((proc, _, _)) = proc {
-                if let Ok(Some(_)) = proc.try_wait() {
-                    // has exited
-                    true
-                } else {
-                    false
-                }
-            } else {
-                true
-            };
-            if make_new_proc {
-                if let Ok(mut new_proc) = process::Command::new(line_parser)
-                    .stdin(Stdio::piped())
-                    .stdout(Stdio::piped())
-                    .spawn()
-                {
-                    if let (Some(stdin), Some(stdout)) =
-                        (new_proc.stdin.take(), new_proc.stdout.take())
-                    {
-                        *proc = Some((new_proc, stdin, BufReader::new(stdout)));
-                    } else {
-                        eprintln!("[WARN/CUSTOM-LINE-PARSER] No stdin/stdout handles!");
-                        _ = new_proc.kill();
-                    }
-                } else {
-                    eprintln!("[WARN/CUSTOM-LINE-PARSER] Can't spawn command '{line_parser}'!");
-                }
+        } => line_parser_proc
+            .lock()
+            .unwrap()
+            .parse_line(line_parser, *line_parser_protocol, line),
+        // only uses the rules
+        MinecraftServerType::Custom {
+            line_parser: None, ..
+        } => ParseOutput::Nothing,
+        MinecraftServerType::VanillaMojang => vanilla_message(line)
+            .map(parse_message)
+            .unwrap_or(ParseOutput::Nothing),
+        MinecraftServerType::Fabric => fabric_message(line)
+            .or_else(|| vanilla_message(line))
+            .map(parse_message)
+            .unwrap_or(ParseOutput::Nothing),
+        MinecraftServerType::Forge | MinecraftServerType::NeoForge => forge_message(line)
+            .map(parse_message)
+            .unwrap_or(ParseOutput::Nothing),
+        MinecraftServerType::VanillaPaperMC
+        | MinecraftServerType::Spigot
+        | MinecraftServerType::Purpur => paper_message(line)
+            .map(parse_message)
+            .unwrap_or(ParseOutput::Nothing),
+        MinecraftServerType::Velocity => paper_message(line)
+            .and_then(velocity_join_leave)
+            .unwrap_or(ParseOutput::Nothing),
+        MinecraftServerType::BungeeCord => paper_message(line)
+            .or_else(|| bungeecord_message(line))
+            .and_then(bungeecord_join_leave)
+            .unwrap_or(ParseOutput::Nothing),
+    }
+}
+
+/// `[12:00:00] [Server thread/INFO]: msg`
+fn vanilla_message(line: &str) -> Option<&str> {
+    let (_time, rest) = line
+        .strip_prefix('[')?
+        .split_once("] [Server thread/INFO]: ")?;
+    Some(rest)
+}
+/// `[12:00:00] [Server thread/INFO] (Minecraft) msg`
+fn fabric_message(line: &str) -> Option<&str> {
+    let (_time, rest) = line
+        .strip_prefix('[')?
+        .split_once("] [Server thread/INFO] (Minecraft) ")?;
+    Some(rest)
+}
+/// `[12:00:00] [Server thread/INFO] [minecraft/DedicatedServer]: msg`.
+/// Only minecraft's own loggers are used, so mods can't fake chat messages.
+fn forge_message(line: &str) -> Option<&str> {
+    let (_time, rest) = line
+        .strip_prefix('[')?
+        .split_once("] [Server thread/INFO] [minecraft/")?;
+    let (_logger, rest) = rest.split_once("]: ")?;
+    Some(rest)
+}
+/// `[12:00:00 INFO]: msg` (paper, spigot, purpur, velocity and waterfall)
+fn paper_message(line: &str) -> Option<&str> {
+    let (_time, rest) = line.strip_prefix('[')?.split_once(" INFO]: ")?;
+    Some(rest)
+}
+/// `12:00:00 [INFO] msg`
+fn bungeecord_message(line: &str) -> Option<&str> {
+    let (_time, rest) = line.split_once(" [INFO] ")?;
+    Some(rest)
+}
+
+/// chat, join/leave and other messages which look the same for all (non-proxy) servers
+fn parse_message(message: &str) -> ParseOutput {
+    let message = message.trim_end();
+    // chat messages which weren't signed (1.19+)
+    let message = message.strip_prefix("[Not Secure] ").unwrap_or(message);
+    if let Some((author, text)) = message
+        .strip_prefix('<')
+        .and_then(|rest| rest.split_once("> "))
+    {
+        return ParseOutput::Event(MinecraftServerEventType::ChatMessage(ChatMessage {
+            author: author.to_owned(),
+            message: text.to_owned(),
+        }));
+    }
+    if message == "Saved the game" {
+        return ParseOutput::Event(MinecraftServerEventType::Saved);
+    }
+    if let Some(version) = message.strip_prefix("Starting minecraft server version ") {
+        return ParseOutput::Event(MinecraftServerEventType::Version(version.to_owned()));
+    }
+    if let Some(event) = paper_tick_message(message) {
+        return ParseOutput::Event(event);
+    }
+    if let Some(username) = message.strip_suffix(" joined the game") {
+        return join_leave(username, true);
+    }
+    if let Some(username) = message.strip_suffix(" left the game") {
+        return join_leave(username, false);
+    }
+    ParseOutput::Nothing
+}
+fn join_leave(username: &str, joined: bool) -> ParseOutput {
+    ParseOutput::Event(MinecraftServerEventType::JoinLeave(
+        events::JoinLeaveEvent {
+            username: username.to_owned(),
+            joined,
+        },
+    ))
+}
+
+/// lines about the tick rate which don't look like other messages:
+/// `Can't keep up!` warnings and the second line of vanilla's `tick query` (which doesn't have a prefix)
+fn tick_message(line: &str) -> Option<MinecraftServerEventType> {
+    if let Some(rest) = line.strip_prefix("Average time per tick: ") {
+        let (mspt, _) = rest.split_once("ms")?;
+        return Some(MinecraftServerEventType::Mspt(mspt.trim().parse().ok()?));
+    }
+    let (prefix, rest) = line.split_once("Can't keep up! Is the server overloaded? Running ")?;
+    // only from the server itself, not in a chat message
+    if !prefix.contains("WARN") || prefix.contains('<') {
+        return None;
+    }
+    let (ms_behind, _) = rest.split_once("ms")?;
+    Some(MinecraftServerEventType::CantKeepUp {
+        ms_behind: ms_behind.trim().parse().ok()?,
+    })
+}
+/// the output of paper's `tps` (`TPS from last 1m, 5m, 15m: 19.9, 20.0, *20.0`)
+/// and `mspt` (`◴ 12.3/4.5/67.8, ...`, on the line after the heading), which may contain color codes
+fn paper_tick_message(message: &str) -> Option<MinecraftServerEventType> {
+    let message = strip_formatting(message);
+    if let Some(rest) = message.strip_prefix("TPS from last 1m, 5m, 15m: ") {
+        let tps = rest.split(',').next()?.trim().trim_start_matches('*');
+        return Some(MinecraftServerEventType::Tps(tps.parse().ok()?));
+    }
+    let rest = message.strip_prefix('◴')?;
+    let (avg, _) = rest.trim_start().split_once('/')?;
+    Some(MinecraftServerEventType::Mspt(avg.parse().ok()?))
+}
+/// removes `§` color codes and ansi escape sequences
+fn strip_formatting(text: &str) -> String {
+    let mut o = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '§' => {
+                chars.next();
             }
-            if let Some((_proc, stdin, stdout)) = proc {
-                if let Err(e) = writeln!(stdin, "{line}") {
-                    eprintln!("[WARN/CUSTOM-LINE-PARSER] Can't write to stdin: {e:?}");
-                    return ParseOutput::Nothing;
-                };
-                let mut buf = String::new();
-                if let Err(e) = stdout.read_line(&mut buf) {
-                    eprintln!("[WARN/CUSTOM-LINE-PARSER] Can't read_line: {e:?}");
-                    return ParseOutput::Nothing;
-                };
-                if buf.ends_with('\n') || buf.ends_with('\r') {
-                    buf.pop();
-                }
-                if buf.len() > 0 {
-                    match buf.as_bytes()[0] {
-                        b'c' => {
-                            ParseOutput::Event(MinecraftServerEventType::ChatMessage(ChatMessage {
-                                author: buf[1..].to_owned(),
-                                message: {
-                                    let mut o = String::new();
-                                    if let Err(e) = stdout.read_line(&mut o) {
-                                        eprintln!(
-                                            "[WARN/CUSTOM-LINE-PARSER] Can't read_line: {e:?}"
-                                        );
-                                        return ParseOutput::Nothing;
-                                    }
-                                    o
-                                },
-                            }))
-                        }
-                        b'j' => ParseOutput::Event(MinecraftServerEventType::JoinLeave(
-                            events::JoinLeaveEvent {
-                                username: buf[1..].to_owned(),
-                                joined: true,
-                            },
-                        )),
-                        b'l' => ParseOutput::Event(MinecraftServerEventType::JoinLeave(
-                            events::JoinLeaveEvent {
-                                username: buf[1..].to_owned(),
-                                joined: false,
-                            },
-                        )),
-                        b'e' => ParseOutput::Error({
-                            if buf.len() > 1 {
-                                match buf.as_bytes()[1] {
-                                    b'c' => ParseError::Custom(buf[2..].to_string()),
-                                    _ => ParseError::Custom(String::new()),
-                                }
-                            } else {
-                                ParseError::Custom(String::new())
-                            }
-                        }),
-                        _ => ParseOutput::Nothing,
+            '\x1b' => {
+                // `ESC [ ... m`
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
                     }
-                } else {
-                    ParseOutput::Nothing
                 }
-            } else {
-                eprintln!("[WARN/CUSTOM-LINE-PARSER] No process!");
-                ParseOutput::Nothing
             }
+            c => o.push(c),
         }
-        MinecraftServerType::VanillaMojang => {
-            if let Some((_time, rest)) = line[1..].split_once("] [Server thread/INFO]: ") {
-                let rest = rest.trim();
-                if rest.starts_with("<") {
-                    if let Some((user, msg)) = rest[1..].split_once("> ") {
-                        return ParseOutput::Event(MinecraftServerEventType::ChatMessage(
-                            ChatMessage {
-                                author: user.to_owned(),
-                                message: msg.to_owned(),
-                            },
-                        ));
-                    }
-                } else if rest.ends_with(" joined the game") {
-                    return ParseOutput::Event(MinecraftServerEventType::JoinLeave(
-                        events::JoinLeaveEvent {
-                            username: rest[0..rest.len() - " joined the game".len()].to_owned(),
-                            joined: true,
-                        },
-                    ));
-                } else if rest.ends_with(" left the game") {
-                    return ParseOutput::Event(MinecraftServerEventType::JoinLeave(
-                        events::JoinLeaveEvent {
-                            username: rest[0..rest.len() - " left the game".len()].to_owned(),
-                            joined: false,
-                        },
-                    ));
-                }
+    }
+    o
+}
+
+/// `[connected player] Steve (/127.0.0.1:54321) has connected` and `... has disconnected`
+fn velocity_join_leave(message: &str) -> Option<ParseOutput> {
+    let rest = message.trim_end().strip_prefix("[connected player] ")?;
+    let (username, rest) = rest.split_once(" (")?;
+    if rest.ends_with(") has connected") {
+        Some(join_leave(username, true))
+    } else if rest.ends_with(") has disconnected") {
+        Some(join_leave(username, false))
+    } else {
+        None
+    }
+}
+/// `[Steve,/127.0.0.1:54321] <-> InitialHandler has connected` (or `[/127.0.0.1:54321|Steve]`)
+/// and `[Steve] -> UpstreamBridge has disconnected`
+fn bungeecord_join_leave(message: &str) -> Option<ParseOutput> {
+    let (player, action) = message.trim_end().strip_prefix('[')?.split_once("] ")?;
+    let joined = match action {
+        "<-> InitialHandler has connected" => true,
+        "-> UpstreamBridge has disconnected" => false,
+        _ => return None,
+    };
+    // the player's address is included in some versions
+    let username = player
+        .split([',', '|'])
+        .find(|part| !part.starts_with('/'))?;
+    Some(join_leave(username, joined))
+}
+
+/// turns log lines which match a regex into events (`rule=<event> <regex>` in the server's config).
+/// The regex's named groups are the event's fields: `chat` needs `player` and `message`,
+/// `join` and `leave` need `player`, and `death` needs `player` (`message` is optional, the whole match is used otherwise).
+/// `custom:<kind>` rules create [`MinecraftServerEventType::Custom`] events with all named groups as fields.
+///
+/// `rule=chat ^\[[\d:]+\] \[Chat\] (?<player>\w+): (?<message>.*)$`
+#[derive(Clone, Debug)]
+pub struct LineRule {
+    pub event: LineRuleEvent,
+    pub regex: Regex,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineRuleEvent {
+    Chat,
+    Join,
+    Leave,
+    Death,
+    /// `custom:<kind>`
+    Custom(String),
+}
+impl LineRuleEvent {
+    /// all events except `custom:<kind>`
+    pub const ALL: [Self; 4] = [Self::Chat, Self::Join, Self::Leave, Self::Death];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        match name.strip_prefix("custom:") {
+            Some(kind) if !kind.is_empty() => Some(Self::Custom(kind.to_owned())),
+            Some(_) => None,
+            None => Self::ALL.into_iter().find(|event| event.name() == name),
+        }
+    }
+    /// `custom` for all `custom:<kind>` events
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Chat => "chat",
+            Self::Join => "join",
+            Self::Leave => "leave",
+            Self::Death => "death",
+            Self::Custom(_) => "custom",
+        }
+    }
+    /// the named groups a rule's regex must have
+    fn required_groups(&self) -> &'static [&'static str] {
+        match self {
+            Self::Chat => &["player", "message"],
+            Self::Join | Self::Leave | Self::Death => &["player"],
+            Self::Custom(_) => &[],
+        }
+    }
+}
+
+impl LineRule {
+    /// fails if the event is unknown, the regex is invalid or it is missing a group the event needs
+    pub fn new(event: &str, regex: &str) -> Result<Self, String> {
+        let event = LineRuleEvent::from_name(event).ok_or_else(|| {
+            format!(
+                "unknown event `{}` (available: {}, custom:<kind>)",
+                event.trim(),
+                LineRuleEvent::ALL.map(|e| e.name()).join(", ")
+            )
+        })?;
+        let regex = Regex::new(regex).map_err(|e| e.to_string())?;
+        if let Some(missing) = event
+            .required_groups()
+            .iter()
+            .find(|group| !regex.capture_names().flatten().any(|name| name == **group))
+        {
+            return Err(format!(
+                "`{}` rules need a `(?<{missing}>...)` group",
+                event.name()
+            ));
+        }
+        Ok(Self { event, regex })
+    }
+    /// `<event> <regex>`, the value of `rule=`
+    pub fn from_config(value: &str) -> Result<Self, String> {
+        let (event, regex) = value
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .ok_or("should be `rule=<event> <regex>`")?;
+        Self::new(event, regex.trim_start())
+    }
+    /// the opposite of [`Self::from_config`]
+    pub fn to_config(&self) -> String {
+        match &self.event {
+            LineRuleEvent::Custom(kind) => format!("custom:{kind} {}", self.regex.as_str()),
+            event => format!("{} {}", event.name(), self.regex.as_str()),
+        }
+    }
+    /// the event for `line`, if the regex matches it (and all groups the event needs took part in the match)
+    pub fn apply(&self, line: &str) -> Option<MinecraftServerEventType> {
+        let captures = self.regex.captures(line)?;
+        let group = |name| captures.name(name).map(|m| m.as_str().to_owned());
+        Some(match &self.event {
+            LineRuleEvent::Chat => MinecraftServerEventType::ChatMessage(ChatMessage {
+                author: group("player")?,
+                message: group("message")?,
+            }),
+            LineRuleEvent::Join | LineRuleEvent::Leave => {
+                MinecraftServerEventType::JoinLeave(events::JoinLeaveEvent {
+                    username: group("player")?,
+                    joined: self.event == LineRuleEvent::Join,
+                })
             }
-            ParseOutput::Nothing
-            // Vanilla servers not yet supported...
+            LineRuleEvent::Death => MinecraftServerEventType::Death(events::DeathEvent {
+                username: group("player")?,
+                message: group("message").unwrap_or_else(|| captures[0].to_owned()),
+            }),
+            LineRuleEvent::Custom(kind) => MinecraftServerEventType::Custom {
+                kind: kind.clone(),
+                fields: self
+                    .regex
+                    .capture_names()
+                    .flatten()
+                    .filter_map(|name| Some((name.to_owned(), group(name)?)))
+                    .collect(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the event the server type's own parsing gets from the line
+    fn parse(server_type: MinecraftServerType, line: &str) -> Option<MinecraftServerEventType> {
+        let settings = MinecraftServerSettings::new(
+            server_type,
+            "/nonexistent".to_owned(),
+            "server.jar".to_owned(),
+        );
+        match parse_line(line, &settings) {
+            ParseOutput::Event(event) => Some(event),
+            _ => None,
         }
-        MinecraftServerType::VanillaPaperMC => {
-            match line.chars().next() {
-                Some('[') => {
-                    if let Some((_time, rest)) = line[1..].split_once(' ') {
-                        if let Some((severity, rest)) = rest.split_once(']') {
-                            if rest.starts_with(": ") {
-                                let rest = &rest[2..];
-                                // eprintln!("Time: '{time}', Severity: '{severity}', Rest: '{rest}'.");
-                                match severity {
-                                    "INFO" => {
-                                        if let Some('<') = rest.chars().next() {
-                                            if let Some((username, message)) =
-                                                rest[1..].split_once('>')
-                                            {
-                                                return ParseOutput::Event(
-                                                    MinecraftServerEventType::ChatMessage(
-                                                        ChatMessage {
-                                                            author: username.to_string(),
-                                                            message: message[1..].to_string(),
-                                                        },
-                                                    ),
-                                                );
-                                            }
-                                        } // join/leave
-                                        if rest.trim_end().ends_with(" joined the game") {
-                                            let username = &rest[..rest.len() - 16];
-                                            return ParseOutput::Event(
-                                                MinecraftServerEventType::JoinLeave(
-                                                    events::JoinLeaveEvent {
-                                                        username: username.to_string(),
-                                                        joined: true,
-                                                    },
-                                                ),
-                                            );
-                                        }
-                                        if rest.trim_end().ends_with(" left the game") {
-                                            let username = &rest[..rest.len() - 14];
-                                            return ParseOutput::Event(
-                                                MinecraftServerEventType::JoinLeave(
-                                                    events::JoinLeaveEvent {
-                                                        username: username.to_string(),
-                                                        joined: false,
-                                                    },
-                                                ),
-                                            );
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                    }
-                }
-                _ => (),
+    }
+
+    const DEATH_RULE: &str =
+        r"death ^\[[^\]]+\]( \[Server thread/INFO\])?: (?<player>\w+) (was slain|fell|drowned).*$";
+
+    #[test]
+    fn rule_needs_a_known_event() {
+        let e = LineRule::new("explode", "(?<player>.*)").unwrap_err();
+        assert!(e.contains("unknown event `explode`"), "{e}");
+        assert!(LineRule::new("custom:", ".*").is_err());
+    }
+
+    #[test]
+    fn rule_needs_a_valid_regex() {
+        assert!(LineRule::new("join", "(?<player>\\w+").is_err());
+    }
+
+    #[test]
+    fn rule_needs_the_event_s_groups() {
+        let e = LineRule::new("chat", r"^<(?<player>\w+)> .*$").unwrap_err();
+        assert_eq!(e, "`chat` rules need a `(?<message>...)` group");
+        let e = LineRule::new("join", r"^(\w+) joined$").unwrap_err();
+        assert_eq!(e, "`join` rules need a `(?<player>...)` group");
+        assert!(LineRule::new("death", r"^(?<player>\w+) died$").is_ok());
+        assert!(LineRule::new("custom:boss_kill", r"^boss killed$").is_ok());
+    }
+
+    #[test]
+    fn config_round_trip() {
+        for config in [
+            DEATH_RULE,
+            r"chat ^\[[\d:]+\] \[Chat\] (?<player>\w+): (?<message>.*)$",
+            r"leave (?<player>\w+) left",
+            r"custom:boss_kill (?<player>\w+) killed the (?<boss>\w+)",
+        ] {
+            assert_eq!(LineRule::from_config(config).unwrap().to_config(), config);
+        }
+        assert!(LineRule::from_config("chat").is_err());
+    }
+
+    #[test]
+    fn death_rule_matches_vanilla_and_paper_lines() {
+        let rule = LineRule::from_config(DEATH_RULE).unwrap();
+        for line in [
+            "[12:00:00] [Server thread/INFO]: Steve was slain by Zombie",
+            "[12:00:00 INFO]: Steve was slain by Zombie",
+        ] {
+            assert!(
+                matches!(
+                    rule.apply(line),
+                    Some(MinecraftServerEventType::Death(events::DeathEvent { username, message }))
+                        if username == "Steve" && message == line
+                ),
+                "{line}"
+            );
+        }
+        // a chat message isn't a death
+        assert!(rule
+            .apply("[12:00:00 INFO]: <Alex> Steve fell off")
+            .is_none());
+        assert!(rule
+            .apply("[12:00:00] [Server thread/INFO]: <Alex> Steve drowned")
+            .is_none());
+    }
+
+    #[test]
+    fn chat_and_join_rules() {
+        let chat = LineRule::from_config(
+            r"chat ^\[[^\]]+\] \[Server thread/INFO\]: \[Discord\] (?<player>\w+): (?<message>.*)$",
+        )
+        .unwrap();
+        assert!(matches!(
+            chat.apply("[12:00:00] [Server thread/INFO]: [Discord] Alex: hi there"),
+            Some(MinecraftServerEventType::ChatMessage(ChatMessage { author, message }))
+                if author == "Alex" && message == "hi there"
+        ));
+        let join = LineRule::from_config(r"join ^\[[^\]]+ INFO\]: (?<player>\w+) joined the game$")
+            .unwrap();
+        assert!(matches!(
+            join.apply("[12:00:00 INFO]: Steve joined the game"),
+            Some(MinecraftServerEventType::JoinLeave(events::JoinLeaveEvent { username, joined: true }))
+                if username == "Steve"
+        ));
+        assert!(join.apply("[12:00:00 INFO]: Steve left the game").is_none());
+    }
+
+    #[test]
+    fn optional_groups_must_take_part() {
+        let rule = LineRule::from_config(r"chat ^(?<player>\w+)(: (?<message>.*))?$").unwrap();
+        assert!(rule.apply("Steve").is_none());
+        assert!(rule.apply("Steve: hi").is_some());
+    }
+
+    #[test]
+    fn custom_rules_use_all_named_groups() {
+        let rule =
+            LineRule::from_config(r"custom:boss_kill (?<player>\w+) killed the (?<boss>\w+)")
+                .unwrap();
+        let Some(MinecraftServerEventType::Custom { kind, fields }) =
+            rule.apply("[12:00:00 INFO]: Steve killed the Wither")
+        else {
+            panic!("no custom event");
+        };
+        assert_eq!(kind, "boss_kill");
+        assert_eq!(fields["player"], "Steve");
+        assert_eq!(fields["boss"], "Wither");
+    }
+
+    #[test]
+    fn paper_tps_and_mspt() {
+        for line in [
+            "[12:00:00 INFO]: §6TPS from last 1m, 5m, 15m: §a19.98, §a20.0, §a20.0",
+            "[12:00:00 INFO]: \x1b[0;33;22mTPS from last 1m, 5m, 15m: \x1b[0;32;1m19.98, \x1b[0;32;1m20.0, \x1b[0;32;1m20.0\x1b[m",
+        ] {
+            assert!(
+                matches!(parse(MinecraftServerType::VanillaPaperMC, line), Some(MinecraftServerEventType::Tps(tps)) if tps == 19.98),
+                "{line}"
+            );
+        }
+        // `*` means the tps is higher than shown
+        assert!(matches!(
+            parse(
+                MinecraftServerType::Purpur,
+                "[12:00:00 INFO]: §6TPS from last 1m, 5m, 15m: §a*20.0, §a*20.0, §a*20.0"
+            ),
+            Some(MinecraftServerEventType::Tps(tps)) if tps == 20.0
+        ));
+        // the heading of `mspt` has no values, the line after it does
+        assert!(parse(
+            MinecraftServerType::VanillaPaperMC,
+            "[12:00:00 INFO]: §6Server tick times §e(§7avg§e/§7min§e/§7max§e)§6 from last 5s§7,§6 10s§7,§6 1m§e:"
+        )
+        .is_none());
+        assert!(matches!(
+            parse(
+                MinecraftServerType::VanillaPaperMC,
+                "[12:00:00 INFO]: §6◴ §a12.3§7/§a4.5§7/§a67.8§e, §a11.9§7/§a4.1§7/§a67.8§e, §a10.2§7/§a3.9§7/§a98.1"
+            ),
+            Some(MinecraftServerEventType::Mspt(mspt)) if mspt == 12.3
+        ));
+        // players can't fake it
+        assert!(matches!(
+            parse(
+                MinecraftServerType::VanillaPaperMC,
+                "[12:00:00 INFO]: <Steve> TPS from last 1m, 5m, 15m: 1.0, 1.0, 1.0"
+            ),
+            Some(MinecraftServerEventType::ChatMessage(_))
+        ));
+    }
+
+    #[test]
+    fn vanilla_tick_query() {
+        // the first lines of `tick query` don't contain numbers
+        for line in [
+            "[12:00:00] [Server thread/INFO]: The game is running normally",
+            "[12:00:00] [Server thread/INFO]: Target tick rate: 20.0 per second.",
+            "Percentiles: P50: 2.9ms P95: 4.1ms P99: 5.6ms, sample: 100",
+        ] {
+            assert!(
+                parse(MinecraftServerType::VanillaMojang, line).is_none(),
+                "{line}"
+            );
+        }
+        for server_type in [
+            MinecraftServerType::VanillaMojang,
+            MinecraftServerType::Fabric,
+        ] {
+            assert!(matches!(
+                parse(server_type, "Average time per tick: 3.2ms (Target: 50.0ms)"),
+                Some(MinecraftServerEventType::Mspt(mspt)) if mspt == 3.2
+            ));
+        }
+        assert!(parse(
+            MinecraftServerType::VanillaMojang,
+            "Average time per tick: ms"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn cant_keep_up() {
+        for (server_type, line) in [
+            (
+                MinecraftServerType::VanillaMojang,
+                "[12:00:00] [Server thread/WARN]: Can't keep up! Is the server overloaded? Running 2043ms or 40 ticks behind",
+            ),
+            (
+                MinecraftServerType::VanillaPaperMC,
+                "[12:00:00 WARN]: Can't keep up! Is the server overloaded? Running 2043ms or 40 ticks behind",
+            ),
+            (
+                MinecraftServerType::Forge,
+                "[12:00:00] [Server thread/WARN] [minecraft/MinecraftServer]: Can't keep up! Is the server overloaded? Running 2043ms or 40 ticks behind",
+            ),
+        ] {
+            assert!(
+                matches!(
+                    parse(server_type, line),
+                    Some(MinecraftServerEventType::CantKeepUp { ms_behind: 2043 })
+                ),
+                "{line}"
+            );
+        }
+        for line in [
+            "[12:00:00] [Server thread/INFO]: <Steve> Can't keep up! Is the server overloaded? Running 2043ms or 40 ticks behind",
+            "[12:00:00 INFO]: <WARN> Can't keep up! Is the server overloaded? Running 2043ms or 40 ticks behind",
+        ] {
+            assert!(
+                !matches!(
+                    parse(MinecraftServerType::VanillaPaperMC, line),
+                    Some(MinecraftServerEventType::CantKeepUp { .. })
+                ),
+                "{line}"
+            );
+        }
+    }
+
+    /// the chat message's author and text, or the joined/left player
+    fn describe(event: Option<MinecraftServerEventType>) -> Option<String> {
+        Some(match event? {
+            MinecraftServerEventType::ChatMessage(ChatMessage { author, message }) => {
+                format!("<{author}> {message}")
             }
-            ParseOutput::Nothing
+            MinecraftServerEventType::JoinLeave(events::JoinLeaveEvent { username, joined }) => {
+                format!("{username} {}", if joined { "joined" } else { "left" })
+            }
+            _ => return None,
+        })
+    }
+
+    #[test]
+    fn server_log_lines() {
+        use MinecraftServerType::*;
+        for (server_type, line, expected) in [
+            // fabric 0.15 (1.20.4), and older versions which log the logger's name
+            (Fabric, "[18:31:02] [Server thread/INFO]: <Steve> hello there", Some("<Steve> hello there")),
+            (Fabric, "[18:31:02] [Server thread/INFO]: [Not Secure] <Steve> hi", Some("<Steve> hi")),
+            (Fabric, "[18:31:02] [Server thread/INFO] (Minecraft) <Steve> hi", Some("<Steve> hi")),
+            (Fabric, "[18:30:51] [Server thread/INFO]: Steve joined the game", Some("Steve joined")),
+            (Fabric, "[18:35:10] [Server thread/INFO]: Steve left the game", Some("Steve left")),
+            // forge 47 (1.20.1)
+            (Forge, "[18:31:02] [Server thread/INFO] [minecraft/MinecraftServer]: <Steve> hello there", Some("<Steve> hello there")),
+            (Forge, "[18:30:51] [Server thread/INFO] [minecraft/MinecraftServer]: Steve joined the game", Some("Steve joined")),
+            (Forge, "[18:35:10] [Server thread/INFO] [minecraft/MinecraftServer]: Steve left the game", Some("Steve left")),
+            (NeoForge, "[18:31:02] [Server thread/INFO] [minecraft/MinecraftServer]: <Steve> hi", Some("<Steve> hi")),
+            // mods can't fake messages
+            (Forge, "[18:31:02] [Server thread/INFO] [somemod/]: <Steve> hi", None),
+            (Forge, "[18:30:51] [Server thread/INFO] [somemod/]: Steve joined the game", None),
+            // paper 1.20.4
+            (VanillaPaperMC, "[18:31:02 INFO]: <Steve> hello there", Some("<Steve> hello there")),
+            (VanillaPaperMC, "[18:31:02 INFO]: [Not Secure] <Steve> hi", Some("<Steve> hi")),
+            (VanillaPaperMC, "[18:30:51 INFO]: Steve joined the game", Some("Steve joined")),
+            (VanillaPaperMC, "[18:35:10 INFO]: Steve left the game", Some("Steve left")),
+            (VanillaPaperMC, "[18:30:51 INFO]: Steve[/127.0.0.1:54321] logged in with entity id 123 at ([world]0.5, 64.0, 0.5)", None),
+            (Spigot, "[18:31:02 INFO]: <Steve> hi", Some("<Steve> hi")),
+            (Purpur, "[18:30:51 INFO]: Steve joined the game", Some("Steve joined")),
+            // velocity 3.3
+            (Velocity, "[18:30:50 INFO]: [connected player] Steve (/127.0.0.1:54321) has connected", Some("Steve joined")),
+            (Velocity, "[18:35:10 INFO]: [connected player] Steve (/127.0.0.1:54321) has disconnected", Some("Steve left")),
+            (Velocity, "[18:30:50 INFO]: [server connection] Steve -> lobby has connected", None),
+            (Velocity, "[18:31:02 INFO]: <Steve> hi", None),
+            // bungeecord and waterfall
+            (BungeeCord, "18:30:50 [INFO] [Steve,/127.0.0.1:54321] <-> InitialHandler has connected", Some("Steve joined")),
+            (BungeeCord, "18:35:10 [INFO] [Steve] -> UpstreamBridge has disconnected", Some("Steve left")),
+            (BungeeCord, "[18:30:50 INFO]: [/127.0.0.1:54321|Steve] <-> InitialHandler has connected", Some("Steve joined")),
+            (BungeeCord, "[18:35:10 INFO]: [Steve] -> UpstreamBridge has disconnected", Some("Steve left")),
+            (BungeeCord, "[18:30:50 INFO]: [Steve] <-> ServerConnector [lobby] has connected", None),
+            (BungeeCord, "[18:31:02 INFO]: <Steve> hi", None),
+        ] {
+            let name = server_type.to_string();
+            assert_eq!(
+                describe(parse(server_type, line)).as_deref(),
+                expected,
+                "{name}: {line}"
+            );
         }
     }
 }
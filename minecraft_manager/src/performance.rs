@@ -0,0 +1,166 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// how long tick samples and `Can't keep up!` warnings are kept
+const HISTORY: Duration = Duration::from_secs(60 * 60);
+/// a paper server's `tps` and `mspt` output which is this close together belongs to the same sample
+const SAME_SAMPLE: Duration = Duration::from_secs(5);
+/// minecraft's normal tick rate
+pub const TARGET_TPS: f32 = 20.0;
+
+/// the server's tick rate at one point in time
+#[derive(Clone, Copy, Debug)]
+pub struct TickSample {
+    pub time: Instant,
+    /// ticks per second. Calculated from `mspt` if the server only logs that (vanilla's `tick query`).
+    pub tps: f32,
+    /// average milliseconds per tick, if the server logs it
+    pub mspt: Option<f32>,
+}
+
+/// the server's recent tick rates and `Can't keep up!` warnings (see [`crate::MinecraftServerType::tick_query_commands`])
+#[derive(Default)]
+pub struct Performance {
+    /// oldest first
+    samples: VecDeque<TickSample>,
+    /// when the server couldn't keep up, and how many milliseconds it was behind
+    cant_keep_up: VecDeque<(Instant, u64)>,
+}
+
+impl Performance {
+    pub fn add_tps(&mut self, tps: f32) {
+        self.add_tps_at(tps, Instant::now());
+    }
+    fn add_tps_at(&mut self, tps: f32, now: Instant) {
+        match self.samples.back_mut() {
+            // `mspt` was logged first
+            Some(sample)
+                if now.duration_since(sample.time) < SAME_SAMPLE && sample.mspt.is_some() =>
+            {
+                sample.tps = tps;
+            }
+            _ => self.samples.push_back(TickSample {
+                time: now,
+                tps,
+                mspt: None,
+            }),
+        }
+        self.prune();
+    }
+    pub fn add_mspt(&mut self, mspt: f32) {
+        self.add_mspt_at(mspt, Instant::now());
+    }
+    fn add_mspt_at(&mut self, mspt: f32, now: Instant) {
+        match self.samples.back_mut() {
+            // paper's `tps` was logged first
+            Some(sample)
+                if now.duration_since(sample.time) < SAME_SAMPLE && sample.mspt.is_none() =>
+            {
+                sample.mspt = Some(mspt);
+            }
+            _ => self.samples.push_back(TickSample {
+                time: now,
+                tps: TARGET_TPS.min(1000.0 / mspt.max(0.001)),
+                mspt: Some(mspt),
+            }),
+        }
+        self.prune();
+    }
+    pub fn add_cant_keep_up(&mut self, ms_behind: u64) {
+        self.cant_keep_up.push_back((Instant::now(), ms_behind));
+        self.prune();
+    }
+    fn prune(&mut self) {
+        while self
+            .samples
+            .front()
+            .is_some_and(|sample| sample.time.elapsed() > HISTORY)
+        {
+            self.samples.pop_front();
+        }
+        while self
+            .cant_keep_up
+            .front()
+            .is_some_and(|(time, _)| time.elapsed() > HISTORY)
+        {
+            self.cant_keep_up.pop_front();
+        }
+    }
+
+    /// the samples from the last hour, oldest first
+    pub fn samples(&self) -> impl Iterator<Item = &TickSample> {
+        self.samples.iter()
+    }
+    pub fn latest(&self) -> Option<&TickSample> {
+        self.samples.back()
+    }
+    /// the lowest tps within `window` (at most an hour)
+    pub fn min_tps(&self, window: Duration) -> Option<f32> {
+        self.samples
+            .iter()
+            .filter(|sample| sample.time.elapsed() <= window)
+            .map(|sample| sample.tps)
+            .reduce(f32::min)
+    }
+    /// how often the server couldn't keep up within `window` (at most an hour)
+    pub fn cant_keep_up_count(&self, window: Duration) -> usize {
+        self.cant_keep_up
+            .iter()
+            .filter(|(time, _)| time.elapsed() <= window)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tps_and_mspt_are_merged() {
+        let start = Instant::now();
+        let mut performance = Performance::default();
+        // paper logs `tps` first
+        performance.add_tps_at(19.5, start);
+        performance.add_mspt_at(12.5, start + Duration::from_secs(1));
+        // and then `mspt` first
+        performance.add_mspt_at(60.0, start + Duration::from_secs(30));
+        performance.add_tps_at(16.0, start + Duration::from_secs(34));
+        let samples: Vec<_> = performance.samples().map(|s| (s.tps, s.mspt)).collect();
+        assert_eq!(samples, [(19.5, Some(12.5)), (16.0, Some(60.0))]);
+    }
+
+    #[test]
+    fn samples_too_far_apart_are_not_merged() {
+        let start = Instant::now();
+        let mut performance = Performance::default();
+        performance.add_tps_at(20.0, start);
+        performance.add_mspt_at(12.5, start + SAME_SAMPLE);
+        // vanilla's `tick query` only logs mspt, so tps is calculated
+        performance.add_mspt_at(100.0, start + SAME_SAMPLE * 3);
+        performance.add_tps_at(10.0, start + SAME_SAMPLE * 4);
+        let samples: Vec<_> = performance.samples().map(|s| (s.tps, s.mspt)).collect();
+        assert_eq!(
+            samples,
+            [
+                (20.0, None),
+                (TARGET_TPS, Some(12.5)),
+                (10.0, Some(100.0)),
+                (10.0, None)
+            ]
+        );
+        assert_eq!(performance.latest().unwrap().tps, 10.0);
+    }
+
+    #[test]
+    fn cant_keep_up_warnings_are_counted() {
+        let mut performance = Performance::default();
+        assert_eq!(performance.min_tps(HISTORY), None);
+        performance.add_tps(19.0);
+        performance.add_cant_keep_up(2043);
+        performance.add_cant_keep_up(5000);
+        assert_eq!(performance.cant_keep_up_count(HISTORY), 2);
+        assert_eq!(performance.min_tps(HISTORY), Some(19.0));
+    }
+}
@@ -0,0 +1,566 @@
+use std::{fmt::Display, path::Path};
+
+/// A `server.properties` file. Comments, unknown keys and the order of all lines are preserved when saving.
+pub struct ServerProperties {
+    lines: Vec<Line>,
+}
+
+enum Line {
+    /// comments, empty lines, or anything else which isn't a property
+    Other(String),
+    Property {
+        key: String,
+        value: String,
+        /// the line as it was in the file, if the property wasn't changed
+        raw: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+pub enum PropertiesError {
+    /// the key isn't a known server property and isn't in the file yet
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        value: String,
+        expected: String,
+    },
+}
+impl Display for PropertiesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownKey(key) => write!(f, "Unknown property '{key}'"),
+            Self::InvalidValue {
+                key,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Invalid value '{value}' for '{key}', expected {expected}"
+            ),
+        }
+    }
+}
+impl std::error::Error for PropertiesError {}
+
+#[derive(Clone, Copy)]
+pub enum PropertyType {
+    Bool,
+    Int { min: i64, max: i64 },
+    String,
+    OneOf(&'static [&'static str]),
+}
+impl Display for PropertyType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool => write!(f, "true or false"),
+            Self::Int { min, max } => write!(f, "a number from {min} to {max}"),
+            Self::String => write!(f, "text"),
+            Self::OneOf(values) => write!(f, "one of {}", values.join(", ")),
+        }
+    }
+}
+
+const MAX: i64 = i32::MAX as i64;
+/// the properties of vanilla servers, and their types
+pub const KNOWN_PROPERTIES: &[(&str, PropertyType)] = &[
+    ("allow-flight", PropertyType::Bool),
+    ("allow-nether", PropertyType::Bool),
+    ("broadcast-console-to-ops", PropertyType::Bool),
+    ("broadcast-rcon-to-ops", PropertyType::Bool),
+    (
+        "difficulty",
+        PropertyType::OneOf(&["peaceful", "easy", "normal", "hard"]),
+    ),
+    ("enable-command-block", PropertyType::Bool),
+    ("enable-jmx-monitoring", PropertyType::Bool),
+    ("enable-query", PropertyType::Bool),
+    ("enable-rcon", PropertyType::Bool),
+    ("enable-status", PropertyType::Bool),
+    ("enforce-secure-profile", PropertyType::Bool),
+    ("enforce-whitelist", PropertyType::Bool),
+    (
+        "entity-broadcast-range-percentage",
+        PropertyType::Int { min: 10, max: 1000 },
+    ),
+    ("force-gamemode", PropertyType::Bool),
+    (
+        "function-permission-level",
+        PropertyType::Int { min: 1, max: 4 },
+    ),
+    (
+        "gamemode",
+        PropertyType::OneOf(&["survival", "creative", "adventure", "spectator"]),
+    ),
+    ("generate-structures", PropertyType::Bool),
+    ("generator-settings", PropertyType::String),
+    ("hardcore", PropertyType::Bool),
+    ("hide-online-players", PropertyType::Bool),
+    ("initial-disabled-packs", PropertyType::String),
+    ("initial-enabled-packs", PropertyType::String),
+    ("level-name", PropertyType::String),
+    ("level-seed", PropertyType::String),
+    ("level-type", PropertyType::String),
+    ("log-ips", PropertyType::Bool),
+    (
+        "max-chained-neighbor-updates",
+        PropertyType::Int { min: -1, max: MAX },
+    ),
+    ("max-players", PropertyType::Int { min: 0, max: MAX }),
+    (
+        "max-tick-time",
+        PropertyType::Int {
+            min: -1,
+            max: i64::MAX,
+        },
+    ),
+    (
+        "max-world-size",
+        PropertyType::Int {
+            min: 1,
+            max: 29999984,
+        },
+    ),
+    ("motd", PropertyType::String),
+    (
+        "network-compression-threshold",
+        PropertyType::Int { min: -1, max: MAX },
+    ),
+    ("online-mode", PropertyType::Bool),
+    ("op-permission-level", PropertyType::Int { min: 0, max: 4 }),
+    (
+        "player-idle-timeout",
+        PropertyType::Int { min: 0, max: MAX },
+    ),
+    ("prevent-proxy-connections", PropertyType::Bool),
+    ("pvp", PropertyType::Bool),
+    ("query.port", PropertyType::Int { min: 1, max: 65535 }),
+    ("rate-limit", PropertyType::Int { min: 0, max: MAX }),
+    ("rcon.password", PropertyType::String),
+    ("rcon.port", PropertyType::Int { min: 1, max: 65535 }),
+    ("require-resource-pack", PropertyType::Bool),
+    ("resource-pack", PropertyType::String),
+    ("resource-pack-prompt", PropertyType::String),
+    ("resource-pack-sha1", PropertyType::String),
+    ("server-ip", PropertyType::String),
+    ("server-port", PropertyType::Int { min: 1, max: 65535 }),
+    ("simulation-distance", PropertyType::Int { min: 3, max: 32 }),
+    ("spawn-animals", PropertyType::Bool),
+    ("spawn-monsters", PropertyType::Bool),
+    ("spawn-npcs", PropertyType::Bool),
+    ("spawn-protection", PropertyType::Int { min: 0, max: MAX }),
+    ("sync-chunk-writes", PropertyType::Bool),
+    ("text-filtering-config", PropertyType::String),
+    ("use-native-transport", PropertyType::Bool),
+    ("view-distance", PropertyType::Int { min: 3, max: 32 }),
+    ("white-list", PropertyType::Bool),
+];
+
+/// properties whose values shouldn't be shown to users
+pub const SECRET_PROPERTIES: &[&str] = &["rcon.password"];
+
+pub fn property_type(key: &str) -> Option<PropertyType> {
+    KNOWN_PROPERTIES
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, t)| *t)
+}
+
+/// checks if `value` is valid for the (known) property `key`.
+/// Returns `Ok(false)` if the property isn't known.
+pub fn validate(key: &str, value: &str) -> Result<bool, PropertiesError> {
+    let Some(property_type) = property_type(key) else {
+        return Ok(false);
+    };
+    let valid = match property_type {
+        PropertyType::Bool => value == "true" || value == "false",
+        PropertyType::Int { min, max } => {
+            value.parse::<i64>().is_ok_and(|v| (min..=max).contains(&v))
+        }
+        PropertyType::String => !value.contains(['\n', '\r']),
+        PropertyType::OneOf(values) => values.contains(&value),
+    };
+    if valid {
+        Ok(true)
+    } else {
+        Err(PropertiesError::InvalidValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected: property_type.to_string(),
+        })
+    }
+}
+
+/// the command which applies the property to a running server, if there is one.
+/// Changes to all other properties only take effect after a restart.
+pub fn live_command(key: &str, value: &str) -> Option<String> {
+    match key {
+        "difficulty" => Some(format!("difficulty {value}")),
+        "white-list" => Some(format!(
+            "whitelist {}",
+            if value == "true" { "on" } else { "off" }
+        )),
+        _ => None,
+    }
+}
+
+impl ServerProperties {
+    pub fn parse(file: &str) -> Self {
+        Self {
+            lines: file
+                .lines()
+                .map(|line| {
+                    let trimmed = line.trim_start();
+                    if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('!') {
+                        return Line::Other(line.to_owned());
+                    }
+                    let (key, value) = split_key_value(trimmed);
+                    Line::Property {
+                        key: unescape(key),
+                        value: unescape(value),
+                        raw: Some(line.to_owned()),
+                    }
+                })
+                .collect(),
+        }
+    }
+    /// reads the file, or returns an empty [`ServerProperties`] if it doesn't exist
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(file) => Ok(Self::parse(&file)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self { lines: vec![] }),
+            Err(e) => Err(e),
+        }
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+    /// all properties in the order they appear in the file
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Property { key, value, .. } => Some((key.as_str(), value.as_str())),
+            Line::Other(_) => None,
+        })
+    }
+    /// validates and sets the property. Unknown properties can only be changed if they are already in the file.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), PropertiesError> {
+        let known = validate(key, value)?;
+        for line in &mut self.lines {
+            if let Line::Property {
+                key: k,
+                value: v,
+                raw,
+            } = line
+            {
+                if k == key {
+                    *v = value.to_owned();
+                    *raw = None;
+                    return Ok(());
+                }
+            }
+        }
+        if known {
+            self.lines.push(Line::Property {
+                key: key.to_owned(),
+                value: value.to_owned(),
+                raw: None,
+            });
+            Ok(())
+        } else {
+            Err(PropertiesError::UnknownKey(key.to_owned()))
+        }
+    }
+}
+
+impl Display for ServerProperties {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Other(line)
+                | Line::Property {
+                    raw: Some(line), ..
+                } => writeln!(f, "{line}")?,
+                Line::Property {
+                    key,
+                    value,
+                    raw: None,
+                } => writeln!(f, "{}={}", escape(key, true), escape(value, false))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// splits at the first unescaped `=` or `:` (or whitespace, if there is no `=` or `:`)
+fn split_key_value(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '=' | ':' => return (line[..i].trim_end(), line[i + 1..].trim_start()),
+            ch if ch.is_whitespace() => {
+                let rest = line[i..].trim_start();
+                let rest = rest
+                    .strip_prefix(['=', ':'])
+                    .map(|r| r.trim_start())
+                    .unwrap_or(rest);
+                return (&line[..i], rest);
+            }
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+/// resolves java properties escapes (`\n`, `\t`, `\uXXXX`, `\=`, ...)
+fn unescape(s: &str) -> String {
+    let mut o = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            o.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => o.push('\n'),
+            Some('r') => o.push('\r'),
+            Some('t') => o.push('\t'),
+            Some('f') => o.push('\u{c}'),
+            Some('u') => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                let unit = u16::from_str_radix(&hex, 16).unwrap_or(0xFFFD);
+                // surrogate pairs are written as two \u escapes
+                if (0xD800..0xDC00).contains(&unit) && chars.as_str().starts_with("\\u") {
+                    let low = u16::from_str_radix(chars.as_str().get(2..6).unwrap_or(""), 16);
+                    if let Ok(low) = low {
+                        chars.by_ref().nth(5);
+                        o.extend(char::decode_utf16([unit, low]).map(|c| c.unwrap_or('\u{FFFD}')));
+                        continue;
+                    }
+                }
+                o.push(char::from_u32(unit as u32).unwrap_or('\u{FFFD}'));
+            }
+            Some(ch) => o.push(ch),
+            None => {}
+        }
+    }
+    o
+}
+
+/// escapes special and non-ascii characters like minecraft does
+fn escape(s: &str, is_key: bool) -> String {
+    let mut o = String::with_capacity(s.len());
+    for (i, ch) in s.chars().enumerate() {
+        match ch {
+            '\\' => o.push_str("\\\\"),
+            '\n' => o.push_str("\\n"),
+            '\r' => o.push_str("\\r"),
+            '\t' => o.push_str("\\t"),
+            '=' | ':' | '#' | '!' => {
+                o.push('\\');
+                o.push(ch);
+            }
+            ' ' if is_key || i == 0 => o.push_str("\\ "),
+            ch if ch.is_ascii() && !ch.is_ascii_control() => o.push(ch),
+            ch => {
+                let mut buf = [0; 2];
+                for unit in ch.encode_utf16(&mut buf) {
+                    o.push_str(&format!("\\u{unit:04X}"));
+                }
+            }
+        }
+    }
+    o
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the start of a server.properties written by a 1.20.4 server
+    const FILE: &str = "#Minecraft server properties\n\
+        #Sat Mar 09 14:02:11 CET 2024\n\
+        enable-jmx-monitoring=false\n\
+        rcon.port=25575\n\
+        level-seed=\n\
+        gamemode=survival\n\
+        enable-command-block=false\n\
+        enable-query=false\n\
+        generator-settings={}\n\
+        enforce-secure-profile=true\n\
+        level-name=world\n\
+        motd=A Minecraft Server\n\
+        query.port=25565\n\
+        pvp=true\n\
+        generate-structures=true\n\
+        max-chained-neighbor-updates=1000000\n\
+        difficulty=easy\n\
+        network-compression-threshold=256\n\
+        max-tick-time=60000\n\
+        require-resource-pack=false\n\
+        use-native-transport=true\n\
+        max-players=20\n\
+        online-mode=true\n\
+        enable-status=true\n\
+        allow-flight=false\n\
+        initial-disabled-packs=\n\
+        broadcast-rcon-to-ops=true\n\
+        view-distance=10\n\
+        server-ip=\n\
+        resource-pack-prompt=\n\
+        allow-nether=true\n\
+        server-port=25565\n\
+        enable-rcon=false\n\
+        sync-chunk-writes=true\n\
+        op-permission-level=4\n\
+        prevent-proxy-connections=false\n\
+        hide-online-players=false\n\
+        resource-pack=\n\
+        entity-broadcast-range-percentage=100\n\
+        simulation-distance=10\n\
+        rcon.password=\n\
+        player-idle-timeout=0\n\
+        debug=false\n\
+        force-gamemode=false\n\
+        rate-limit=0\n\
+        hardcore=false\n\
+        white-list=false\n\
+        broadcast-console-to-ops=true\n\
+        spawn-npcs=true\n\
+        spawn-animals=true\n\
+        log-ips=true\n\
+        function-permission-level=2\n\
+        initial-enabled-packs=vanilla\n\
+        level-type=minecraft\\:normal\n\
+        text-filtering-config=\n\
+        spawn-monsters=true\n\
+        enforce-whitelist=false\n\
+        spawn-protection=16\n\
+        resource-pack-sha1=\n\
+        max-world-size=29999984\n";
+
+    #[test]
+    fn unchanged_files_are_kept() {
+        let properties = ServerProperties::parse(FILE);
+        assert_eq!(properties.to_string(), FILE);
+        assert_eq!(properties.get("level-type"), Some("minecraft:normal"));
+        assert_eq!(properties.get("level-seed"), Some(""));
+        assert_eq!(properties.get("debug"), Some("false"));
+        assert_eq!(properties.iter().count(), 58);
+    }
+
+    #[test]
+    fn set_only_changes_its_line() {
+        let mut properties = ServerProperties::parse(FILE);
+        properties.set("motd", "§aHello: world").unwrap();
+        properties.set("max-players", "10").unwrap();
+        let expected = FILE
+            .replace("motd=A Minecraft Server\n", "motd=\\u00A7aHello\\: world\n")
+            .replace("max-players=20\n", "max-players=10\n");
+        assert_eq!(properties.to_string(), expected);
+        assert_eq!(
+            ServerProperties::parse(&expected).get("motd"),
+            Some("§aHello: world")
+        );
+
+        // unknown keys which are in the file can be changed, new ones are appended
+        properties.set("debug", "true").unwrap();
+        properties.set("hardcore", "true").unwrap();
+        properties.set("rcon.password", "s3cret").unwrap();
+        assert!(matches!(
+            properties.set("not-a-property", "1"),
+            Err(PropertiesError::UnknownKey(_))
+        ));
+        let mut properties = ServerProperties::parse("#comment\npvp=true\n");
+        properties.set("difficulty", "hard").unwrap();
+        assert_eq!(
+            properties.to_string(),
+            "#comment\npvp=true\ndifficulty=hard\n"
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let properties = ServerProperties::parse(
+            "# \\u00A7 in a comment\n\
+            motd=\\u00A7l\\uD83D\\uDE00 smile\n\
+            level-name = my\\=world\\:2\n\
+            key\\:with\\=escapes : value\n\
+            resource-pack\thttps\\://example.com/pack.zip\n\
+            text-filtering-config=\\\\server\\ttab\n\
+            generator-settings=\\uD83D broken\n",
+        );
+        assert_eq!(properties.get("motd"), Some("§l😀 smile"));
+        assert_eq!(properties.get("level-name"), Some("my=world:2"));
+        assert_eq!(properties.get("key:with=escapes"), Some("value"));
+        assert_eq!(
+            properties.get("resource-pack"),
+            Some("https://example.com/pack.zip")
+        );
+        assert_eq!(
+            properties.get("text-filtering-config"),
+            Some("\\server\ttab")
+        );
+        // a lone high surrogate
+        assert_eq!(
+            properties.get("generator-settings"),
+            Some("\u{FFFD} broken")
+        );
+
+        assert_eq!(escape("§l😀 smile", false), "\\u00A7l\\uD83D\\uDE00 smile");
+        assert_eq!(escape("my=world:2", false), "my\\=world\\:2");
+        assert_eq!(escape(" #!", false), "\\ \\#\\!");
+        assert_eq!(escape("a key", true), "a\\ key");
+        for value in ["§l😀 smile", "my=world:2", " leading", "a\\b\nc\td", "#!"] {
+            assert_eq!(unescape(&escape(value, false)), value);
+        }
+    }
+
+    #[test]
+    fn values_are_validated() {
+        for (key, value) in [
+            ("pvp", "true"),
+            ("server-port", "1"),
+            ("server-port", "65535"),
+            ("max-tick-time", "-1"),
+            ("view-distance", "32"),
+            ("difficulty", "hard"),
+            ("motd", "anything"),
+        ] {
+            assert!(validate(key, value).unwrap(), "{key}={value}");
+        }
+        assert!(!validate("debug", "whatever").unwrap());
+        for (key, value) in [
+            ("pvp", "yes"),
+            ("server-port", "0"),
+            ("server-port", "65536"),
+            ("max-players", "-1"),
+            ("max-players", "2147483648"),
+            ("view-distance", "2"),
+            ("view-distance", "ten"),
+            ("difficulty", "Hard"),
+            ("gamemode", "hardcore"),
+            ("motd", "two\nlines"),
+        ] {
+            assert!(
+                matches!(
+                    validate(key, value),
+                    Err(PropertiesError::InvalidValue { key: k, value: v, .. }) if k == key && v == value
+                ),
+                "{key}={value}"
+            );
+        }
+        assert_eq!(
+            validate("gamemode", "hardcore").unwrap_err().to_string(),
+            "Invalid value 'hardcore' for 'gamemode', expected one of survival, creative, adventure, spectator"
+        );
+        let mut properties = ServerProperties::parse(FILE);
+        assert!(properties.set("server-port", "70000").is_err());
+        assert_eq!(properties.to_string(), FILE);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{fs, io, time::Duration};
+
+/// clock ticks per second, which `/proc/<pid>/stat` counts cpu time in (100 on practically all linux systems)
+const CLOCK_TICKS: u64 = 100;
+
+/// a process's resource usage, read from `/proc/<pid>`
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessStats {
+    /// user and system cpu time of all threads
+    pub cpu_time: Duration,
+    /// resident memory in bytes
+    pub rss: u64,
+    pub threads: u32,
+}
+
+/// reads the process's current resource usage (linux only)
+pub fn read(pid: u32) -> io::Result<ProcessStats> {
+    let invalid = |file: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected /proc/{pid}/{file}"),
+        )
+    };
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat"))?;
+    // the command's name is in parentheses and can contain spaces, so the fields are counted from the last `)`.
+    // after it are the fields 3 (state) and later, utime and stime are 14 and 15.
+    let (_, fields) = stat.rsplit_once(')').ok_or_else(|| invalid("stat"))?;
+    let mut fields = fields.split_whitespace().skip(11);
+    let mut ticks = || -> Option<u64> { fields.next()?.parse().ok() };
+    let (Some(utime), Some(stime)) = (ticks(), ticks()) else {
+        return Err(invalid("stat"));
+    };
+    let cpu_time = Duration::from_millis((utime + stime) * 1000 / CLOCK_TICKS);
+
+    let status = fs::read_to_string(format!("/proc/{pid}/status"))?;
+    let value = |key: &str| -> Option<u64> {
+        let line = status.lines().find_map(|line| line.strip_prefix(key))?;
+        line.split_whitespace().next()?.parse().ok()
+    };
+    let rss = value("VmRSS:").ok_or_else(|| invalid("status"))? * 1024;
+    let threads = value("Threads:").ok_or_else(|| invalid("status"))? as u32;
+    Ok(ProcessStats {
+        cpu_time,
+        rss,
+        threads,
+    })
+}
+
+/// the server's resource usage at one point in time
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceUsage {
+    pub stats: ProcessStats,
+    /// the average cpu usage since the previous sample, 100% is one core.
+    /// `None` for the first sample.
+    pub cpu_percent: Option<f32>,
+}
+
+/// i.e. `2d 3h 4m` or `5m 6s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{days}d {hours}h {mins}m")
+    } else if hours > 0 {
+        format!("{hours}h {mins}m")
+    } else {
+        format!("{mins}m {}s", secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn read_own_process() {
+        let stats = read(std::process::id()).unwrap();
+        assert!(stats.rss > 0, "{stats:?}");
+        assert!(stats.threads >= 1, "{stats:?}");
+        // busy for a while, so there is some cpu time
+        let start = std::time::Instant::now();
+        let mut n = 0u64;
+        while start.elapsed() < Duration::from_millis(50) {
+            n = std::hint::black_box(n.wrapping_add(1));
+        }
+        assert!(read(std::process::id()).unwrap().cpu_time > stats.cpu_time);
+    }
+
+    #[test]
+    fn read_missing_process() {
+        // higher than linux's maximum pid (2^22)
+        let e = read(u32::MAX).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn durations() {
+        for (secs, formatted) in [
+            (0, "0m 0s"),
+            (59, "0m 59s"),
+            (3599, "59m 59s"),
+            (3600, "1h 0m"),
+            (86399, "23h 59m"),
+            (2 * 86400 + 3 * 3600 + 4 * 60 + 5, "2d 3h 4m"),
+        ] {
+            assert_eq!(format_duration(Duration::from_secs(secs)), formatted);
+        }
+    }
+}
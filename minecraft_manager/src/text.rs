@@ -0,0 +1,294 @@
+use std::fmt::{Display, Write};
+
+/// A minecraft JSON text component, as used by `tellraw`.
+/// Build one with [`TextComponent::text`] and the builder methods,
+/// then use [`tellraw`] (or `to_string()`) to get the command/JSON.
+#[derive(Clone, Debug, Default)]
+pub struct TextComponent {
+    pub text: String,
+    /// a color name (`gray`, `gold`, ...) or a hex color (`#RRGGBB`)
+    pub color: Option<String>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+    pub click_event: Option<ClickEvent>,
+    pub hover_event: Option<HoverEvent>,
+    pub extra: Vec<TextComponent>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ClickEvent {
+    OpenUrl(String),
+    RunCommand(String),
+    SuggestCommand(String),
+    CopyToClipboard(String),
+}
+
+#[derive(Clone, Debug)]
+pub enum HoverEvent {
+    ShowText(Box<TextComponent>),
+}
+
+impl TextComponent {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+    /// an empty component, used to group other components (see [`TextComponent::append`])
+    pub fn empty() -> Self {
+        Self::default()
+    }
+    pub fn color(mut self, color: impl Into<String>) -> Self {
+        self.color = Some(color.into());
+        self
+    }
+    /// sets the color from an `0xRRGGBB` value
+    pub fn color_rgb(self, rgb: u32) -> Self {
+        self.color(format!("#{:06X}", rgb & 0xFFFFFF))
+    }
+    pub fn bold(mut self) -> Self {
+        self.bold = Some(true);
+        self
+    }
+    pub fn italic(mut self) -> Self {
+        self.italic = Some(true);
+        self
+    }
+    pub fn underlined(mut self) -> Self {
+        self.underlined = Some(true);
+        self
+    }
+    pub fn strikethrough(mut self) -> Self {
+        self.strikethrough = Some(true);
+        self
+    }
+    pub fn obfuscated(mut self) -> Self {
+        self.obfuscated = Some(true);
+        self
+    }
+    pub fn click(mut self, click_event: ClickEvent) -> Self {
+        self.click_event = Some(click_event);
+        self
+    }
+    pub fn hover_text(mut self, hover_text: TextComponent) -> Self {
+        self.hover_event = Some(HoverEvent::ShowText(Box::new(hover_text)));
+        self
+    }
+    /// adds a child component, which inherits this component's formatting
+    pub fn append(mut self, child: TextComponent) -> Self {
+        self.extra.push(child);
+        self
+    }
+    pub fn append_all(mut self, children: impl IntoIterator<Item = TextComponent>) -> Self {
+        self.extra.extend(children);
+        self
+    }
+    /// splits `text` into plain text and clickable links (`http://` or `https://`)
+    pub fn with_links(text: &str) -> Self {
+        let mut o = Self::empty();
+        let mut plain = String::new();
+        for part in text.split_inclusive(char::is_whitespace) {
+            let word = part.trim_end();
+            if word.starts_with("https://") || word.starts_with("http://") {
+                if !plain.is_empty() {
+                    o.extra.push(Self::text(std::mem::take(&mut plain)));
+                }
+                o.extra.push(link(word));
+                plain.push_str(&part[word.len()..]);
+            } else {
+                plain.push_str(part);
+            }
+        }
+        if o.extra.is_empty() {
+            return Self::text(plain);
+        }
+        if !plain.is_empty() {
+            o.extra.push(Self::text(plain));
+        }
+        o
+    }
+    /// the text of this component and all its children, without any formatting
+    pub fn plain_text(&self) -> String {
+        let mut o = self.text.clone();
+        for child in &self.extra {
+            o.push_str(&child.plain_text());
+        }
+        o
+    }
+}
+
+/// a clickable, underlined link which shows the url when hovered
+pub fn link(url: &str) -> TextComponent {
+    TextComponent::text(url)
+        .color("aqua")
+        .underlined()
+        .click(ClickEvent::OpenUrl(url.to_owned()))
+        .hover_text(TextComponent::text(url))
+}
+
+/// the `tellraw` command (without the `/`) which shows `text` to everyone matching `target` (i.e. `@a`)
+pub fn tellraw(target: &str, text: &TextComponent) -> String {
+    format!("tellraw {target} {text}")
+}
+
+impl Display for TextComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{\"text\":")?;
+        write_json_str(f, &self.text)?;
+        if let Some(color) = &self.color {
+            write!(f, ",\"color\":")?;
+            write_json_str(f, color)?;
+        }
+        for (key, val) in [
+            ("bold", self.bold),
+            ("italic", self.italic),
+            ("underlined", self.underlined),
+            ("strikethrough", self.strikethrough),
+            ("obfuscated", self.obfuscated),
+        ] {
+            if let Some(val) = val {
+                write!(f, ",\"{key}\":{val}")?;
+            }
+        }
+        if let Some(click_event) = &self.click_event {
+            let (action, value) = match click_event {
+                ClickEvent::OpenUrl(v) => ("open_url", v),
+                ClickEvent::RunCommand(v) => ("run_command", v),
+                ClickEvent::SuggestCommand(v) => ("suggest_command", v),
+                ClickEvent::CopyToClipboard(v) => ("copy_to_clipboard", v),
+            };
+            write!(f, ",\"clickEvent\":{{\"action\":\"{action}\",\"value\":")?;
+            write_json_str(f, value)?;
+            write!(f, "}}")?;
+        }
+        if let Some(hover_event) = &self.hover_event {
+            match hover_event {
+                HoverEvent::ShowText(text) => {
+                    write!(
+                        f,
+                        ",\"hoverEvent\":{{\"action\":\"show_text\",\"contents\":{text}}}"
+                    )?;
+                }
+            }
+        }
+        if !self.extra.is_empty() {
+            write!(f, ",\"extra\":[")?;
+            for (i, child) in self.extra.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, "{child}")?;
+            }
+            write!(f, "]")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// writes `s` as a JSON string (including the quotes)
+fn write_json_str(f: &mut impl Write, s: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ch if (ch as u32) < 0x20 || ch == '\u{7F}' => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn json_str(s: &str) -> String {
+        let mut o = String::new();
+        write_json_str(&mut o, s).unwrap();
+        o
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(json_str(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(json_str(r"C:\dir"), r#""C:\\dir""#);
+        assert_eq!(json_str("a\nb\r\tc"), r#""a\nb\r\tc""#);
+        assert_eq!(json_str("\u{0}\u{1b}\u{7f}"), r#""\u0000\u001b\u007f""#);
+        assert_eq!(json_str("§aé ☃"), "\"§aé ☃\"");
+    }
+
+    #[test]
+    fn escaped_strings_parse_back() {
+        for s in [
+            "\"}],\"text\":\"injected",
+            "\\\"",
+            "\n\r\t\u{0}\u{1f}\u{7f}",
+            "§l☃",
+        ] {
+            assert_eq!(serde_json::from_str::<String>(&json_str(s)).unwrap(), s);
+        }
+    }
+
+    #[test]
+    fn nested_components_parse_back() {
+        let component = TextComponent::text("<\"Steve\"> ")
+            .color("gold")
+            .bold()
+            .append(
+                TextComponent::text("run \\ this\n")
+                    .italic()
+                    .click(ClickEvent::RunCommand("/say \"hi\"".to_owned()))
+                    .hover_text(
+                        TextComponent::text("hover ").append(TextComponent::text("\"nested\"")),
+                    ),
+            )
+            .append(link("https://example.com/?a=\"b\""));
+        let parsed: Value = serde_json::from_str(&component.to_string()).unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "text": "<\"Steve\"> ",
+                "color": "gold",
+                "bold": true,
+                "extra": [
+                    {
+                        "text": "run \\ this\n",
+                        "italic": true,
+                        "clickEvent": { "action": "run_command", "value": "/say \"hi\"" },
+                        "hoverEvent": {
+                            "action": "show_text",
+                            "contents": { "text": "hover ", "extra": [{ "text": "\"nested\"" }] },
+                        },
+                    },
+                    {
+                        "text": "https://example.com/?a=\"b\"",
+                        "color": "aqua",
+                        "underlined": true,
+                        "clickEvent": { "action": "open_url", "value": "https://example.com/?a=\"b\"" },
+                        "hoverEvent": {
+                            "action": "show_text",
+                            "contents": { "text": "https://example.com/?a=\"b\"" },
+                        },
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn tellraw_command() {
+        assert_eq!(
+            tellraw("@a", &TextComponent::text("hi").color("gray")),
+            r#"tellraw @a {"text":"hi","color":"gray"}"#
+        );
+    }
+}
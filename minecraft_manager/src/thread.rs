@@ -1,6 +1,17 @@
-use std::thread::JoinHandle;
+use std::{
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    events::MinecraftServerEventType,
+    performance::Performance,
+    resources::{self, ResourceUsage},
+    tasks::MinecraftServerTaskCallback,
+};
 
-use crate::tasks::MinecraftServerTaskCallback;
+/// how often [`MinecraftServerThread::update`] reads the server process's resource usage
+const RESOURCES_INTERVAL: Duration = Duration::from_secs(5);
 
 use {
     crate::{
@@ -16,6 +27,16 @@ pub struct MinecraftServerThread {
     events: ThreadData<MinecraftServerEvent>,
     task_sender: MinecraftServerTaskSender,
     join_handle: JoinHandle<MinecraftServerStopReason>,
+    /// players which are currently online (according to join/leave events)
+    players: Vec<String>,
+    /// the minecraft version, once the server has logged it
+    version: Option<String>,
+    /// the server process's id and when it was spawned
+    process: Option<(u32, Instant)>,
+    /// the most recent resource usage, and when it was read
+    resources: Option<(Instant, ResourceUsage)>,
+    /// tick rates and `Can't keep up!` warnings from the server's log
+    performance: Performance,
 }
 
 /// A clonable type allowing multiple threads to send tasks to the server.
@@ -37,11 +58,23 @@ impl MinecraftServerTaskSender {
 
 impl MinecraftServerThread {
     pub fn start(settings: MinecraftServerSettings) -> Self {
-        let (task_sender, event_receiver, join_handle) = threaded::run(settings);
+        Self::start_with_output(settings, None)
+    }
+    /// like [`Self::start`], but the server's log lines are sent to `output` instead of being printed
+    pub fn start_with_output(
+        settings: MinecraftServerSettings,
+        output: Option<mpsc::Sender<String>>,
+    ) -> Self {
+        let (task_sender, event_receiver, join_handle) = threaded::run(settings, output);
         Self {
             events: ThreadData::new(event_receiver, 100),
             task_sender: MinecraftServerTaskSender(task_sender),
             join_handle,
+            players: vec![],
+            version: None,
+            process: None,
+            resources: None,
+            performance: Performance::default(),
         }
     }
     pub fn is_finished(&self) -> bool {
@@ -59,7 +92,83 @@ impl MinecraftServerThread {
         }
     }
     pub fn update(&mut self) {
-        self.events.update();
+        let new_events = self.events.update();
+        for event in self
+            .events
+            .buffer
+            .iter()
+            .skip(self.events.buffer.len().saturating_sub(new_events))
+        {
+            match &event.event {
+                MinecraftServerEventType::JoinLeave(e) => {
+                    if e.joined {
+                        if !self.players.contains(&e.username) {
+                            self.players.push(e.username.clone());
+                        }
+                    } else {
+                        self.players.retain(|player| player != &e.username);
+                    }
+                }
+                MinecraftServerEventType::Version(version) => {
+                    self.version = Some(version.clone());
+                }
+                MinecraftServerEventType::Spawned { pid } => {
+                    self.process = Some((*pid, Instant::now()));
+                }
+                MinecraftServerEventType::Tps(tps) => self.performance.add_tps(*tps),
+                MinecraftServerEventType::Mspt(mspt) => self.performance.add_mspt(*mspt),
+                MinecraftServerEventType::CantKeepUp { ms_behind } => {
+                    self.performance.add_cant_keep_up(*ms_behind)
+                }
+                _ => {}
+            }
+        }
+        if self
+            .resources
+            .is_none_or(|(time, _)| time.elapsed() >= RESOURCES_INTERVAL)
+        {
+            self.update_resources();
+        }
+    }
+    fn update_resources(&mut self) {
+        let Some((pid, _)) = self.process else {
+            return;
+        };
+        let now = Instant::now();
+        // the process has exited (or this isn't linux)
+        let Ok(stats) = resources::read(pid) else {
+            self.resources = None;
+            return;
+        };
+        let cpu_percent = self.resources.map(|(time, previous)| {
+            let cpu = stats.cpu_time.saturating_sub(previous.stats.cpu_time);
+            100.0 * cpu.as_secs_f32() / now.duration_since(time).as_secs_f32().max(0.001)
+        });
+        self.resources = Some((now, ResourceUsage { stats, cpu_percent }));
+    }
+    /// the players which are currently online
+    pub fn online_players(&self) -> &[String] {
+        &self.players
+    }
+    /// the minecraft version the server logged when it started
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+    /// the server process's id, once it was spawned
+    pub fn pid(&self) -> Option<u32> {
+        self.process.map(|(pid, _)| pid)
+    }
+    /// how long ago the server process was spawned
+    pub fn uptime(&self) -> Option<Duration> {
+        self.process.map(|(_, spawned)| spawned.elapsed())
+    }
+    /// the server's recent tick rates, from the output of [`crate::MinecraftServerType::tick_query_commands`]
+    pub fn performance(&self) -> &Performance {
+        &self.performance
+    }
+    /// the server process's resource usage, updated every few seconds by [`Self::update`]
+    pub fn resources(&self) -> Option<&ResourceUsage> {
+        self.resources.as_ref().map(|(_, usage)| usage)
     }
     pub fn handle_new_events(
         &mut self,
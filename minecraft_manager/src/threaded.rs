@@ -5,8 +5,9 @@ use std::{
 };
 
 use crate::{
+    java,
     parse_line::{parse_line, ParseOutput},
-    MinecraftServerType,
+    warn, GetCommandError, MinecraftServerType,
 };
 
 use {
@@ -18,8 +19,11 @@ use {
     std::sync::mpsc,
 };
 
+/// runs the server in a new thread. The server's log lines are sent to `output` if it is set,
+/// otherwise they (and what the thread is doing) are printed to stderr.
 pub fn run(
     settings: MinecraftServerSettings,
+    output: Option<mpsc::Sender<String>>,
 ) -> (
     mpsc::Sender<(MinecraftServerTask, mpsc::Sender<Result<u8, String>>)>,
     mpsc::Receiver<MinecraftServerEvent>,
@@ -31,14 +35,41 @@ pub fn run(
 
     // thread
     let join_handle = std::thread::spawn(move || {
-        let mut command = settings.get_command();
+        let mut command = match settings.get_command() {
+            Ok(command) => command,
+            Err(e) => {
+                warn(
+                    output.as_ref(),
+                    format!("[WARN/SERVER-THREAD] Not starting the server: {e}"),
+                );
+                let reason = match e {
+                    GetCommandError::NoCompatibleJava(e) => {
+                        MinecraftServerStopReasons::NoCompatibleJava(e)
+                    }
+                    e => MinecraftServerStopReasons::CouldNotBuildCommand(e),
+                };
+                return MinecraftServerStopReason { time: (), reason };
+            }
+        };
         command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
-        eprintln!("Spawning {command:?}");
+        if output.is_none() {
+            eprintln!("Spawning {command:?}");
+        }
+        if let MinecraftServerType::Custom {
+            line_parser_proc, ..
+        } = &settings.server_type
+        {
+            line_parser_proc.lock().unwrap().set_output(output.clone());
+        }
         match command.spawn() {
             Ok(mut process) => {
+                _ = events.send(MinecraftServerEvent {
+                    time: (),
+                    event: MinecraftServerEventType::Spawned { pid: process.id() },
+                });
                 if let (Some(mut stdin), Some(stdout), Some(mut _stderr)) = (
                     process.stdin.take(),
                     process.stdout.take(),
@@ -47,6 +78,7 @@ pub fn run(
                     let stdout_lines = {
                         // the stdout reading thread
                         let (lines, stdout_lines) = mpsc::channel();
+                        let output = output.clone();
                         std::thread::spawn(move || {
                             let mut stdout = BufReader::new(stdout);
                             let mut line = String::new();
@@ -54,22 +86,28 @@ pub fn run(
                                 line.clear();
                                 match stdout.read_line(&mut line) {
                                     Ok(_) if !line.trim().is_empty() => {
-                                        eprintln!("> {}", line.trim());
+                                        match &output {
+                                            Some(output) => _ = output.send(line.trim().to_owned()),
+                                            None => eprintln!("> {}", line.trim()),
+                                        }
                                         match lines.send(line.trim().to_owned()) {
                                             Ok(_) => (),
                                             Err(_) => return,
                                         }
                                     }
                                     Ok(0) => {
-                                        eprintln!(
-                                            " [ Stdout read thread ]    Reached EOF, stopping."
-                                        );
+                                        if output.is_none() {
+                                            eprintln!(
+                                                " [ Stdout read thread ]    Reached EOF, stopping."
+                                            );
+                                        }
                                         return;
                                     }
                                     Ok(_) => {} // empty line, but read newline char - ignore
                                     Err(e) => {
-                                        eprintln!(
-                                            " [ Stdout read thread ]    Read error, stopping. ({e:?})"
+                                        warn(
+                                            output.as_ref(),
+                                            format!("[WARN/SERVER-THREAD] Couldn't read the server's stdout, stopping. ({e:?})"),
                                         );
                                         return;
                                     }
@@ -80,28 +118,33 @@ pub fn run(
                     };
                     loop {
                         while let Ok(task) = tasks.try_recv() {
-                            eprintln!("[GOT TASK] {task:?}");
+                            if output.is_none() {
+                                eprintln!("[GOT TASK] {task:?}");
+                            }
                             // iterate over all new tasks
                             match task.0 {
-                                MinecraftServerTask::Stop => match writeln!(stdin, "stop") {
-                                    Ok(_) => {
-                                        task.1.send(Ok(0));
-                                        while let Ok(None) = process.try_wait() {
-                                            std::thread::sleep(std::time::Duration::from_millis(
-                                                250,
-                                            ));
+                                MinecraftServerTask::Stop => {
+                                    match writeln!(stdin, "{}", settings.server_type.stop_command())
+                                    {
+                                        Ok(_) => {
+                                            task.1.send(Ok(0));
+                                            while let Ok(None) = process.try_wait() {
+                                                std::thread::sleep(
+                                                    std::time::Duration::from_millis(250),
+                                                );
+                                            }
+                                            task.1.send(Ok(100));
                                         }
-                                        task.1.send(Ok(100));
-                                    }
-                                    Err(e) => {
-                                        events.send(MinecraftServerEvent {
+                                        Err(e) => {
+                                            events.send(MinecraftServerEvent {
                                             time: (),
                                             event: MinecraftServerEventType::Warning(
                                                 MinecraftServerEvents::MinecraftServerWarning::CantWriteToStdin(e),
                                             ),
                                         });
+                                        }
                                     }
-                                },
+                                }
                                 MinecraftServerTask::Kill => {
                                     process.kill();
                                     task.1.send(Ok(100));
@@ -122,16 +165,22 @@ pub fn run(
                                 }
                             }
                         }
-                        while let Ok(line) = stdout_lines.try_recv() {
-                            // iterate over all new lines from stdout
+                        // one line at a time, so tasks are handled between lines (custom line parsers can be slow)
+                        if let Ok(line) = stdout_lines.try_recv() {
                             // eprintln!(" [ server manager thread ]    Found line '{}'", line);
                             match parse_line(&line, &settings) {
                                 ParseOutput::Event(event) => {
                                     events.send(MinecraftServerEvent { time: (), event });
                                 }
+                                ParseOutput::Events(new_events) => {
+                                    for event in new_events {
+                                        _ = events.send(MinecraftServerEvent { time: (), event });
+                                    }
+                                }
                                 ParseOutput::Error(_) => (),
                                 ParseOutput::Nothing => (),
                             }
+                            continue;
                         }
                         // stop the loop once the process exits
                         match process.try_wait() {
@@ -141,9 +190,7 @@ pub fn run(
                                     line_parser_proc, ..
                                 } = &settings.server_type
                                 {
-                                    if let Some(proc) = &mut *line_parser_proc.lock().unwrap() {
-                                        _ = proc.0.kill();
-                                    }
+                                    line_parser_proc.lock().unwrap().kill();
                                 }
                                 return MinecraftServerStopReason {
                                     time: (),
@@ -160,7 +207,10 @@ pub fn run(
                         std::thread::sleep(std::time::Duration::from_millis(200));
                     }
                 } else {
-                    eprintln!("No stdin/out!");
+                    warn(
+                        output.as_ref(),
+                        "[WARN/SERVER-THREAD] No stdin/out!".to_owned(),
+                    );
                     events.send(MinecraftServerEvent {
                         time: (),
                         event: MinecraftServerEventType::Warning(
@@ -180,7 +230,10 @@ pub fn run(
                 }
             }
             Err(e) => {
-                eprintln!("Couldn't spawn server process: {e:?}");
+                warn(
+                    output.as_ref(),
+                    format!("[WARN/SERVER-THREAD] Couldn't spawn server process: {e:?}"),
+                );
                 MinecraftServerStopReason {
                     time: (),
                     reason: MinecraftServerStopReasons::ProcessCouldNotBeSpawned(e),
@@ -196,6 +249,12 @@ pub struct MinecraftServerStopReason {
     time: (),
     reason: MinecraftServerStopReasons,
 }
+impl MinecraftServerStopReason {
+    /// if the server exited with an error on its own, instead of being stopped or killed
+    pub fn is_crash(&self) -> bool {
+        matches!(&self.reason, MinecraftServerStopReasons::ProcessEnded(status) if !status.success())
+    }
+}
 impl Display for MinecraftServerStopReason {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.reason)
@@ -207,6 +266,10 @@ pub enum MinecraftServerStopReasons {
     ProcessEnded(ExitStatus),
     ProcessCouldNotBeSpawned(std::io::Error),
     ProcessCouldNotBeAwaited(std::io::Error),
+    /// the server wasn't started because no installed java supports its minecraft version
+    NoCompatibleJava(java::NoCompatibleJava),
+    /// the server wasn't started because its (neo)forge `run.sh` couldn't be used
+    CouldNotBuildCommand(GetCommandError),
 }
 impl Display for MinecraftServerStopReasons {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -230,6 +293,8 @@ impl Display for MinecraftServerStopReasons {
                 f,
                 "Couldn't wait for process to end (check console/log for errors)"
             ),
+            Self::NoCompatibleJava(e) => write!(f, "{e}"),
+            Self::CouldNotBuildCommand(e) => write!(f, "{e}"),
         }
     }
 }